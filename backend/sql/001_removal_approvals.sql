@@ -0,0 +1,28 @@
+-- =============================================================================
+-- Four-eyes approval for large partial pick removals
+-- Pending removal requests waiting for a supervisor decision
+-- =============================================================================
+
+IF OBJECT_ID('dbo.cust_PartialPickRemovalApproval', 'U') IS NULL
+BEGIN
+    CREATE TABLE dbo.cust_PartialPickRemovalApproval (
+        RequestId       INT IDENTITY(1,1) NOT NULL PRIMARY KEY,
+        RunNo           INT NOT NULL,
+        ItemsJson       NVARCHAR(MAX) NOT NULL,
+        LineCount       INT NOT NULL,
+        TotalQty        FLOAT NOT NULL,
+        Reason          NVARCHAR(500) NOT NULL,
+        Threshold       NVARCHAR(200) NOT NULL,
+        Status          VARCHAR(10) NOT NULL CONSTRAINT DF_RemovalApproval_Status DEFAULT 'PENDING',
+        RequestedBy     NVARCHAR(50) NOT NULL,
+        RequestedDate   DATETIME NOT NULL CONSTRAINT DF_RemovalApproval_RequestedDate DEFAULT GETDATE(),
+        DecidedBy       NVARCHAR(50) NULL,
+        DecidedDate     DATETIME NULL,
+        DecisionComment NVARCHAR(500) NULL,
+        AffectedRows    INT NULL
+    );
+
+    CREATE INDEX IX_RemovalApproval_Status_RunNo
+        ON dbo.cust_PartialPickRemovalApproval (Status, RunNo);
+END
+GO
@@ -0,0 +1,55 @@
+-- =============================================================================
+-- Reason codes for partial pick removals and the removal audit trail
+-- =============================================================================
+
+IF OBJECT_ID('dbo.cust_PartialPickReasonCode', 'U') IS NULL
+BEGIN
+    CREATE TABLE dbo.cust_PartialPickReasonCode (
+        ReasonCode      VARCHAR(20) NOT NULL PRIMARY KEY,
+        Description     NVARCHAR(200) NOT NULL,
+        RequiresComment BIT NOT NULL CONSTRAINT DF_ReasonCode_RequiresComment DEFAULT 0,
+        Active          BIT NOT NULL CONSTRAINT DF_ReasonCode_Active DEFAULT 1,
+        SortOrder       INT NOT NULL CONSTRAINT DF_ReasonCode_SortOrder DEFAULT 0,
+        ModifiedBy      NVARCHAR(50) NULL,
+        ModifiedDate    DATETIME NULL
+    );
+
+    INSERT INTO dbo.cust_PartialPickReasonCode (ReasonCode, Description, RequiresComment, SortOrder)
+    VALUES
+        ('WRONG_LOT', 'Wrong lot', 0, 10),
+        ('QA_HOLD', 'QA hold', 0, 20),
+        ('RECIPE_CHANGE', 'Recipe change', 0, 30),
+        ('DUPLICATE_PICK', 'Duplicate pick', 0, 40),
+        ('OTHER', 'Other', 1, 90);
+END
+GO
+
+IF OBJECT_ID('dbo.cust_PartialPickRemovalAudit', 'U') IS NULL
+BEGIN
+    CREATE TABLE dbo.cust_PartialPickRemovalAudit (
+        AuditId           INT IDENTITY(1,1) NOT NULL PRIMARY KEY,
+        RunNo             INT NOT NULL,
+        RowNum            INT NOT NULL,
+        LineId            INT NOT NULL,
+        BatchNo           NVARCHAR(50) NULL,
+        ItemKey           NVARCHAR(50) NULL,
+        QtyBefore         FLOAT NOT NULL,
+        QtyAfter          FLOAT NOT NULL,
+        ReasonCode        VARCHAR(20) NOT NULL,
+        Comment           NVARCHAR(500) NULL,
+        RemovedBy         NVARCHAR(50) NOT NULL,
+        ApprovedBy        NVARCHAR(50) NULL,
+        ApprovalRequestId INT NULL,
+        RemovedDate       DATETIME NOT NULL CONSTRAINT DF_RemovalAudit_RemovedDate DEFAULT GETDATE()
+    );
+
+    CREATE INDEX IX_RemovalAudit_RunNo ON dbo.cust_PartialPickRemovalAudit (RunNo, RemovedDate);
+    CREATE INDEX IX_RemovalAudit_RemovedDate ON dbo.cust_PartialPickRemovalAudit (RemovedDate);
+END
+GO
+
+IF COL_LENGTH('dbo.cust_PartialPickRemovalApproval', 'ReasonCode') IS NULL
+BEGIN
+    ALTER TABLE dbo.cust_PartialPickRemovalApproval ADD ReasonCode VARCHAR(20) NULL;
+END
+GO
@@ -0,0 +1,59 @@
+-- =============================================================================
+-- Outbound webhooks for removal events
+--
+-- Removals write one outbox row per audited line in the same transaction as
+-- the update. The webhook worker fans outbox rows out to matching
+-- subscriptions and delivers them with retries.
+-- =============================================================================
+
+IF OBJECT_ID('dbo.cust_PartialPickWebhook', 'U') IS NULL
+BEGIN
+    CREATE TABLE dbo.cust_PartialPickWebhook (
+        WebhookId    INT IDENTITY(1,1) NOT NULL PRIMARY KEY,
+        Name         NVARCHAR(100) NOT NULL,
+        Url          NVARCHAR(500) NOT NULL,
+        Secret       NVARCHAR(200) NOT NULL,
+        -- Comma-separated event types, or * for all
+        EventTypes   NVARCHAR(200) NOT NULL CONSTRAINT DF_Webhook_EventTypes DEFAULT '*',
+        Active       BIT NOT NULL CONSTRAINT DF_Webhook_Active DEFAULT 1,
+        ModifiedBy   NVARCHAR(50) NULL,
+        ModifiedDate DATETIME NULL
+    );
+END
+GO
+
+IF OBJECT_ID('dbo.cust_PartialPickOutbox', 'U') IS NULL
+BEGIN
+    CREATE TABLE dbo.cust_PartialPickOutbox (
+        OutboxId       INT IDENTITY(1,1) NOT NULL PRIMARY KEY,
+        EventType      VARCHAR(50) NOT NULL,
+        RunNo          INT NOT NULL,
+        AuditId        INT NOT NULL,
+        CreatedDate    DATETIME NOT NULL CONSTRAINT DF_Outbox_CreatedDate DEFAULT GETDATE(),
+        -- Set once deliveries have been created for every matching webhook
+        DispatchedDate DATETIME NULL
+    );
+
+    CREATE INDEX IX_Outbox_Pending ON dbo.cust_PartialPickOutbox (DispatchedDate, OutboxId);
+END
+GO
+
+IF OBJECT_ID('dbo.cust_PartialPickWebhookDelivery', 'U') IS NULL
+BEGIN
+    CREATE TABLE dbo.cust_PartialPickWebhookDelivery (
+        DeliveryId      INT IDENTITY(1,1) NOT NULL PRIMARY KEY,
+        OutboxId        INT NOT NULL,
+        WebhookId       INT NOT NULL,
+        -- PENDING, DELIVERED or DEAD
+        Status          VARCHAR(20) NOT NULL CONSTRAINT DF_Delivery_Status DEFAULT 'PENDING',
+        Attempts        INT NOT NULL CONSTRAINT DF_Delivery_Attempts DEFAULT 0,
+        NextAttemptDate DATETIME NOT NULL CONSTRAINT DF_Delivery_NextAttemptDate DEFAULT GETDATE(),
+        LastStatusCode  INT NULL,
+        LastError       NVARCHAR(1000) NULL,
+        CreatedDate     DATETIME NOT NULL CONSTRAINT DF_Delivery_CreatedDate DEFAULT GETDATE(),
+        DeliveredDate   DATETIME NULL
+    );
+
+    CREATE INDEX IX_Delivery_Due ON dbo.cust_PartialPickWebhookDelivery (Status, NextAttemptDate);
+END
+GO
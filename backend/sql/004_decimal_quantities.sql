@@ -0,0 +1,25 @@
+-- =============================================================================
+-- Exact quantities: store audit and approval quantities as DECIMAL
+-- FLOAT values such as 0.30000000000000004 are rounded to six places
+-- =============================================================================
+
+IF EXISTS (
+    SELECT 1 FROM sys.columns
+    WHERE object_id = OBJECT_ID('dbo.cust_PartialPickRemovalAudit')
+      AND name = 'QtyBefore' AND TYPE_NAME(system_type_id) = 'float'
+)
+BEGIN
+    ALTER TABLE dbo.cust_PartialPickRemovalAudit ALTER COLUMN QtyBefore DECIMAL(18, 6) NOT NULL;
+    ALTER TABLE dbo.cust_PartialPickRemovalAudit ALTER COLUMN QtyAfter DECIMAL(18, 6) NOT NULL;
+END
+GO
+
+IF EXISTS (
+    SELECT 1 FROM sys.columns
+    WHERE object_id = OBJECT_ID('dbo.cust_PartialPickRemovalApproval')
+      AND name = 'TotalQty' AND TYPE_NAME(system_type_id) = 'float'
+)
+BEGIN
+    ALTER TABLE dbo.cust_PartialPickRemovalApproval ALTER COLUMN TotalQty DECIMAL(18, 6) NOT NULL;
+END
+GO
@@ -0,0 +1,48 @@
+-- =============================================================================
+-- Administration of LOCAL accounts in tbl_user
+--
+-- tbl_user keeps name, password and auth_source. Roles and the disabled flag
+-- of LOCAL accounts live next to it, and every change made through the API
+-- is recorded in the audit table.
+-- =============================================================================
+
+IF OBJECT_ID('dbo.cust_PartialPickUserAccount', 'U') IS NULL
+BEGIN
+    CREATE TABLE dbo.cust_PartialPickUserAccount (
+        uname        NVARCHAR(50) NOT NULL PRIMARY KEY,
+        -- Comma-separated roles, e.g. supervisor,admin
+        Roles        VARCHAR(200) NOT NULL CONSTRAINT DF_UserAccount_Roles DEFAULT '',
+        Disabled     BIT NOT NULL CONSTRAINT DF_UserAccount_Disabled DEFAULT 0,
+        ModifiedBy   NVARCHAR(50) NULL,
+        ModifiedDate DATETIME NULL
+    );
+END
+GO
+
+IF OBJECT_ID('dbo.cust_PartialPickUserAudit', 'U') IS NULL
+BEGIN
+    CREATE TABLE dbo.cust_PartialPickUserAudit (
+        AuditId     INT IDENTITY(1,1) NOT NULL PRIMARY KEY,
+        Username    NVARCHAR(50) NOT NULL,
+        Action      VARCHAR(20) NOT NULL,
+        Detail      NVARCHAR(500) NULL,
+        ChangedBy   NVARCHAR(50) NOT NULL,
+        ChangedDate DATETIME NOT NULL CONSTRAINT DF_UserAudit_ChangedDate DEFAULT GETUTCDATE()
+    );
+
+    CREATE INDEX IX_UserAudit_Username ON dbo.cust_PartialPickUserAudit (Username, ChangedDate);
+END
+GO
+
+-- bcrypt hashes are 60 characters; widen pword if it is shorter
+IF EXISTS (
+    SELECT 1 FROM sys.columns
+    WHERE object_id = OBJECT_ID('dbo.tbl_user') AND name = 'pword'
+      AND max_length <> -1
+      AND CASE WHEN TYPE_NAME(system_type_id) IN ('nchar', 'nvarchar')
+               THEN max_length / 2 ELSE max_length END < 60
+)
+BEGIN
+    ALTER TABLE dbo.tbl_user ALTER COLUMN pword NVARCHAR(100) NULL;
+END
+GO
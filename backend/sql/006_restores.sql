@@ -0,0 +1,33 @@
+-- =============================================================================
+-- Restoring removals from the support CLI
+--
+-- A restore puts a line's ToPickedPartialQty back to its value before one
+-- audited removal and is itself audited with reason RESTORE, pointing at the
+-- removal it undoes. RESTORE is inactive so it cannot be sent with a removal.
+-- =============================================================================
+
+IF COL_LENGTH('dbo.cust_PartialPickRemovalAudit', 'RestoresAuditId') IS NULL
+BEGIN
+    ALTER TABLE dbo.cust_PartialPickRemovalAudit ADD RestoresAuditId INT NULL;
+END
+GO
+
+IF NOT EXISTS (
+    SELECT 1 FROM sys.indexes
+    WHERE object_id = OBJECT_ID('dbo.cust_PartialPickRemovalAudit')
+      AND name = 'UX_RemovalAudit_RestoresAuditId'
+)
+BEGIN
+    -- One restore per removal
+    CREATE UNIQUE INDEX UX_RemovalAudit_RestoresAuditId
+        ON dbo.cust_PartialPickRemovalAudit (RestoresAuditId)
+        WHERE RestoresAuditId IS NOT NULL;
+END
+GO
+
+IF NOT EXISTS (SELECT 1 FROM dbo.cust_PartialPickReasonCode WHERE ReasonCode = 'RESTORE')
+BEGIN
+    INSERT INTO dbo.cust_PartialPickReasonCode (ReasonCode, Description, RequiresComment, Active, SortOrder)
+    VALUES ('RESTORE', 'Restored removal', 0, 0, 1000);
+END
+GO
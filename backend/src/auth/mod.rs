@@ -0,0 +1,170 @@
+//! Request Authentication
+//!
+//! Verifies the JWT issued by `POST /api/auth/login` and exposes the caller
+//! to handlers through the [`AuthenticatedUser`] extractor.
+//!
+//! # Roles
+//!
+//! Roles are embedded in the token at login. They are assigned from
+//! comma-separated username lists in the environment:
+//!
+//! | Variable | Role |
+//! |----------|------|
+//! | `RM_SUPERVISOR_USERS` | `supervisor` |
+//! | `RM_ADMIN_USERS` | `admin` (also passes `supervisor` checks) |
+//!
+//! LOCAL accounts can also have roles stored by an admin (see [`users`]).
+
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::{FromRequest, HttpRequest, HttpResponse};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use serde_json::json;
+use std::env;
+use std::future::{ready, Ready};
+
+use crate::models::auth::Claims;
+
+pub mod users;
+
+const JWT_SECRET_ENV: &str = "JWT_SECRET";
+const DEFAULT_JWT_SECRET: &str = "rm-partial-pick-remover-secret-key-change-in-production";
+
+const ENV_SUPERVISOR_USERS: &str = "RM_SUPERVISOR_USERS";
+const ENV_ADMIN_USERS: &str = "RM_ADMIN_USERS";
+
+pub const ROLE_SUPERVISOR: &str = "supervisor";
+pub const ROLE_ADMIN: &str = "admin";
+
+/// Secret used to sign and verify JWTs
+pub fn jwt_secret() -> String {
+    env::var(JWT_SECRET_ENV).unwrap_or_else(|_| DEFAULT_JWT_SECRET.to_string())
+}
+
+/// Whether tokens are signed with the built-in development secret
+pub fn uses_default_jwt_secret() -> bool {
+    jwt_secret() == DEFAULT_JWT_SECRET
+}
+
+fn listed_in(var: &str, username: &str) -> bool {
+    env::var(var)
+        .map(|list| {
+            list.split(',')
+                .any(|u| u.trim().eq_ignore_ascii_case(username))
+        })
+        .unwrap_or(false)
+}
+
+/// Roles granted to a user by configuration
+pub fn roles_for(username: &str) -> Vec<String> {
+    let mut roles = vec![];
+    if listed_in(ENV_SUPERVISOR_USERS, username) {
+        roles.push(ROLE_SUPERVISOR.to_string());
+    }
+    if listed_in(ENV_ADMIN_USERS, username) {
+        roles.push(ROLE_ADMIN.to_string());
+    }
+    roles
+}
+
+/// Caller identity taken from a valid `Authorization: Bearer` token
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub username: String,
+    pub display_name: String,
+    pub roles: Vec<String>,
+    /// Plant chosen at login
+    pub plant: String,
+    /// Plants the user may reach
+    pub plants: Vec<String>,
+}
+
+impl AuthenticatedUser {
+    /// Admins pass every role check
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(role) || r.eq_ignore_ascii_case(ROLE_ADMIN))
+    }
+
+    pub fn can_access_plant(&self, code: &str) -> bool {
+        self.plants.iter().any(|p| p.eq_ignore_ascii_case(code))
+    }
+}
+
+fn unauthorized(message: &str) -> actix_web::Error {
+    InternalError::from_response(
+        message.to_string(),
+        HttpResponse::Unauthorized().json(json!({
+            "success": false,
+            "message": message,
+        })),
+    )
+    .into()
+}
+
+pub(crate) fn authenticate_request(
+    req: &HttpRequest,
+) -> Result<AuthenticatedUser, actix_web::Error> {
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| unauthorized("Missing bearer token"))?;
+
+    let data = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(jwt_secret().as_bytes()),
+        &Validation::default(),
+    )
+    .map_err(|_| unauthorized("Invalid or expired token"))?;
+
+    let claims = data.claims;
+    Ok(AuthenticatedUser {
+        display_name: if claims.display_name.is_empty() {
+            claims.sub.clone()
+        } else {
+            claims.display_name
+        },
+        username: claims.sub,
+        roles: claims.roles,
+        plant: claims.plant,
+        plants: claims.plants,
+    })
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(authenticate_request(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_role() {
+        let user = AuthenticatedUser {
+            username: "alice".to_string(),
+            display_name: "Alice".to_string(),
+            roles: vec![ROLE_SUPERVISOR.to_string()],
+            plant: "TH1".to_string(),
+            plants: vec!["TH1".to_string()],
+        };
+        assert!(user.has_role(ROLE_SUPERVISOR));
+        assert!(!user.has_role(ROLE_ADMIN));
+
+        let admin = AuthenticatedUser {
+            roles: vec![ROLE_ADMIN.to_string()],
+            ..user
+        };
+        assert!(admin.has_role(ROLE_SUPERVISOR));
+        assert!(admin.can_access_plant("th1"));
+        assert!(!admin.can_access_plant("TH2"));
+    }
+}
@@ -0,0 +1,526 @@
+//! LOCAL Account Administration
+//!
+//! LOCAL accounts are `tbl_user` rows with `auth_source = 'LOCAL'` that sign
+//! in with a password instead of through the directory. Admins maintain them
+//! through `/api/users`; directory (`LDAP`) rows are never changed here.
+//!
+//! Passwords are stored as bcrypt hashes. Rows created by hand before the
+//! admin API may still hold a plaintext password, which keeps working until
+//! it is reset. Roles and the disabled flag are kept in
+//! `cust_PartialPickUserAccount`, and every change is written to
+//! `cust_PartialPickUserAudit` in the same transaction.
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use thiserror::Error;
+use tiberius::Row;
+
+use crate::auth::{ROLE_ADMIN, ROLE_SUPERVISOR};
+use crate::db::mssql::MssqlPool;
+use crate::db::row::column;
+use crate::models::auth::{LocalUser, UserAuditEntry};
+use crate::plant::PlantClock;
+
+/// Usernames of LOCAL accounts start with this; login routes on it
+pub const LOCAL_PREFIX: &str = "LOCAL";
+
+const MAX_USERNAME_LENGTH: usize = 50;
+const MIN_PASSWORD_LENGTH: usize = 8;
+/// bcrypt ignores everything after 72 bytes
+const MAX_PASSWORD_BYTES: usize = 72;
+
+/// Most recent audit entries returned at once
+const AUDIT_LIMIT: i32 = 500;
+
+/// Why a change to a LOCAL account was refused
+#[derive(Error, Debug)]
+pub enum UserAdminError {
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error("User {0} not found")]
+    NotFound(String),
+
+    #[error("User {0} is a directory account and is managed in Active Directory")]
+    DirectoryAccount(String),
+
+    #[error("User {0} already exists")]
+    Exists(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] anyhow::Error),
+}
+
+/// Roles that can be stored for an account
+const ASSIGNABLE_ROLES: &[&str] = &[ROLE_SUPERVISOR, ROLE_ADMIN];
+
+pub fn validate_username(username: &str) -> Result<(), UserAdminError> {
+    let valid_chars = username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if !username.to_uppercase().starts_with(LOCAL_PREFIX)
+        || username.len() <= LOCAL_PREFIX.len()
+        || username.len() > MAX_USERNAME_LENGTH
+        || !valid_chars
+    {
+        return Err(UserAdminError::Invalid(format!(
+            "Username must start with {}, be at most {} characters and use only letters, digits, '_', '.' or '-'",
+            LOCAL_PREFIX, MAX_USERNAME_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+pub fn validate_password(password: &str) -> Result<(), UserAdminError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH || password.len() > MAX_PASSWORD_BYTES {
+        return Err(UserAdminError::Invalid(format!(
+            "Password must be at least {} characters and at most {} bytes",
+            MIN_PASSWORD_LENGTH, MAX_PASSWORD_BYTES
+        )));
+    }
+    Ok(())
+}
+
+/// Lowercased, deduplicated roles, refusing unknown ones
+pub fn normalize_roles(roles: &[String]) -> Result<Vec<String>, UserAdminError> {
+    let mut normalized: Vec<String> = vec![];
+    for role in roles {
+        let role = role.trim().to_lowercase();
+        if role.is_empty() || normalized.contains(&role) {
+            continue;
+        }
+        if !ASSIGNABLE_ROLES.contains(&role.as_str()) {
+            return Err(UserAdminError::Invalid(format!(
+                "Unknown role {}; allowed: {}",
+                role,
+                ASSIGNABLE_ROLES.join(", ")
+            )));
+        }
+        normalized.push(role);
+    }
+    Ok(normalized)
+}
+
+/// Roles stored as `supervisor,admin`
+pub fn parse_roles(stored: Option<&str>) -> Vec<String> {
+    stored
+        .unwrap_or_default()
+        .split(',')
+        .map(|r| r.trim().to_lowercase())
+        .filter(|r| !r.is_empty())
+        .collect()
+}
+
+fn is_hash(stored: &str) -> bool {
+    stored.starts_with("$2")
+}
+
+/// bcrypt hash of a new password
+pub async fn hash_password(password: String) -> Result<String> {
+    Ok(tokio::task::spawn_blocking(move || bcrypt::hash(password, bcrypt::DEFAULT_COST)).await??)
+}
+
+/// Check a login password against the stored hash, or the plaintext of a
+/// row that was never reset; a NULL password never matches
+pub async fn verify_password(password: String, stored: Option<String>) -> bool {
+    match stored {
+        Some(stored) if is_hash(&stored) => {
+            tokio::task::spawn_blocking(move || bcrypt::verify(password, &stored).unwrap_or(false))
+                .await
+                .unwrap_or(false)
+        }
+        Some(stored) => stored == password,
+        None => false,
+    }
+}
+
+fn map_user(row: &Row, clock: &PlantClock) -> Result<LocalUser> {
+    let username: String = column(row, "uname")?;
+    let first_name = column::<Option<String>>(row, "Fname")?.unwrap_or_default();
+    let last_name = column::<Option<String>>(row, "Lname")?.unwrap_or_default();
+    let display_name = format!("{} {}", first_name, last_name).trim().to_string();
+
+    Ok(LocalUser {
+        display_name: if display_name.is_empty() {
+            username.clone()
+        } else {
+            display_name
+        },
+        username,
+        first_name,
+        last_name,
+        roles: parse_roles(column(row, "Roles")?),
+        disabled: column::<Option<bool>>(row, "Disabled")?.unwrap_or(false),
+        password_hashed: column::<i32>(row, "PasswordHashed")? == 1,
+        modified_by: column(row, "ModifiedBy")?,
+        modified_date: column::<Option<NaiveDateTime>>(row, "ModifiedDate")?
+            .map(|d| clock.format(d)),
+    })
+}
+
+const USER_QUERY: &str = r#"
+    SELECT u.uname, u.Fname, u.Lname,
+           CASE WHEN u.pword LIKE '$2%' THEN 1 ELSE 0 END AS PasswordHashed,
+           a.Roles, a.Disabled, a.ModifiedBy, a.ModifiedDate
+    FROM tbl_user u
+    LEFT JOIN cust_PartialPickUserAccount a ON a.uname = u.uname
+    WHERE u.auth_source = 'LOCAL'
+"#;
+
+pub async fn list_users(
+    pool: &MssqlPool,
+    clock: &PlantClock,
+    include_disabled: bool,
+) -> Result<Vec<LocalUser>> {
+    let sql = format!(
+        "{} AND (@P1 = 1 OR ISNULL(a.Disabled, 0) = 0) ORDER BY u.uname",
+        USER_QUERY
+    );
+
+    pool.execute_read_with_params(
+        &sql,
+        |query| {
+            query.bind(include_disabled);
+        },
+        |row| map_user(row, clock),
+    )
+    .await
+}
+
+pub async fn get_user(
+    pool: &MssqlPool,
+    clock: &PlantClock,
+    username: &str,
+) -> Result<Option<LocalUser>> {
+    let sql = format!("{} AND u.uname = @P1", USER_QUERY);
+
+    let users = pool
+        .execute_read_with_params(
+            &sql,
+            |query| {
+                query.bind(username.to_string());
+            },
+            |row| map_user(row, clock),
+        )
+        .await?;
+
+    Ok(users.into_iter().next())
+}
+
+/// Create a LOCAL account with a hashed password
+pub async fn create_user(
+    pool: &MssqlPool,
+    username: &str,
+    first_name: &str,
+    last_name: &str,
+    password_hash: String,
+    roles: &[String],
+    created_by: &str,
+) -> Result<(), UserAdminError> {
+    let sql = r#"
+        SET XACT_ABORT ON;
+        BEGIN TRANSACTION;
+
+        DECLARE @Source VARCHAR(20) =
+            (SELECT auth_source FROM tbl_user WITH (UPDLOCK, HOLDLOCK) WHERE uname = @P1);
+
+        IF @Source IS NULL
+        BEGIN
+            INSERT INTO tbl_user (uname, Fname, Lname, pword, auth_source)
+            VALUES (@P1, @P4, @P5, @P6, 'LOCAL');
+
+            INSERT INTO cust_PartialPickUserAccount (uname, Roles, Disabled, ModifiedBy, ModifiedDate)
+            VALUES (@P1, @P7, 0, @P2, GETUTCDATE());
+
+            INSERT INTO cust_PartialPickUserAudit (Username, Action, Detail, ChangedBy, ChangedDate)
+            VALUES (@P1, 'CREATED', @P3, @P2, GETUTCDATE());
+        END
+
+        COMMIT TRANSACTION;
+
+        SELECT @Source AS AuthSource
+    "#;
+
+    let roles = roles.join(",");
+    let detail = audit_detail(Some(first_name), Some(last_name), Some(&roles));
+    let source = pool
+        .execute_query_with_params(
+            sql,
+            |query| {
+                query.bind(username.to_string());
+                query.bind(created_by.to_string());
+                query.bind(detail);
+                query.bind(first_name.to_string());
+                query.bind(last_name.to_string());
+                query.bind(password_hash);
+                query.bind(roles);
+            },
+            |row| Ok(column::<Option<String>>(row, "AuthSource")?),
+        )
+        .await?;
+
+    match source.into_iter().next().flatten() {
+        None => Ok(()),
+        Some(_) => Err(UserAdminError::Exists(username.to_string())),
+    }
+}
+
+/// Set the name and roles of a LOCAL account; `None` keeps a value
+pub async fn update_user(
+    pool: &MssqlPool,
+    username: &str,
+    first_name: Option<&str>,
+    last_name: Option<&str>,
+    roles: Option<&[String]>,
+    changed_by: &str,
+) -> Result<(), UserAdminError> {
+    let change = r#"
+        UPDATE tbl_user
+        SET Fname = COALESCE(@P4, Fname), Lname = COALESCE(@P5, Lname)
+        WHERE uname = @P1;
+
+        MERGE cust_PartialPickUserAccount AS target
+        USING (SELECT @P1 AS uname) AS source ON target.uname = source.uname
+        WHEN MATCHED THEN
+            UPDATE SET Roles = COALESCE(@P6, Roles), ModifiedBy = @P2, ModifiedDate = GETUTCDATE()
+        WHEN NOT MATCHED THEN
+            INSERT (uname, Roles, Disabled, ModifiedBy, ModifiedDate)
+            VALUES (@P1, COALESCE(@P6, ''), 0, @P2, GETUTCDATE());
+    "#;
+
+    let roles = roles.map(|r| r.join(","));
+    let detail = audit_detail(first_name, last_name, roles.as_deref());
+    let (first_name, last_name) = (
+        first_name.map(str::to_string),
+        last_name.map(str::to_string),
+    );
+    change_local_user(
+        pool,
+        username,
+        changed_by,
+        "UPDATED",
+        detail,
+        change,
+        move |query| {
+            query.bind(first_name);
+            query.bind(last_name);
+            query.bind(roles);
+        },
+    )
+    .await
+}
+
+/// Disable or re-enable a LOCAL account
+pub async fn set_disabled(
+    pool: &MssqlPool,
+    username: &str,
+    disabled: bool,
+    changed_by: &str,
+) -> Result<(), UserAdminError> {
+    let change = r#"
+        MERGE cust_PartialPickUserAccount AS target
+        USING (SELECT @P1 AS uname) AS source ON target.uname = source.uname
+        WHEN MATCHED THEN
+            UPDATE SET Disabled = @P4, ModifiedBy = @P2, ModifiedDate = GETUTCDATE()
+        WHEN NOT MATCHED THEN
+            INSERT (uname, Roles, Disabled, ModifiedBy, ModifiedDate)
+            VALUES (@P1, '', @P4, @P2, GETUTCDATE());
+    "#;
+
+    let action = if disabled { "DISABLED" } else { "ENABLED" };
+    change_local_user(
+        pool,
+        username,
+        changed_by,
+        action,
+        None,
+        change,
+        move |query| {
+            query.bind(disabled);
+        },
+    )
+    .await
+}
+
+/// Replace the password of a LOCAL account with a new hash
+pub async fn set_password(
+    pool: &MssqlPool,
+    username: &str,
+    password_hash: String,
+    changed_by: &str,
+) -> Result<(), UserAdminError> {
+    let change = "UPDATE tbl_user SET pword = @P4 WHERE uname = @P1;";
+
+    change_local_user(
+        pool,
+        username,
+        changed_by,
+        "PASSWORD_RESET",
+        None,
+        change,
+        move |query| {
+            query.bind(password_hash);
+        },
+    )
+    .await
+}
+
+/// Run `change` and its audit entry if `username` is a LOCAL account
+///
+/// `change` sees the username as `@P1` and the admin as `@P2`; `bind_fn`
+/// binds its own parameters from `@P4` on.
+async fn change_local_user(
+    pool: &MssqlPool,
+    username: &str,
+    changed_by: &str,
+    action: &str,
+    detail: Option<String>,
+    change: &str,
+    bind_fn: impl FnOnce(&mut tiberius::Query<'_>),
+) -> Result<(), UserAdminError> {
+    let sql = format!(
+        r#"
+        SET XACT_ABORT ON;
+        BEGIN TRANSACTION;
+
+        DECLARE @Source VARCHAR(20) =
+            (SELECT auth_source FROM tbl_user WITH (UPDLOCK, HOLDLOCK) WHERE uname = @P1);
+
+        IF @Source = 'LOCAL'
+        BEGIN
+            {change}
+
+            INSERT INTO cust_PartialPickUserAudit (Username, Action, Detail, ChangedBy, ChangedDate)
+            VALUES (@P1, '{action}', @P3, @P2, GETUTCDATE());
+        END
+
+        COMMIT TRANSACTION;
+
+        SELECT @Source AS AuthSource
+        "#,
+        change = change,
+        action = action
+    );
+
+    let source = pool
+        .execute_query_with_params(
+            &sql,
+            |query| {
+                query.bind(username.to_string());
+                query.bind(changed_by.to_string());
+                query.bind(detail);
+                bind_fn(query);
+            },
+            |row| Ok(column::<Option<String>>(row, "AuthSource")?),
+        )
+        .await?;
+
+    match source.into_iter().next().flatten().as_deref() {
+        Some("LOCAL") => Ok(()),
+        Some(_) => Err(UserAdminError::DirectoryAccount(username.to_string())),
+        None => Err(UserAdminError::NotFound(username.to_string())),
+    }
+}
+
+/// What changed, for the audit trail; passwords are never recorded
+fn audit_detail(
+    first_name: Option<&str>,
+    last_name: Option<&str>,
+    roles: Option<&str>,
+) -> Option<String> {
+    let mut parts = vec![];
+    if first_name.is_some() || last_name.is_some() {
+        parts.push(format!(
+            "name: {} {}",
+            first_name.unwrap_or("(unchanged)"),
+            last_name.unwrap_or("(unchanged)")
+        ));
+    }
+    if let Some(roles) = roles {
+        parts.push(format!(
+            "roles: {}",
+            if roles.is_empty() { "none" } else { roles }
+        ));
+    }
+    Some(parts.join("; ")).filter(|d| !d.is_empty())
+}
+
+/// Most recent changes, for one account or all
+pub async fn list_audit(
+    pool: &MssqlPool,
+    clock: &PlantClock,
+    username: Option<&str>,
+) -> Result<Vec<UserAuditEntry>> {
+    let sql = r#"
+        SELECT TOP (@P2) AuditId, Username, Action, Detail, ChangedBy, ChangedDate
+        FROM cust_PartialPickUserAudit
+        WHERE @P1 IS NULL OR Username = @P1
+        ORDER BY AuditId DESC
+    "#;
+
+    pool.execute_read_with_params(
+        sql,
+        |query| {
+            query.bind(username.map(str::to_string));
+            query.bind(AUDIT_LIMIT);
+        },
+        |row| {
+            Ok(UserAuditEntry {
+                audit_id: column(row, "AuditId")?,
+                username: column(row, "Username")?,
+                action: column(row, "Action")?,
+                detail: column(row, "Detail")?,
+                changed_by: column(row, "ChangedBy")?,
+                changed_date: clock.format(column(row, "ChangedDate")?),
+            })
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation() {
+        assert!(validate_username("LOCAL_line1").is_ok());
+        assert!(validate_username("local.qa").is_ok());
+        assert!(validate_username("LOCAL").is_err());
+        assert!(validate_username("jsmith").is_err());
+        assert!(validate_username("LOCAL user").is_err());
+
+        assert!(validate_password("short").is_err());
+        assert!(validate_password("long enough").is_ok());
+
+        assert_eq!(
+            normalize_roles(&[" Supervisor".into(), "supervisor".into(), "".into()]).unwrap(),
+            vec!["supervisor"]
+        );
+        assert!(normalize_roles(&["root".into()]).is_err());
+        assert_eq!(
+            parse_roles(Some("supervisor, admin")),
+            vec!["supervisor", "admin"]
+        );
+        assert!(parse_roles(None).is_empty());
+    }
+
+    #[test]
+    fn test_audit_detail_never_has_password() {
+        assert_eq!(
+            audit_detail(Some("Line"), None, Some("")),
+            Some("name: Line (unchanged); roles: none".to_string())
+        );
+        assert_eq!(audit_detail(None, None, None), None);
+    }
+
+    #[tokio::test]
+    async fn test_verify_password() {
+        let hash = bcrypt::hash("correct horse", 4).unwrap();
+        assert!(verify_password("correct horse".into(), Some(hash.clone())).await);
+        assert!(!verify_password("wrong".into(), Some(hash)).await);
+        // Rows created by hand before hashing
+        assert!(verify_password("plain".into(), Some("plain".into())).await);
+        assert!(!verify_password("".into(), None).await);
+    }
+}
@@ -0,0 +1,806 @@
+//! Support CLI
+//!
+//! The RM operations from a terminal, for when the web UI is down or for
+//! night-shift support over SSH. It reads the same `.env` as the API server
+//! and uses the same plants, reason codes, approval thresholds and audit
+//! trail.
+//!
+//! Commands that change data show what they are about to change and ask for
+//! confirmation; `--yes` answers for scripts. Without a terminal and without
+//! `--yes` they refuse to run. The login name of the shell is recorded in
+//! `User3`, `ModifiedBy` and the audit trail the same way as a web user;
+//! `--on-behalf-of` only adds a name to the audit comment next to it.
+//!
+//! Run leases are kept in the API server's memory, so `remove` and `restore`
+//! cannot see them and go through even while a station holds an exclusive
+//! lease on the run. They print a reminder before asking for confirmation.
+
+mod output;
+
+/// Shown before changes and by `config-check`; see the module docs
+const LEASE_NOTE: &str = "run leases are held by the API server and are not checked by the CLI; \
+     make sure no station is working on the run";
+
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
+use dotenv::dotenv;
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use output::Format;
+use rm_partial_pick_remover_api::auth::uses_default_jwt_secret;
+use rm_partial_pick_remover_api::db::row::column;
+use rm_partial_pick_remover_api::digest::{self, DigestConfig};
+use rm_partial_pick_remover_api::ldap::{LdapClient, LdapConfig};
+use rm_partial_pick_remover_api::models::qty::Qty;
+use rm_partial_pick_remover_api::models::rm::{RMLine, RemoveItem, RemoveItemStatus};
+use rm_partial_pick_remover_api::plant::{Plant, PlantRegistry};
+use rm_partial_pick_remover_api::rm::approval::{self, ApprovalPolicy};
+use rm_partial_pick_remover_api::rm::lines::eligible_lines;
+use rm_partial_pick_remover_api::rm::removal::{plan, remove_items, target_qty, RemovalContext};
+use rm_partial_pick_remover_api::rm::restore::{restore_removal, RestoreStatus};
+use rm_partial_pick_remover_api::rm::runs::{open_runs, RunFilter, DEFAULT_RUN_LIMIT};
+use rm_partial_pick_remover_api::rm::{history, reason};
+use rm_partial_pick_remover_api::server::{SecurityConfig, ServerConfig};
+
+/// Tables (or `table.column`) each feature needs, with the script that creates them
+const REQUIRED_OBJECTS: &[(&str, &str)] = &[
+    ("cust_PartialPicked", "the BME database"),
+    (
+        "cust_PartialPickRemovalApproval",
+        "sql/001_removal_approvals.sql",
+    ),
+    (
+        "cust_PartialPickReasonCode",
+        "sql/002_reason_codes_and_audit.sql",
+    ),
+    (
+        "cust_PartialPickRemovalAudit",
+        "sql/002_reason_codes_and_audit.sql",
+    ),
+    ("cust_PartialPickWebhook", "sql/003_webhooks.sql"),
+    ("cust_PartialPickOutbox", "sql/003_webhooks.sql"),
+    ("cust_PartialPickUserAccount", "sql/005_local_users.sql"),
+    ("cust_PartialPickUserAudit", "sql/005_local_users.sql"),
+    (
+        "cust_PartialPickRemovalAudit.RestoresAuditId",
+        "sql/006_restores.sql",
+    ),
+];
+
+/// RM partial pick removal from the command line
+#[derive(Debug, Parser)]
+#[command(name = "rm-remover-cli", version)]
+struct Cli {
+    /// Plant code; the default plant when omitted
+    #[arg(long, global = true)]
+    plant: Option<String>,
+
+    /// Person a change is made for, added to the audit comment; the login
+    /// name is still recorded as the user
+    #[arg(long, global = true)]
+    on_behalf_of: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Runs with lines that can be removed, most recently modified first
+    Runs {
+        /// First plant-local day as YYYY-MM-DD
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Last plant-local day as YYYY-MM-DD
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// Only runs with open lines of this item
+        #[arg(long)]
+        item_key: Option<String>,
+        #[arg(long, default_value_t = DEFAULT_RUN_LIMIT)]
+        limit: i32,
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Lines of a run that can be removed
+    Search {
+        run_no: i32,
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Remove partial quantities from lines of a run
+    Remove(RemoveArgs),
+    /// Undo one removal by its audit id (see `history`)
+    Restore {
+        audit_id: i32,
+        /// Comment stored in the audit trail
+        #[arg(long)]
+        comment: Option<String>,
+        /// Do not ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Removal audit trail of a run, newest first
+    History {
+        run_no: i32,
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Write the removal digest of a day now, and mail it when SMTP is configured
+    Digest {
+        /// Plant-local day as YYYY-MM-DD [default: yesterday]
+        #[arg(long)]
+        date: Option<NaiveDate>,
+        /// Only write the files
+        #[arg(long)]
+        no_mail: bool,
+    },
+    /// Check settings, database tables and directory connectivity of every plant
+    ConfigCheck {
+        /// Do not connect to the directory
+        #[arg(long)]
+        skip_ldap: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+struct RemoveArgs {
+    run_no: i32,
+
+    /// Line to change as ROW:LINE; repeat for several lines
+    #[arg(long = "line", value_name = "ROW:LINE", value_parser = parse_line_key,
+          required_unless_present = "all", conflicts_with = "all")]
+    lines: Vec<(i32, i32)>,
+
+    /// Every eligible line of the run, optionally narrowed by batch and item
+    #[arg(long)]
+    all: bool,
+
+    /// Only lines of this batch, with --all
+    #[arg(long, conflicts_with = "lines")]
+    batch_no: Option<String>,
+
+    /// Only lines of this item, with --all
+    #[arg(long, conflicts_with = "lines")]
+    item_key: Option<String>,
+
+    /// Reason code, see `GET /api/rm/reason-codes`
+    #[arg(long)]
+    reason: String,
+
+    /// Comment stored with the reason; required by some codes
+    #[arg(long)]
+    comment: Option<String>,
+
+    /// New ToPickedPartialQty instead of zero
+    #[arg(long, conflicts_with = "reduce_by")]
+    new_qty: Option<Qty>,
+
+    /// Amount to take off ToPickedPartialQty
+    #[arg(long)]
+    reduce_by: Option<Qty>,
+
+    /// Round the result down to whole packs
+    #[arg(long)]
+    round_to_pack_size: bool,
+
+    /// Do not ask for confirmation
+    #[arg(long, short)]
+    yes: bool,
+}
+
+fn parse_line_key(value: &str) -> Result<(i32, i32), String> {
+    let (row, line) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected ROW:LINE, got {}", value))?;
+    match (row.trim().parse(), line.trim().parse()) {
+        (Ok(row), Ok(line)) => Ok((row, line)),
+        _ => Err(format!("expected numbers in ROW:LINE, got {}", value)),
+    }
+}
+
+/// Login name of the shell, recorded for every change
+fn login_name() -> Result<String> {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .ok()
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty())
+        .context("No login name in USER or USERNAME")
+}
+
+/// Audit comment with `--on-behalf-of` appended, so the claimed name is kept
+/// next to the login instead of replacing it
+fn with_on_behalf_of(comment: Option<String>, on_behalf_of: Option<&str>) -> Option<String> {
+    let comment = comment
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty());
+    match on_behalf_of.map(str::trim).filter(|n| !n.is_empty()) {
+        Some(name) => Some(match comment {
+            Some(c) => format!("{} (on behalf of {})", c, name),
+            None => format!("On behalf of {}", name),
+        }),
+        None => comment,
+    }
+}
+
+/// Ask before changing data; `yes` answers for scripts
+fn confirm(prompt: &str, yes: bool) -> Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+    if !io::stdin().is_terminal() {
+        bail!("Refusing to change data without a terminal; pass --yes to confirm");
+    }
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+struct Session {
+    registry: PlantRegistry,
+    plant: Arc<Plant>,
+}
+
+impl Session {
+    async fn open(plant: Option<&str>) -> Result<Self> {
+        let registry = PlantRegistry::from_env().await?;
+        let plant = registry
+            .for_login(plant)
+            .cloned()
+            .with_context(|| format!("Unknown plant {}", plant.unwrap_or_default()))?;
+        Ok(Self { registry, plant })
+    }
+
+    /// The login recorded for changes, which must be allowed at the plant
+    fn user(&self) -> Result<String> {
+        let user = login_name()?;
+        if !self.registry.plants_for(&user).contains(&self.plant.code) {
+            bail!("User {} may not work at plant {}", user, self.plant.code);
+        }
+        Ok(user)
+    }
+}
+
+async fn search(plant: &Plant, run_no: i32, format: Format) -> Result<bool> {
+    let mut lines = eligible_lines(&plant.pool, run_no).await?;
+    plant.enrich(&mut lines).await;
+    println!("{}", output::lines(&lines, format)?);
+    Ok(true)
+}
+
+async fn list_runs(plant: &Plant, filter: RunFilter, format: Format) -> Result<bool> {
+    let runs = open_runs(&plant.pool, &plant.clock, &filter).await?;
+    println!("{}", output::runs(&runs, format)?);
+    Ok(true)
+}
+
+async fn show_history(plant: &Plant, run_no: i32, format: Format) -> Result<bool> {
+    let entries = history::run_history(&plant.pool, &plant.clock, run_no).await?;
+    println!("{}", output::history(&entries, format)?);
+    Ok(true)
+}
+
+async fn run_digest(plant: &Plant, date: Option<NaiveDate>, no_mail: bool) -> Result<bool> {
+    let config = DigestConfig::from_env(plant.env_prefix())?;
+    let day = date.unwrap_or_else(|| digest::previous_day(&plant.clock));
+    let (digest, files) = digest::generate(
+        &plant.pool,
+        &plant.code,
+        &plant.clock,
+        &config,
+        day,
+        !no_mail,
+    )
+    .await?;
+
+    println!(
+        "{}: {} lines removed from {} runs, total quantity {}",
+        digest.title(),
+        digest.total.lines,
+        digest.runs,
+        digest.total.qty
+    );
+    for path in [&files.html, &files.summary_csv, &files.removals_csv] {
+        println!("wrote {}", path.display());
+    }
+    if let Some(mail) = config.mail.as_ref().filter(|_| !no_mail) {
+        let to: Vec<String> = mail.to.iter().map(|m| m.email.to_string()).collect();
+        println!("mailed to {}", to.join(", "));
+    }
+    Ok(true)
+}
+
+/// Lines named with `--line`, or all eligible lines matching the filters
+fn select_lines(lines: &[RMLine], args: &RemoveArgs) -> Result<Vec<RMLine>> {
+    if args.all {
+        let matches = |filter: &Option<String>, value: &str| {
+            filter
+                .as_deref()
+                .is_none_or(|f| f.trim().eq_ignore_ascii_case(value.trim()))
+        };
+        return Ok(lines
+            .iter()
+            .filter(|l| {
+                matches(&args.batch_no, &l.batch_no) && matches(&args.item_key, &l.item_key)
+            })
+            .cloned()
+            .collect());
+    }
+
+    args.lines
+        .iter()
+        .map(|(row, line)| {
+            lines
+                .iter()
+                .find(|l| l.row_num == *row && l.line_id == *line)
+                .cloned()
+                .with_context(|| {
+                    format!(
+                        "Line {}:{} of run {} not found or not eligible for removal",
+                        row, line, args.run_no
+                    )
+                })
+        })
+        .collect()
+}
+
+async fn remove(
+    plant: &Plant,
+    user: &str,
+    on_behalf_of: Option<&str>,
+    args: RemoveArgs,
+) -> Result<bool> {
+    let mut reason =
+        reason::validate(&plant.pool, Some(&args.reason), args.comment.as_deref()).await?;
+    reason.comment = with_on_behalf_of(reason.comment, on_behalf_of);
+
+    let lines = eligible_lines(&plant.pool, args.run_no).await?;
+    let selected = select_lines(&lines, &args)?;
+    if selected.is_empty() {
+        println!("No eligible lines of run {} match", args.run_no);
+        return Ok(true);
+    }
+
+    // Tokens of the lines as shown, so lines changed before confirming are refused
+    let items: Vec<RemoveItem> = selected
+        .iter()
+        .map(|line| RemoveItem {
+            row_num: line.row_num,
+            line_id: line.line_id,
+            concurrency_token: Some(line.concurrency_token.clone()),
+            new_qty: args.new_qty,
+            reduce_by: args.reduce_by,
+            round_to_pack_size: args.round_to_pack_size,
+        })
+        .collect();
+    for (line, item) in selected.iter().zip(&items) {
+        if let Err(msg) = target_qty(line.to_picked_partial_qty, line.pack_size, item) {
+            bail!("Line {}:{}: {}", line.row_num, line.line_id, msg);
+        }
+    }
+
+    let planned = plan(&selected, &items);
+    println!("{}\n", output::plan(&planned));
+
+    let policy = ApprovalPolicy::from_env();
+    if let Some(threshold) = policy.evaluate(&planned) {
+        println!("This removal needs supervisor approval: {}", threshold);
+        if !confirm("Submit it for approval?", args.yes)? {
+            println!("Cancelled");
+            return Ok(false);
+        }
+        let request_id = approval::create_request(
+            &plant.pool,
+            args.run_no,
+            &planned,
+            &reason,
+            &threshold,
+            user,
+        )
+        .await?;
+        println!("Submitted as approval request #{}", request_id);
+        return Ok(true);
+    }
+
+    println!("Note: {}", LEASE_NOTE);
+    let prompt = format!(
+        "Change {} line(s) of run {} at plant {} as {} (reason {})?",
+        planned.len(),
+        args.run_no,
+        plant.code,
+        user,
+        reason.code
+    );
+    if !confirm(&prompt, args.yes)? {
+        println!("Cancelled");
+        return Ok(false);
+    }
+
+    let ctx = RemovalContext {
+        removed_by: user.to_string(),
+        approved_by: None,
+        approval_request_id: None,
+        reason,
+        removed_at: plant.clock.now(),
+    };
+    let outcome = remove_items(&plant.pool, args.run_no, &items, &ctx).await;
+    println!("{}", output::results(&outcome.results));
+
+    let removed = outcome
+        .results
+        .iter()
+        .filter(|r| r.status == RemoveItemStatus::Removed)
+        .count();
+    println!("\n{} of {} line(s) changed", removed, outcome.results.len());
+    Ok(outcome.errors.is_empty())
+}
+
+async fn restore(
+    plant: &Plant,
+    user: &str,
+    on_behalf_of: Option<&str>,
+    audit_id: i32,
+    comment: Option<String>,
+    yes: bool,
+) -> Result<bool> {
+    let entry = history::audit_entry(&plant.pool, &plant.clock, audit_id)
+        .await?
+        .with_context(|| format!("No audit entry #{}", audit_id))?;
+    println!(
+        "{}\n",
+        output::history(std::slice::from_ref(&entry), Format::Table)?
+    );
+
+    println!("Note: {}", LEASE_NOTE);
+    let prompt = format!(
+        "Restore line {}:{} of run {} from {} to {} as {}?",
+        entry.row_num, entry.line_id, entry.run_no, entry.qty_after, entry.qty_before, user
+    );
+    if !confirm(&prompt, yes)? {
+        println!("Cancelled");
+        return Ok(false);
+    }
+
+    let comment = with_on_behalf_of(comment, on_behalf_of);
+    let outcome = restore_removal(
+        &plant.pool,
+        audit_id,
+        user,
+        comment.as_deref(),
+        plant.clock.now(),
+    )
+    .await?;
+
+    match outcome.status {
+        RestoreStatus::Restored => {
+            println!(
+                "Restored line {}:{} of run {} to {}",
+                entry.row_num,
+                entry.line_id,
+                entry.run_no,
+                outcome.qty_after.unwrap_or(entry.qty_before)
+            );
+            Ok(true)
+        }
+        RestoreStatus::NotFound => bail!("Audit entry #{} is not a removal", audit_id),
+        RestoreStatus::AlreadyRestored => bail!("Removal #{} was already restored", audit_id),
+        RestoreStatus::Conflict => bail!(
+            "Line {}:{} changed after removal #{} (ToPickedPartialQty is {}, expected {}) or has been picked",
+            entry.row_num,
+            entry.line_id,
+            audit_id,
+            outcome
+                .qty_before
+                .map(|q| q.to_string())
+                .unwrap_or_else(|| "not eligible".to_string()),
+            entry.qty_after
+        ),
+    }
+}
+
+/// Print one check and remember failures
+struct Checks {
+    failed: bool,
+}
+
+impl Checks {
+    fn ok(&self, what: &str) {
+        println!("OK    {}", what);
+    }
+
+    fn warn(&self, what: &str) {
+        println!("WARN  {}", what);
+    }
+
+    fn fail(&mut self, what: &str) {
+        println!("FAIL  {}", what);
+        self.failed = true;
+    }
+}
+
+async fn config_check(plant_code: Option<&str>, skip_ldap: bool) -> Result<bool> {
+    let mut checks = Checks { failed: false };
+
+    match ServerConfig::from_env() {
+        Ok(config) => checks.ok(&format!(
+            "server settings, listening on {}",
+            config.address()
+        )),
+        Err(e) => checks.fail(&format!("server settings: {:#}", e)),
+    }
+    match SecurityConfig::from_env() {
+        Ok(_) => checks.ok("CORS and security settings"),
+        Err(e) => checks.fail(&format!("CORS and security settings: {:#}", e)),
+    }
+    if uses_default_jwt_secret() {
+        checks.warn("JWT_SECRET is not set, tokens use the built-in development secret");
+    }
+    checks.warn(LEASE_NOTE);
+    let policy = ApprovalPolicy::from_env();
+    let limits: Vec<String> = [
+        policy.max_lines.map(|v| format!("{} lines", v)),
+        policy.max_qty.map(|v| format!("total quantity {}", v)),
+        policy.max_percent.map(|v| format!("{}% of StandardQty", v)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if !limits.is_empty() {
+        checks.ok(&format!("approval required above {}", limits.join(", ")));
+    }
+
+    let registry = match PlantRegistry::from_env().await {
+        Ok(registry) => registry,
+        Err(e) => {
+            checks.fail(&format!("plant settings: {:#}", e));
+            return Ok(false);
+        }
+    };
+
+    for plant in registry.plants() {
+        if plant_code.is_some_and(|code| !plant.code.eq_ignore_ascii_case(code)) {
+            continue;
+        }
+        println!(
+            "\nPlant {} ({}), time zone {}",
+            plant.code,
+            plant.name,
+            plant.clock.timezone()
+        );
+
+        match plant.pool.negotiated_encryption().await {
+            Ok(encrypted) => {
+                let what = format!(
+                    "database reachable, connection {} ({})",
+                    if encrypted {
+                        "encrypted"
+                    } else {
+                        "not encrypted beyond login"
+                    },
+                    plant.pool.tls().encryption
+                );
+                if encrypted {
+                    checks.ok(&what)
+                } else {
+                    checks.warn(&what)
+                }
+                check_tables(plant, &mut checks).await;
+            }
+            Err(e) => checks.fail(&format!("database: {:#}", e)),
+        }
+
+        match DigestConfig::from_env(plant.env_prefix()) {
+            Ok(config) => {
+                if let Some(schedule) = &config.schedule {
+                    checks.ok(&format!(
+                        "digest scheduled at '{}', written to {}",
+                        schedule,
+                        config.output_dir.display()
+                    ));
+                }
+                if let Some(mail) = &config.mail {
+                    checks.ok(&format!(
+                        "digest mailed via {}:{} ({})",
+                        mail.host, mail.port, mail.tls
+                    ));
+                }
+            }
+            Err(e) => checks.fail(&format!("digest settings: {:#}", e)),
+        }
+
+        if let Some(master) = &plant.master {
+            let tables = master.tables().join(", ");
+            match master.probe(&plant.pool).await {
+                Ok(()) => checks.ok(&format!("master data lookups in {}", tables)),
+                Err(e) => checks.fail(&format!("master data lookups in {}: {:#}", tables, e)),
+            }
+        }
+
+        match LdapConfig::from_env(plant.env_prefix()) {
+            Ok(config) if skip_ldap => checks.ok(&format!("directory settings, {}", config.url)),
+            Ok(config) => {
+                let target = match &config.bind_dn {
+                    Some(dn) => format!("{} as {}", config.url, dn),
+                    None => config.url.clone(),
+                };
+                match LdapClient::new(config).check().await {
+                    Ok(()) => checks.ok(&format!("directory reachable at {}", target)),
+                    Err(e) => checks.fail(&format!("directory at {}: {}", target, e)),
+                }
+            }
+            Err(e) => checks.fail(&format!("directory settings: {}", e)),
+        }
+    }
+
+    Ok(!checks.failed)
+}
+
+async fn check_tables(plant: &Plant, checks: &mut Checks) {
+    let probes: Vec<String> = REQUIRED_OBJECTS
+        .iter()
+        .map(|(name, _)| {
+            let missing = match name.split_once('.') {
+                Some((table, column)) => {
+                    format!("COL_LENGTH('dbo.{}', '{}') IS NULL", table, column)
+                }
+                None => format!("OBJECT_ID('dbo.{}', 'U') IS NULL", name),
+            };
+            format!("SELECT '{}' AS Name WHERE {}", name, missing)
+        })
+        .collect();
+    let sql = probes.join(" UNION ALL ");
+
+    match plant
+        .pool
+        .execute_query(&sql, |row| Ok(column::<String>(row, "Name")?))
+        .await
+    {
+        Ok(missing) if missing.is_empty() => checks.ok("all tables present"),
+        Ok(missing) => {
+            for (name, script) in REQUIRED_OBJECTS
+                .iter()
+                .filter(|(n, _)| missing.iter().any(|m| m == n))
+            {
+                checks.fail(&format!("{} missing, create it with {}", name, script));
+            }
+        }
+        Err(e) => checks.fail(&format!("could not check tables: {:#}", e)),
+    }
+}
+
+async fn run(cli: Cli) -> Result<bool> {
+    let Cli {
+        plant,
+        on_behalf_of,
+        command,
+    } = cli;
+
+    match command {
+        Command::ConfigCheck { skip_ldap } => config_check(plant.as_deref(), skip_ldap).await,
+        Command::Runs {
+            from,
+            to,
+            item_key,
+            limit,
+            format,
+        } => {
+            let session = Session::open(plant.as_deref()).await?;
+            let filter = RunFilter {
+                from,
+                to,
+                item_key,
+                limit,
+            };
+            list_runs(&session.plant, filter, format).await
+        }
+        Command::Search { run_no, format } => {
+            let session = Session::open(plant.as_deref()).await?;
+            search(&session.plant, run_no, format).await
+        }
+        Command::History { run_no, format } => {
+            let session = Session::open(plant.as_deref()).await?;
+            show_history(&session.plant, run_no, format).await
+        }
+        Command::Digest { date, no_mail } => {
+            let session = Session::open(plant.as_deref()).await?;
+            run_digest(&session.plant, date, no_mail).await
+        }
+        Command::Remove(args) => {
+            let session = Session::open(plant.as_deref()).await?;
+            let user = session.user()?;
+            remove(&session.plant, &user, on_behalf_of.as_deref(), args).await
+        }
+        Command::Restore {
+            audit_id,
+            comment,
+            yes,
+        } => {
+            let session = Session::open(plant.as_deref()).await?;
+            let user = session.user()?;
+            restore(
+                &session.plant,
+                &user,
+                on_behalf_of.as_deref(),
+                audit_id,
+                comment,
+                yes,
+            )
+            .await
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    dotenv().ok();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    match run(Cli::parse()).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line_key() {
+        assert_eq!(parse_line_key("12:3"), Ok((12, 3)));
+        assert_eq!(parse_line_key(" 12 : 3 "), Ok((12, 3)));
+        assert!(parse_line_key("12").is_err());
+        assert!(parse_line_key("a:3").is_err());
+    }
+
+    #[test]
+    fn test_remove_arguments() {
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from(
+                [
+                    &["rm-remover-cli", "remove", "1001", "--reason", "QA_HOLD"],
+                    args,
+                ]
+                .concat(),
+            )
+        };
+
+        assert!(parse(&["--line", "1:1", "--line", "2:1"]).is_ok());
+        assert!(parse(&["--all", "--batch-no", "B1"]).is_ok());
+        // A line or --all is required, and filters only narrow --all
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--line", "1:1", "--all"]).is_err());
+        assert!(parse(&["--line", "1:1", "--batch-no", "B1"]).is_err());
+        assert!(parse(&["--line", "1:1", "--new-qty", "5", "--reduce-by", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_on_behalf_of_is_kept_in_the_comment() {
+        assert_eq!(with_on_behalf_of(None, None), None);
+        assert_eq!(
+            with_on_behalf_of(Some(" spill ".to_string()), None),
+            Some("spill".to_string())
+        );
+        assert_eq!(
+            with_on_behalf_of(None, Some("jdoe")),
+            Some("On behalf of jdoe".to_string())
+        );
+        assert_eq!(
+            with_on_behalf_of(Some("spill".to_string()), Some("jdoe")),
+            Some("spill (on behalf of jdoe)".to_string())
+        );
+        // The old flag is gone, so a name can no longer replace the login
+        assert!(Cli::try_parse_from(["rm-remover-cli", "--user", "jdoe", "search", "1"]).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+//! Table, JSON and CSV rendering of command results
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use rm_partial_pick_remover_api::models::rm::{
+    OpenRun, RMLine, RemovalAuditEntry, RemoveItemResult,
+};
+use rm_partial_pick_remover_api::rm::history;
+use rm_partial_pick_remover_api::rm::removal::PlannedRemoval;
+
+/// Output format of read commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+/// Columns padded to their widest cell, with a dashed line under the header
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+
+    let dashes: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    let mut out = vec![
+        line(headers.to_vec()),
+        line(dashes.iter().map(String::as_str).collect()),
+    ];
+    out.extend(
+        rows.iter()
+            .map(|row| line(row.iter().map(String::as_str).collect())),
+    );
+    out.join("\n")
+}
+
+fn json<T: Serialize>(rows: &[T]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(rows)?)
+}
+
+fn csv<T: Serialize>(rows: &[T]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+fn opt<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// `OpenRun` with the batches joined, so each run stays one CSV row
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct RunRow<'a> {
+    run_no: i32,
+    line_count: i32,
+    item_count: i32,
+    outstanding_qty: String,
+    earliest_modified: Option<&'a str>,
+    latest_modified: Option<&'a str>,
+    batches: String,
+}
+
+impl<'a> From<&'a OpenRun> for RunRow<'a> {
+    fn from(run: &'a OpenRun) -> Self {
+        Self {
+            run_no: run.run_no,
+            line_count: run.line_count,
+            item_count: run.item_count,
+            outstanding_qty: run.outstanding_qty.to_string(),
+            earliest_modified: run.earliest_modified.as_deref(),
+            latest_modified: run.latest_modified.as_deref(),
+            batches: run.batches.join(" "),
+        }
+    }
+}
+
+pub fn runs(runs: &[OpenRun], format: Format) -> Result<String> {
+    match format {
+        Format::Json => json(runs),
+        Format::Csv => csv(&runs.iter().map(RunRow::from).collect::<Vec<_>>()),
+        Format::Table => Ok(table(
+            &[
+                "Run", "Lines", "Items", "ToPick", "Earliest", "Latest", "Batches",
+            ],
+            &runs
+                .iter()
+                .map(|r| {
+                    vec![
+                        r.run_no.to_string(),
+                        r.line_count.to_string(),
+                        r.item_count.to_string(),
+                        r.outstanding_qty.to_string(),
+                        opt(r.earliest_modified.as_deref()),
+                        opt(r.latest_modified.as_deref()),
+                        r.batches.join(" "),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        )),
+    }
+}
+
+pub fn lines(lines: &[RMLine], format: Format) -> Result<String> {
+    match format {
+        Format::Json => json(lines),
+        Format::Csv => csv(lines),
+        Format::Table => Ok(table(
+            &[
+                "Row",
+                "Line",
+                "Batch",
+                "Type",
+                "Item",
+                "Description",
+                "Location",
+                "Unit",
+                "Standard",
+                "Pack",
+                "ToPick",
+                "Picked",
+            ],
+            &lines
+                .iter()
+                .map(|l| {
+                    vec![
+                        l.row_num.to_string(),
+                        l.line_id.to_string(),
+                        l.batch_no.clone(),
+                        l.line_typ.clone(),
+                        l.item_key.clone(),
+                        opt(l.item_description.as_deref()),
+                        l.location.clone(),
+                        l.unit.clone(),
+                        l.standard_qty.to_string(),
+                        l.pack_size.to_string(),
+                        l.to_picked_partial_qty.to_string(),
+                        opt(l.picked_partial_qty),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        )),
+    }
+}
+
+pub fn history(entries: &[RemovalAuditEntry], format: Format) -> Result<String> {
+    match format {
+        Format::Json => json(entries),
+        Format::Csv => history::to_csv(entries),
+        Format::Table => Ok(table(
+            &[
+                "Audit", "Date", "Row", "Line", "Batch", "Item", "Before", "After", "Reason", "By",
+                "Approved", "Comment",
+            ],
+            &entries
+                .iter()
+                .map(|e| {
+                    vec![
+                        e.audit_id.to_string(),
+                        e.removed_date.clone(),
+                        e.row_num.to_string(),
+                        e.line_id.to_string(),
+                        e.batch_no.clone(),
+                        e.item_key.clone(),
+                        e.qty_before.to_string(),
+                        e.qty_after.to_string(),
+                        e.reason_code.clone(),
+                        e.removed_by.clone(),
+                        opt(e.approved_by.as_deref()),
+                        opt(e.comment.as_deref()),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        )),
+    }
+}
+
+/// The change a removal is about to make, shown before confirming
+pub fn plan(planned: &[PlannedRemoval]) -> String {
+    table(
+        &["Row", "Line", "Batch", "Item", "ToPick", "Remove", "Left"],
+        &planned
+            .iter()
+            .map(|p| {
+                vec![
+                    p.line.row_num.to_string(),
+                    p.line.line_id.to_string(),
+                    p.line.batch_no.clone(),
+                    p.line.item_key.clone(),
+                    p.line.to_picked_partial_qty.to_string(),
+                    p.remove_qty.to_string(),
+                    (p.line.to_picked_partial_qty - p.remove_qty).to_string(),
+                ]
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+pub fn results(results: &[RemoveItemResult]) -> String {
+    table(
+        &["Row", "Line", "Status", "Before", "After", "Message"],
+        &results
+            .iter()
+            .map(|r| {
+                vec![
+                    r.row_num.to_string(),
+                    r.line_id.to_string(),
+                    format!("{:?}", r.status),
+                    opt(r.qty_before),
+                    opt(r.qty_after),
+                    opt(r.message.as_deref()),
+                ]
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_pads_columns() {
+        let rendered = table(
+            &["Row", "Item"],
+            &[
+                vec!["1".to_string(), "SUGAR".to_string()],
+                vec!["12".to_string(), "".to_string()],
+            ],
+        );
+        assert_eq!(rendered, "Row  Item\n---  -----\n1    SUGAR\n12");
+    }
+}
@@ -0,0 +1,160 @@
+//! SQL Server Browser Lookup
+//!
+//! A named instance (`HOST\INSTANCE`) usually listens on a dynamic port. The
+//! SQL Server Browser service on UDP 1434 answers which one (MC-SQLR): the
+//! client sends `0x04` followed by the instance name and gets back a
+//! `;`-separated list such as
+//! `ServerName;DB01;InstanceName;PLANT;IsClustered;No;Version;15.0.2000.5;tcp;49712;;`.
+
+use std::io::{Error, ErrorKind, Result};
+use std::time::Duration;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::timeout;
+
+/// Port of the SQL Server Browser service
+pub const BROWSER_PORT: u16 = 1434;
+
+/// How long to wait for the browser to answer
+const BROWSER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// CLNT_UCAST_INST: ask for one instance
+const CLNT_UCAST_INST: u8 = 0x04;
+
+/// SVR_RESP: header byte of every answer
+const SVR_RESP: u8 = 0x05;
+
+/// Ask the browser at `host:browser_port` for the TCP port of `instance`
+///
+/// # Errors
+///
+/// Returns a `TimedOut` error when the browser does not answer and a
+/// `NotFound` or `InvalidData` error when the answer has no TCP port for
+/// the instance.
+pub async fn resolve_instance_port(host: &str, browser_port: u16, instance: &str) -> Result<u16> {
+    let addr = lookup_host((host, browser_port))
+        .await?
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Unknown host {}", host)))?;
+    let local = if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(addr).await?;
+    socket
+        .send(&[&[CLNT_UCAST_INST], instance.as_bytes()].concat())
+        .await?;
+
+    let mut buf = vec![0u8; 4096];
+    let len = timeout(BROWSER_TIMEOUT, socket.recv(&mut buf))
+        .await
+        .map_err(|_| {
+            Error::new(
+                ErrorKind::TimedOut,
+                format!("no answer from {} within {:?}", addr, BROWSER_TIMEOUT),
+            )
+        })??;
+
+    parse_reply(&buf[..len], instance)
+}
+
+/// TCP port of `instance` in a browser reply
+fn parse_reply(reply: &[u8], instance: &str) -> Result<u16> {
+    let invalid = |reason: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Invalid SQL Server Browser reply for instance {}: {}",
+                instance, reason
+            ),
+        )
+    };
+
+    let (header, body) = match reply {
+        [header, _, _, body @ ..] => (*header, body),
+        _ => return Err(invalid("too short")),
+    };
+    if header != SVR_RESP {
+        return Err(invalid("unexpected header"));
+    }
+    let text = String::from_utf8_lossy(body);
+
+    // Several instances can be listed, each ending with `;;`
+    for entry in text.split(";;") {
+        let fields: Vec<&str> = entry.split(';').collect();
+        let value = |key: &str| {
+            fields
+                .chunks(2)
+                .find(|pair| pair[0].eq_ignore_ascii_case(key))
+                .and_then(|pair| pair.get(1))
+                .copied()
+        };
+        if !value("InstanceName").is_some_and(|name| name.eq_ignore_ascii_case(instance)) {
+            continue;
+        }
+        return match value("tcp") {
+            Some(port) => port.parse().map_err(|_| invalid("bad tcp port")),
+            None => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("Instance {} does not accept TCP connections", instance),
+            )),
+        };
+    }
+
+    Err(Error::new(
+        ErrorKind::NotFound,
+        format!("SQL Server Browser does not know instance {}", instance),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(text: &str) -> Vec<u8> {
+        let len = (text.len() as u16).to_le_bytes();
+        [&[SVR_RESP, len[0], len[1]], text.as_bytes()].concat()
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let text = "ServerName;DB01;InstanceName;OTHER;IsClustered;No;Version;15.0;tcp;50001;;\
+                    ServerName;DB01;InstanceName;PLANT;IsClustered;No;Version;15.0;tcp;49712;;";
+        assert_eq!(parse_reply(&reply(text), "plant").unwrap(), 49712);
+
+        let pipes_only = "ServerName;DB01;InstanceName;PLANT;np;\\\\DB01\\pipe\\sql\\query;;";
+        assert_eq!(
+            parse_reply(&reply(pipes_only), "PLANT").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert_eq!(
+            parse_reply(&[0x01, 0, 0], "PLANT").unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    /// A UDP stand-in for the browser service answers on a local port
+    #[tokio::test]
+    async fn test_resolve_against_local_browser() {
+        let browser = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = browser.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let mut buf = [0u8; 256];
+            let (len, from) = browser.recv_from(&mut buf).await.unwrap();
+            let text = "ServerName;DB01;InstanceName;PLANT;IsClustered;No;tcp;49712;;";
+            browser.send_to(&reply(text), from).await.unwrap();
+            buf[..len].to_vec()
+        });
+
+        assert_eq!(
+            resolve_instance_port("127.0.0.1", port, "PLANT")
+                .await
+                .unwrap(),
+            49712
+        );
+        assert_eq!(server.await.unwrap(), b"\x04PLANT");
+    }
+}
@@ -0,0 +1,447 @@
+//! Connection Settings
+//!
+//! A database is configured either with separate variables or with one
+//! ADO.NET connection string as handed out by DBAs:
+//!
+//! ```text
+//! DB_CONNECTION_STRING=Server=tcp:DB01\PLANT;Database=TFCPILOT;User ID=rm;Password=...;Encrypt=true;Application Name=RM Remover
+//! ```
+//!
+//! | Variable | Description | Default |
+//! |----------|-------------|---------|
+//! | `DB_CONNECTION_STRING` | ADO.NET connection string, replaces the variables below | (unset) |
+//! | `DB_SERVER` | `host`, `host,port` or `host\INSTANCE` | (required) |
+//! | `DB_PORT` | TCP port, overrides the one in `DB_SERVER` | `1433` |
+//! | `DB_DATABASE` | Database name | (required) |
+//! | `DB_USERNAME` / `DB_PASSWORD` | SQL login | (required) |
+//! | `DB_APPLICATION_NAME` | Name shown in `sys.dm_exec_sessions` | crate name |
+//!
+//! In a connection string, `Encrypt`, `TrustServerCertificate`,
+//! `TrustServerCertificateCA` and `HostNameInCertificate` override the
+//! settings of [`crate::db::tls`]. `{prefix}DB_DATABASE` still picks the
+//! database when set, so plants can share a string, and `DB_PASSWORD` is used
+//! when the string carries no password. A plant that sets its own
+//! `DB_SERVER` does not use the shared string. A named instance without a
+//! port is looked up through the SQL Server Browser.
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::warn;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::db::mssql::prefixed_var;
+use crate::db::tls::{DbEncryption, DbTls};
+
+/// Default SQL Server TCP port
+pub const DEFAULT_PORT: u16 = 1433;
+
+/// Where the server is: a host with either a fixed port or an instance name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerAddress {
+    pub host: String,
+    pub instance: Option<String>,
+    pub port: Option<u16>,
+}
+
+impl ServerAddress {
+    /// Port to connect to without asking the browser, if known
+    pub fn fixed_port(&self) -> Option<u16> {
+        match (&self.port, &self.instance) {
+            (Some(port), _) => Some(*port),
+            (None, Some(_)) => None,
+            (None, None) => Some(DEFAULT_PORT),
+        }
+    }
+}
+
+impl FromStr for ServerAddress {
+    type Err = anyhow::Error;
+
+    /// Parse `[tcp:]host[\instance][,port]`
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let s = match s.split_once(':') {
+            Some((protocol, rest)) if protocol.eq_ignore_ascii_case("tcp") => rest,
+            Some((protocol, _))
+                if matches!(protocol.to_ascii_lowercase().as_str(), "np" | "lpc") =>
+            {
+                bail!("Only TCP connections are supported, got: {}", s)
+            }
+            _ => s,
+        };
+
+        let (server, port) = match s.split_once(',') {
+            Some((server, port)) => (
+                server,
+                Some(
+                    port.trim()
+                        .parse::<u16>()
+                        .map_err(|_| anyhow!("Invalid port in server address: {}", s))?,
+                ),
+            ),
+            None => (s, None),
+        };
+        let (host, instance) = match server.split_once('\\') {
+            Some((host, instance)) => (host.trim(), Some(instance.trim().to_string())),
+            None => (server.trim(), None),
+        };
+
+        let host = match host {
+            "" => bail!("Server address has no host: {}", s),
+            "." | "(local)" => "localhost",
+            host => host,
+        };
+        Ok(Self {
+            host: host.to_string(),
+            instance: instance.filter(|i| !i.is_empty()),
+            port,
+        })
+    }
+}
+
+impl fmt::Display for ServerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.host)?;
+        if let Some(instance) = &self.instance {
+            write!(f, "\\{}", instance)?;
+        }
+        match self.port {
+            Some(port) => write!(f, ":{}", port),
+            None if self.instance.is_none() => write!(f, ":{}", DEFAULT_PORT),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Everything needed to open connections to one database
+#[derive(Debug, Clone)]
+pub struct ConnectionSettings {
+    pub address: ServerAddress,
+    pub database: String,
+    pub username: String,
+    pub password: String,
+    pub application_name: String,
+    pub tls: DbTls,
+}
+
+impl ConnectionSettings {
+    /// Load the settings from `{prefix}DB_CONNECTION_STRING` or the separate
+    /// `{prefix}DB_*` variables, falling back to `DB_*` for everything except
+    /// the database name when a prefix is given
+    pub fn from_env(prefix: &str) -> Result<Self> {
+        let database = env::var(format!("{}DB_DATABASE", prefix))
+            .ok()
+            .filter(|d| !d.trim().is_empty());
+        let application_name = prefixed_var(prefix, "DB_APPLICATION_NAME")
+            .ok()
+            .filter(|n| !n.trim().is_empty())
+            .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+        let mut tls = DbTls::from_env(prefix)?;
+
+        if let Some(value) = connection_string_var(prefix) {
+            let cs: ConnectionString = value
+                .parse()
+                .with_context(|| format!("Invalid {}DB_CONNECTION_STRING", prefix))?;
+            if cs.integrated_security() {
+                bail!("Integrated Security is not supported; use a SQL login");
+            }
+            let unknown = cs.unknown_keys();
+            if !unknown.is_empty() {
+                warn!("Ignoring connection string keys: {}", unknown.join(", "));
+            }
+            cs.apply_tls(&mut tls)?;
+            tls.validate()?;
+
+            let password = match cs.get(&["password", "pwd"]) {
+                Some(password) => password.to_string(),
+                None => prefixed_var(prefix, "DB_PASSWORD")
+                    .context("Connection string has no Password and DB_PASSWORD is not set")?,
+            };
+            return Ok(Self {
+                address: cs.server()?,
+                database: database
+                    .or_else(|| cs.get(&["database", "initial catalog"]).map(str::to_string))
+                    .with_context(|| {
+                        format!(
+                            "Connection string has no Database and {}DB_DATABASE is not set",
+                            prefix
+                        )
+                    })?,
+                username: cs
+                    .get(&["user id", "uid", "user", "username"])
+                    .map(str::to_string)
+                    .context("Connection string has no User ID")?,
+                password,
+                application_name: cs
+                    .get(&["application name", "app"])
+                    .map(str::to_string)
+                    .unwrap_or(application_name),
+                tls,
+            });
+        }
+
+        let mut address: ServerAddress = prefixed_var(prefix, "DB_SERVER")
+            .context("DB_SERVER not set")?
+            .parse()
+            .context("Invalid DB_SERVER")?;
+        if let Some(port) = prefixed_var(prefix, "DB_PORT")
+            .ok()
+            .and_then(|p| p.trim().parse::<u16>().ok())
+        {
+            address.port = Some(port);
+        }
+
+        Ok(Self {
+            address,
+            database: database.with_context(|| format!("{}DB_DATABASE not set", prefix))?,
+            username: prefixed_var(prefix, "DB_USERNAME").context("DB_USERNAME not set")?,
+            password: prefixed_var(prefix, "DB_PASSWORD").context("DB_PASSWORD not set")?,
+            application_name,
+            tls,
+        })
+    }
+}
+
+/// The connection string for a plant: its own, or the shared one unless the
+/// plant sets its own `DB_SERVER`
+fn connection_string_var(prefix: &str) -> Option<String> {
+    let var = |name: String| env::var(name).ok().filter(|s| !s.trim().is_empty());
+    var(format!("{}DB_CONNECTION_STRING", prefix)).or_else(|| {
+        if !prefix.is_empty() && var(format!("{}DB_SERVER", prefix)).is_some() {
+            return None;
+        }
+        var("DB_CONNECTION_STRING".to_string())
+    })
+}
+
+/// Keys of an ADO.NET connection string this service understands
+const KNOWN_KEYS: &[&str] = &[
+    "server",
+    "data source",
+    "address",
+    "addr",
+    "network address",
+    "database",
+    "initial catalog",
+    "user id",
+    "uid",
+    "user",
+    "username",
+    "password",
+    "pwd",
+    "application name",
+    "app",
+    "encrypt",
+    "trustservercertificate",
+    "trust server certificate",
+    "trustservercertificateca",
+    "hostnameincertificate",
+    "host name in certificate",
+    "integrated security",
+    "trusted_connection",
+];
+
+/// A parsed ADO.NET connection string, keys lowercased
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionString {
+    values: HashMap<String, String>,
+}
+
+impl FromStr for ConnectionString {
+    type Err = anyhow::Error;
+
+    /// Parse `key=value` pairs separated by `;`
+    ///
+    /// Values may be quoted with `'` or `"` to contain `;`, with the quote
+    /// doubled inside, and `==` in a key stands for `=`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut values = HashMap::new();
+        let mut chars = s.chars().peekable();
+
+        loop {
+            // Key, up to a single `=`
+            let mut key = String::new();
+            loop {
+                match chars.next() {
+                    Some('=') if chars.peek() == Some(&'=') => {
+                        chars.next();
+                        key.push('=');
+                    }
+                    Some('=') => break,
+                    Some(';') if key.trim().is_empty() => key.clear(),
+                    Some(';') => bail!("Missing value for {}", key.trim()),
+                    Some(c) => key.push(c),
+                    None if key.trim().is_empty() => return Ok(Self { values }),
+                    None => bail!("Missing value for {}", key.trim()),
+                }
+            }
+
+            // Value, quoted or up to the next `;`
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            let mut value = String::new();
+            match chars.peek().copied() {
+                Some(quote @ ('\'' | '"')) => {
+                    chars.next();
+                    loop {
+                        match chars.next() {
+                            Some(c) if c == quote && chars.peek() == Some(&quote) => {
+                                chars.next();
+                                value.push(quote);
+                            }
+                            Some(c) if c == quote => break,
+                            Some(c) => value.push(c),
+                            None => bail!("Unterminated quote in value of {}", key.trim()),
+                        }
+                    }
+                    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                    if !matches!(chars.next(), Some(';') | None) {
+                        bail!("Unexpected text after quoted value of {}", key.trim());
+                    }
+                }
+                _ => {
+                    for c in chars.by_ref() {
+                        if c == ';' {
+                            break;
+                        }
+                        value.push(c);
+                    }
+                    value = value.trim().to_string();
+                }
+            }
+
+            let key = key.trim().to_ascii_lowercase();
+            if key.is_empty() {
+                bail!("Connection string has a value without a key");
+            }
+            values.insert(key, value);
+        }
+    }
+}
+
+impl ConnectionString {
+    /// Value of the first of `keys` that is set
+    pub fn get(&self, keys: &[&str]) -> Option<&str> {
+        keys.iter()
+            .find_map(|key| self.values.get(*key))
+            .map(String::as_str)
+    }
+
+    pub fn server(&self) -> Result<ServerAddress> {
+        self.get(&[
+            "server",
+            "data source",
+            "address",
+            "addr",
+            "network address",
+        ])
+        .context("Connection string has no Server")?
+        .parse()
+    }
+
+    /// Whether the string asks for Windows authentication
+    pub fn integrated_security(&self) -> bool {
+        self.get(&["integrated security", "trusted_connection"])
+            .is_some_and(|value| parse_bool(value).unwrap_or(true))
+    }
+
+    /// Keys that are not used, e.g. `MultipleActiveResultSets`
+    pub fn unknown_keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self
+            .values
+            .keys()
+            .map(String::as_str)
+            .filter(|key| !KNOWN_KEYS.contains(key))
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Let the encryption keys of the string override `tls`
+    pub fn apply_tls(&self, tls: &mut DbTls) -> Result<()> {
+        if let Some(value) = self.get(&["encrypt"]) {
+            tls.encryption = match value.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "mandatory" | "strict" => DbEncryption::Required,
+                "false" | "no" | "optional" => DbEncryption::Off,
+                _ => bail!("Invalid Encrypt value in connection string: {}", value),
+            };
+        }
+        if let Some(value) = self.get(&["trustservercertificate", "trust server certificate"]) {
+            tls.trust_server_certificate = parse_bool(value).ok_or_else(|| {
+                anyhow!(
+                    "Invalid TrustServerCertificate value in connection string: {}",
+                    value
+                )
+            })?;
+        }
+        if let Some(path) = self.get(&["trustservercertificateca"]) {
+            tls.ca_cert = Some(PathBuf::from(path));
+        }
+        if let Some(name) = self.get(&["hostnameincertificate", "host name in certificate"]) {
+            tls.server_name = Some(name.to_string());
+        }
+        Ok(())
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" => Some(true),
+        "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_address() {
+        let named: ServerAddress = r"tcp:DB01\PLANT".parse().unwrap();
+        assert_eq!(named.host, "DB01");
+        assert_eq!(named.instance.as_deref(), Some("PLANT"));
+        assert_eq!(named.fixed_port(), None);
+        assert_eq!(named.to_string(), r"DB01\PLANT");
+
+        let with_port: ServerAddress = r"db01.example.local\PLANT,50100".parse().unwrap();
+        assert_eq!(with_port.fixed_port(), Some(50100));
+
+        let plain: ServerAddress = ".".parse().unwrap();
+        assert_eq!(plain.to_string(), "localhost:1433");
+
+        assert!("np:DB01".parse::<ServerAddress>().is_err());
+        assert!("DB01,port".parse::<ServerAddress>().is_err());
+    }
+
+    #[test]
+    fn test_connection_string() {
+        let cs: ConnectionString = "Data Source=DB01\\PLANT; Initial Catalog=TFCPILOT;\
+             User ID=rm;Password='se;cr''et';Application Name=\"RM Remover\";\
+             Encrypt=True;HostNameInCertificate=db01.example.local;MultipleActiveResultSets=true;"
+            .parse()
+            .unwrap();
+
+        assert_eq!(cs.get(&["server", "data source"]), Some("DB01\\PLANT"));
+        assert_eq!(cs.get(&["database", "initial catalog"]), Some("TFCPILOT"));
+        assert_eq!(cs.get(&["password"]), Some("se;cr'et"));
+        assert_eq!(cs.get(&["application name"]), Some("RM Remover"));
+        assert_eq!(cs.server().unwrap().instance.as_deref(), Some("PLANT"));
+
+        let mut tls = DbTls {
+            encryption: DbEncryption::Off,
+            ..DbTls::default()
+        };
+        cs.apply_tls(&mut tls).unwrap();
+        assert_eq!(tls.encryption, DbEncryption::Required);
+        assert_eq!(tls.server_name.as_deref(), Some("db01.example.local"));
+
+        assert!("Server".parse::<ConnectionString>().is_err());
+        assert!("Password='open".parse::<ConnectionString>().is_err());
+        let integrated: ConnectionString = "Server=DB01;Integrated Security=SSPI".parse().unwrap();
+        assert!(integrated.integrated_security());
+        assert_eq!(cs.unknown_keys(), vec!["multipleactiveresultsets"]);
+    }
+}
@@ -0,0 +1,103 @@
+//! Database Error Types
+//!
+//! Failures of MSSQL operations, classified so callers can tell a blocked
+//! query from a transient fault worth retrying and from a real error.
+
+use std::time::Duration;
+use thiserror::Error;
+use tiberius::error::{Error as TdsError, IoErrorKind};
+
+/// Server error numbers that go away when the statement is run again
+///
+/// 1205 deadlock victim, 1222 lock request timeout, -2 client timeout,
+/// 233/10053/10054/10060 transport failures, 40197/40501/40613/49918-49920
+/// Azure SQL failover and throttling.
+const TRANSIENT_SERVER_CODES: &[u32] = &[
+    1205, 1222, 233, 10053, 10054, 10060, 40197, 40501, 40613, 49918, 49919, 49920,
+];
+
+/// Errors that can occur during database operations
+#[derive(Error, Debug)]
+pub enum DbError {
+    /// Connecting or logging in took longer than the connect timeout
+    #[error("Connecting to the database timed out after {0:?}")]
+    ConnectTimeout(Duration),
+
+    /// The statement ran longer than the query timeout and was abandoned
+    #[error("Database operation timed out after {0:?}")]
+    QueryTimeout(Duration),
+
+    /// Deadlock, dropped connection or similar; the statement may be retried
+    #[error("Transient database error: {0}")]
+    Transient(TdsError),
+
+    /// Any other driver or server error
+    #[error("Database error: {0}")]
+    Query(TdsError),
+}
+
+impl DbError {
+    /// Whether running the same statement again may succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, DbError::ConnectTimeout(_) | DbError::Transient(_))
+    }
+
+    /// The database error behind an `anyhow` error, if any
+    pub fn find(err: &anyhow::Error) -> Option<&DbError> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<DbError>())
+    }
+}
+
+impl From<TdsError> for DbError {
+    fn from(err: TdsError) -> Self {
+        if is_transient(&err) {
+            DbError::Transient(err)
+        } else {
+            DbError::Query(err)
+        }
+    }
+}
+
+fn is_transient(err: &TdsError) -> bool {
+    match err {
+        TdsError::Io { kind, .. } => matches!(
+            kind,
+            IoErrorKind::ConnectionReset
+                | IoErrorKind::ConnectionAborted
+                | IoErrorKind::ConnectionRefused
+                | IoErrorKind::BrokenPipe
+                | IoErrorKind::TimedOut
+                | IoErrorKind::UnexpectedEof
+        ),
+        TdsError::Server(token) => TRANSIENT_SERVER_CODES.contains(&token.code()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classification() {
+        let reset = TdsError::Io {
+            kind: IoErrorKind::ConnectionReset,
+            message: "connection reset by peer".to_string(),
+        };
+        assert!(DbError::from(reset).is_retryable());
+
+        let conversion = TdsError::Conversion("bad value".into());
+        assert!(!DbError::from(conversion).is_retryable());
+
+        assert!(DbError::ConnectTimeout(Duration::from_secs(5)).is_retryable());
+        assert!(!DbError::QueryTimeout(Duration::from_secs(5)).is_retryable());
+
+        let err = anyhow::Error::from(DbError::QueryTimeout(Duration::from_secs(30)))
+            .context("Failed to load run");
+        assert!(matches!(
+            DbError::find(&err),
+            Some(DbError::QueryTimeout(_))
+        ));
+    }
+}
@@ -1 +1,6 @@
+pub mod browser;
+pub mod connection;
+pub mod error;
 pub mod mssql;
+pub mod row;
+pub mod tls;
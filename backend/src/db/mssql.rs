@@ -1,74 +1,355 @@
-use anyhow::{Context, Result};
-use log::info;
+//! MSSQL Access
+//!
+//! Every operation opens its own connection, so dropping an operation's
+//! future (a timeout, or actix dropping the handler when the HTTP client
+//! disconnects) closes the connection and SQL Server rolls back whatever
+//! the batch had not committed.
+//!
+//! Connecting and running a statement each have a timeout. Read-only queries
+//! and idempotent writes are retried on transient failures such as a
+//! deadlock or a dropped connection; other writes are never re-run.
+//!
+//! | Variable | Description | Default |
+//! |----------|-------------|---------|
+//! | `DB_CONNECT_TIMEOUT_SECS` | Time allowed to connect and log in | `15` |
+//! | `DB_QUERY_TIMEOUT_SECS` | Time allowed for one statement | `30` |
+//! | `DB_RETRY_ATTEMPTS` | Attempts for retryable operations, including the first | `3` |
+//! | `DB_RETRY_BACKOFF_MS` | Delay before the first retry, doubled each time | `200` |
+//!
+//! Each can be overridden per plant with the `PLANT_<CODE>_` prefix.
+//! The server and login are configured in [`crate::db::connection`] and
+//! encryption in [`crate::db::tls`].
+
+use anyhow::{bail, Result};
+use log::{info, warn};
 use std::env;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tiberius::error::Error as TdsError;
 use tiberius::{AuthMethod, Client, Config, Query, Row};
 use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
 use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
 
+use crate::db::browser::{resolve_instance_port, BROWSER_PORT};
+use crate::db::connection::{ConnectionSettings, ServerAddress};
+use crate::db::error::DbError;
+use crate::db::row::column;
+use crate::db::tls::DbTls;
+
 pub type DbClient = Client<Compat<TcpStream>>;
 
+/// Timeouts and retries applied to every operation of a pool
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DbPolicy {
+    pub connect_timeout: Duration,
+    pub query_timeout: Duration,
+    /// Attempts for retryable operations, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each further one
+    pub retry_backoff: Duration,
+}
+
+impl Default for DbPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(Self::DEFAULT_CONNECT_TIMEOUT_SECS),
+            query_timeout: Duration::from_secs(Self::DEFAULT_QUERY_TIMEOUT_SECS),
+            max_attempts: Self::DEFAULT_RETRY_ATTEMPTS,
+            retry_backoff: Duration::from_millis(Self::DEFAULT_RETRY_BACKOFF_MS),
+        }
+    }
+}
+
+impl DbPolicy {
+    /// Environment variable names
+    const ENV_CONNECT_TIMEOUT_SECS: &'static str = "DB_CONNECT_TIMEOUT_SECS";
+    const ENV_QUERY_TIMEOUT_SECS: &'static str = "DB_QUERY_TIMEOUT_SECS";
+    const ENV_RETRY_ATTEMPTS: &'static str = "DB_RETRY_ATTEMPTS";
+    const ENV_RETRY_BACKOFF_MS: &'static str = "DB_RETRY_BACKOFF_MS";
+
+    /// Default values
+    const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 15;
+    const DEFAULT_QUERY_TIMEOUT_SECS: u64 = 30;
+    const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+    const DEFAULT_RETRY_BACKOFF_MS: u64 = 200;
+
+    /// Longest wait between two attempts
+    const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+    /// Load the policy from `{prefix}DB_*`, falling back to `DB_*`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for a value that is not a number, a zero timeout or
+    /// zero attempts.
+    pub fn from_env(prefix: &str) -> Result<Self> {
+        let policy = Self {
+            connect_timeout: Duration::from_secs(number_var(
+                prefix,
+                Self::ENV_CONNECT_TIMEOUT_SECS,
+                Self::DEFAULT_CONNECT_TIMEOUT_SECS,
+            )?),
+            query_timeout: Duration::from_secs(number_var(
+                prefix,
+                Self::ENV_QUERY_TIMEOUT_SECS,
+                Self::DEFAULT_QUERY_TIMEOUT_SECS,
+            )?),
+            max_attempts: number_var(
+                prefix,
+                Self::ENV_RETRY_ATTEMPTS,
+                Self::DEFAULT_RETRY_ATTEMPTS,
+            )?,
+            retry_backoff: Duration::from_millis(number_var(
+                prefix,
+                Self::ENV_RETRY_BACKOFF_MS,
+                Self::DEFAULT_RETRY_BACKOFF_MS,
+            )?),
+        };
+
+        if policy.connect_timeout.is_zero() || policy.query_timeout.is_zero() {
+            bail!("Database timeouts must be greater than 0");
+        }
+        if policy.max_attempts == 0 {
+            bail!("{} must be at least 1", Self::ENV_RETRY_ATTEMPTS);
+        }
+
+        Ok(policy)
+    }
+
+    /// Wait before attempt `attempt + 1`
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.retry_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(Self::MAX_BACKOFF)
+    }
+}
+
 #[derive(Clone)]
 pub struct MssqlPool {
     config: Config,
-    host: String,
-    port: u16,
+    address: ServerAddress,
+    /// Port of a named instance as last reported by the SQL Server Browser
+    instance_port: Arc<Mutex<Option<u16>>>,
+    policy: DbPolicy,
+    tls: DbTls,
 }
 
 impl MssqlPool {
-    pub async fn new() -> Result<Self> {
-        let server = env::var("DB_SERVER").context("DB_SERVER not set")?;
-        let database = env::var("DB_DATABASE").context("DB_DATABASE not set")?;
-        let username = env::var("DB_USERNAME").context("DB_USERNAME not set")?;
-        let password = env::var("DB_PASSWORD").context("DB_PASSWORD not set")?;
-        let port = env::var("DB_PORT")
-            .ok()
-            .and_then(|p| p.parse::<u16>().ok())
-            .unwrap_or(1433);
+    /// Connection settings from `{prefix}DB_*`, falling back to `DB_*` for
+    /// everything except the database name when a prefix is given
+    pub async fn from_env(prefix: &str) -> Result<Self> {
+        Ok(Self::new(
+            ConnectionSettings::from_env(prefix)?,
+            DbPolicy::from_env(prefix)?,
+        ))
+    }
+
+    /// Pool for the given settings; no connection is opened until first use
+    pub fn new(settings: ConnectionSettings, policy: DbPolicy) -> Self {
+        let ConnectionSettings {
+            address,
+            database,
+            username,
+            password,
+            application_name,
+            tls,
+        } = settings;
 
         let mut config = Config::new();
-        config.host(&server);
-        config.port(port);
-        config.database(database);
+        config.host(&address.host);
+        if let Some(port) = address.port {
+            config.port(port);
+        }
+        config.database(&database);
+        config.application_name(&application_name);
         config.authentication(AuthMethod::sql_server(username, password));
-        config.trust_cert();
+        tls.apply(&mut config, &address.host);
 
-        info!("MSSQL configuration initialized for server: {}:{}", server, port);
+        info!(
+            "MSSQL configuration initialized for server: {} (database: {}, query timeout {:?}, \
+             encryption {}, {})",
+            address,
+            database,
+            policy.query_timeout,
+            tls.encryption,
+            tls.verification()
+        );
 
-        Ok(MssqlPool {
+        MssqlPool {
             config,
-            host: server,
-            port,
-        })
+            address,
+            instance_port: Arc::new(Mutex::new(None)),
+            policy,
+            tls,
+        }
     }
 
-    pub async fn get_client(&self) -> Result<DbClient> {
-        let tcp = TcpStream::connect(format!("{}:{}", self.host, self.port))
-            .await
-            .context("Failed to connect to MSSQL server")?;
+    pub fn tls(&self) -> &DbTls {
+        &self.tls
+    }
+
+    /// Whether a new connection is encrypted, as reported by the server
+    pub async fn negotiated_encryption(&self) -> Result<bool> {
+        let rows = self
+            .execute_query(
+                "SELECT CAST(CONNECTIONPROPERTY('encrypt_option') AS VARCHAR(10)) AS EncryptOption",
+                |row| Ok(column::<&str>(row, "EncryptOption")?.eq_ignore_ascii_case("TRUE")),
+            )
+            .await?;
+        Ok(rows.into_iter().next().unwrap_or(false))
+    }
 
-        tcp.set_nodelay(true)?;
+    /// Port to connect to, asking the SQL Server Browser for a named instance
+    async fn port(&self) -> Result<u16, TdsError> {
+        if let Some(port) = self.address.fixed_port() {
+            return Ok(port);
+        }
+        if let Some(port) = *self.instance_port.lock().unwrap() {
+            return Ok(port);
+        }
 
-        let client = Client::connect(self.config.clone(), tcp.compat_write())
+        let instance = self.address.instance.as_deref().unwrap_or_default();
+        let port = resolve_instance_port(&self.address.host, BROWSER_PORT, instance)
             .await
-            .context("Failed to authenticate with MSSQL server")?;
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "SQL Server Browser lookup of {} failed: {}",
+                        self.address, e
+                    ),
+                )
+            })?;
+        info!(
+            "SQL Server Browser on {} resolved instance {} to port {}",
+            self.address.host, instance, port
+        );
+        *self.instance_port.lock().unwrap() = Some(port);
+        Ok(port)
+    }
+
+    async fn connect(&self) -> Result<DbClient, DbError> {
+        let connect = async {
+            let port = self.port().await?;
+            let tcp = match TcpStream::connect((self.address.host.as_str(), port)).await {
+                Ok(tcp) => tcp,
+                Err(e) => {
+                    // A restarted instance may listen on a new dynamic port
+                    self.instance_port.lock().unwrap().take();
+                    return Err(e.into());
+                }
+            };
+            tcp.set_nodelay(true)?;
+            Client::connect(self.config.clone(), tcp.compat_write()).await
+        };
 
-        Ok(client)
+        match timeout(self.policy.connect_timeout, connect).await {
+            Ok(client) => Ok(client?),
+            Err(_) => Err(DbError::ConnectTimeout(self.policy.connect_timeout)),
+        }
     }
 
+    /// Run a statement on a new connection within the query timeout
+    async fn run_statement<T, Fut>(
+        &self,
+        statement: impl FnOnce(DbClient) -> Fut,
+    ) -> Result<T, DbError>
+    where
+        Fut: Future<Output = Result<T, tiberius::error::Error>>,
+    {
+        let client = self.connect().await?;
+        match timeout(self.policy.query_timeout, statement(client)).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(DbError::QueryTimeout(self.policy.query_timeout)),
+        }
+    }
+
+    async fn fetch_rows(
+        &self,
+        query_str: &str,
+        bind_fn: impl FnOnce(&mut Query<'_>),
+    ) -> Result<Vec<Row>, DbError> {
+        let mut query = Query::new(query_str);
+        bind_fn(&mut query);
+
+        self.run_statement(|mut client| async move {
+            query.query(&mut client).await?.into_first_result().await
+        })
+        .await
+    }
+
+    async fn execute_statement(
+        &self,
+        query_str: &str,
+        bind_fn: impl FnOnce(&mut Query<'_>),
+    ) -> Result<u64, DbError> {
+        let mut query = Query::new(query_str);
+        bind_fn(&mut query);
+
+        self.run_statement(
+            |mut client| async move { Ok(query.execute(&mut client).await?.total()) },
+        )
+        .await
+    }
+
+    /// Run `attempt` until it succeeds, fails for good or runs out of attempts
+    async fn with_retry<T, Fut>(&self, mut attempt: impl FnMut() -> Fut) -> Result<T, DbError>
+    where
+        Fut: Future<Output = Result<T, DbError>>,
+    {
+        let mut tries = 1;
+        loop {
+            match attempt().await {
+                Err(e) if e.is_retryable() && tries < self.policy.max_attempts => {
+                    let delay = self.policy.backoff(tries);
+                    warn!(
+                        "{} (attempt {} of {}), retrying in {:?}",
+                        e, tries, self.policy.max_attempts, delay
+                    );
+                    sleep(delay).await;
+                    tries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Run a read-only query, retrying transient failures
     pub async fn execute_query<T, F>(&self, query_str: &str, mapper: F) -> Result<Vec<T>>
     where
         F: Fn(&Row) -> Result<T>,
     {
-        let mut client = self.get_client().await?;
-        let query = Query::new(query_str);
+        let rows = self
+            .with_retry(|| self.fetch_rows(query_str, |_| {}))
+            .await?;
 
-        let stream = query.query(&mut client).await?;
-        let rows = stream.into_first_result().await?;
+        rows.iter().map(mapper).collect()
+    }
 
-        let results: Result<Vec<T>> = rows.iter().map(mapper).collect();
-        results
+    /// Run a read-only query with parameters, retrying transient failures
+    ///
+    /// `bind_fn` is called again for every attempt.
+    pub async fn execute_read_with_params<T, F>(
+        &self,
+        query_str: &str,
+        bind_fn: impl Fn(&mut Query<'_>),
+        mapper: F,
+    ) -> Result<Vec<T>>
+    where
+        F: Fn(&Row) -> Result<T>,
+    {
+        let bind_fn = &bind_fn;
+        let rows = self
+            .with_retry(|| self.fetch_rows(query_str, bind_fn))
+            .await?;
+
+        rows.iter().map(mapper).collect()
     }
 
+    /// Run a batch that may write and return its first result set; never retried
     pub async fn execute_query_with_params<T, F>(
         &self,
         query_str: &str,
@@ -78,48 +359,64 @@ impl MssqlPool {
     where
         F: Fn(&Row) -> Result<T>,
     {
-        let mut client = self.get_client().await?;
-        let mut query = Query::new(query_str);
-        bind_fn(&mut query);
-
-        let stream = query.query(&mut client).await?;
-        let rows = stream.into_first_result().await?;
+        let rows = self.fetch_rows(query_str, bind_fn).await?;
 
-        let results: Result<Vec<T>> = rows.iter().map(mapper).collect();
-        results
+        rows.iter().map(mapper).collect()
     }
 
+    /// Run a write and return the affected rows; never retried
     pub async fn execute_update(
         &self,
         query_str: &str,
         bind_fn: impl FnOnce(&mut Query<'_>),
     ) -> Result<u64> {
-        let mut client = self.get_client().await?;
-        let mut query = Query::new(query_str);
-        bind_fn(&mut query);
-
-        let result = query.execute(&mut client).await?;
-        let affected_rows = result.total();
+        Ok(self.execute_statement(query_str, bind_fn).await?)
+    }
 
-        Ok(affected_rows)
+    /// Run a write that has the same effect when repeated, retrying
+    /// transient failures
+    ///
+    /// `bind_fn` is called again for every attempt.
+    pub async fn execute_idempotent_update(
+        &self,
+        query_str: &str,
+        bind_fn: impl Fn(&mut Query<'_>),
+    ) -> Result<u64> {
+        let bind_fn = &bind_fn;
+        Ok(self
+            .with_retry(|| self.execute_statement(query_str, bind_fn))
+            .await?)
     }
 }
 
-pub fn get_string(row: &Row, col: &str) -> String {
-    row.try_get::<&str, _>(col)
-        .unwrap_or(None)
-        .unwrap_or("")
-        .to_string()
+fn number_var<T: std::str::FromStr>(prefix: &str, name: &str, default: T) -> Result<T> {
+    match prefixed_var(prefix, name) {
+        Ok(value) if !value.trim().is_empty() => match value.trim().parse::<T>() {
+            Ok(parsed) => Ok(parsed),
+            Err(_) => bail!("{}{} must be a valid number, got: {}", prefix, name, value),
+        },
+        _ => Ok(default),
+    }
 }
 
-pub fn get_i32(row: &Row, col: &str) -> i32 {
-    row.try_get::<i32, _>(col).unwrap_or(None).unwrap_or(0)
+pub fn prefixed_var(prefix: &str, name: &str) -> Result<String, env::VarError> {
+    env::var(format!("{}{}", prefix, name)).or_else(|_| env::var(name))
 }
 
-pub fn get_f64(row: &Row, col: &str) -> f64 {
-    row.try_get::<f64, _>(col).unwrap_or(None).unwrap_or(0.0)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub fn get_optional_f64(row: &Row, col: &str) -> Option<f64> {
-    row.try_get::<f64, _>(col).unwrap_or(None)
+    #[test]
+    fn test_backoff_doubles_up_to_cap() {
+        let policy = DbPolicy {
+            retry_backoff: Duration::from_millis(200),
+            ..DbPolicy::default()
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(20), DbPolicy::MAX_BACKOFF);
+    }
 }
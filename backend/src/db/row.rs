@@ -0,0 +1,213 @@
+//! Typed Row Mapping
+//!
+//! Every row is read through [`FromColumn`], which fails with the column name
+//! and the expected and actual SQL types instead of turning a NULL, a renamed
+//! column or a changed type into `""` or `0`. A schema change shows up as an
+//! error rather than as zero quantities.
+//!
+//! Nullable columns are read as `Option<T>`; a NULL in any other column is
+//! an error.
+//!
+//! ```rust,ignore
+//! impl FromRow for Line {
+//!     fn from_row(row: &Row) -> Result<Self, RowError> {
+//!         Ok(Line {
+//!             run_no: column(row, "RunNo")?,
+//!             picked_qty: column::<Option<f64>>(row, "PickedPartialQty")?,
+//!         })
+//!     }
+//! }
+//! ```
+
+use chrono::NaiveDateTime;
+use thiserror::Error;
+use tiberius::{ColumnData, FromSql, Row};
+
+/// Why a row could not be mapped
+#[derive(Error, Debug, PartialEq)]
+pub enum RowError {
+    #[error("Column {0} is missing from the result")]
+    MissingColumn(String),
+
+    #[error("Column {0} is NULL but is not nullable")]
+    UnexpectedNull(String),
+
+    #[error("Column {column} has SQL type {actual}, expected {expected}")]
+    TypeMismatch {
+        column: String,
+        expected: &'static str,
+        actual: &'static str,
+    },
+}
+
+/// Build a value from a whole result row
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, RowError>;
+}
+
+/// A value read from a single column
+pub trait FromColumn<'a>: Sized {
+    /// SQL types the value is read from, for error messages
+    const SQL_TYPE: &'static str;
+
+    /// `None` when the column has another type, `Some(None)` for NULL
+    fn from_column(data: &'a ColumnData<'static>) -> Option<Option<Self>>;
+
+    /// Value for a NULL column, `None` when NULL is not allowed
+    fn from_null() -> Option<Self> {
+        None
+    }
+}
+
+/// Read column `name` of `row` as `T`
+pub fn column<'a, T: FromColumn<'a>>(row: &'a Row, name: &str) -> Result<T, RowError> {
+    let (_, data) = row
+        .cells()
+        .find(|(c, _)| c.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| RowError::MissingColumn(name.to_string()))?;
+
+    match T::from_column(data) {
+        Some(Some(value)) => Ok(value),
+        Some(None) => T::from_null().ok_or_else(|| RowError::UnexpectedNull(name.to_string())),
+        None => Err(RowError::TypeMismatch {
+            column: name.to_string(),
+            expected: T::SQL_TYPE,
+            actual: sql_type(data),
+        }),
+    }
+}
+
+/// SQL type name of a value as sent by the server
+fn sql_type(data: &ColumnData<'_>) -> &'static str {
+    match data {
+        ColumnData::U8(_) => "TINYINT",
+        ColumnData::I16(_) => "SMALLINT",
+        ColumnData::I32(_) => "INT",
+        ColumnData::I64(_) => "BIGINT",
+        ColumnData::F32(_) => "REAL",
+        ColumnData::F64(_) => "FLOAT",
+        ColumnData::Bit(_) => "BIT",
+        ColumnData::String(_) => "VARCHAR",
+        ColumnData::Guid(_) => "UNIQUEIDENTIFIER",
+        ColumnData::Binary(_) => "VARBINARY",
+        ColumnData::Numeric(_) => "DECIMAL",
+        ColumnData::Xml(_) => "XML",
+        ColumnData::DateTime(_) | ColumnData::SmallDateTime(_) => "DATETIME",
+        ColumnData::Time(_) => "TIME",
+        ColumnData::Date(_) => "DATE",
+        ColumnData::DateTime2(_) => "DATETIME2",
+        ColumnData::DateTimeOffset(_) => "DATETIMEOFFSET",
+    }
+}
+
+impl<'a, T: FromColumn<'a>> FromColumn<'a> for Option<T> {
+    const SQL_TYPE: &'static str = T::SQL_TYPE;
+
+    fn from_column(data: &'a ColumnData<'static>) -> Option<Option<Self>> {
+        T::from_column(data).map(|value| value.map(Some))
+    }
+
+    fn from_null() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl FromColumn<'_> for i32 {
+    const SQL_TYPE: &'static str = "INT";
+
+    fn from_column(data: &ColumnData<'static>) -> Option<Option<Self>> {
+        match data {
+            ColumnData::U8(v) => Some(v.map(i32::from)),
+            ColumnData::I16(v) => Some(v.map(i32::from)),
+            ColumnData::I32(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+impl FromColumn<'_> for f64 {
+    const SQL_TYPE: &'static str = "FLOAT or DECIMAL";
+
+    fn from_column(data: &ColumnData<'static>) -> Option<Option<Self>> {
+        match data {
+            ColumnData::F32(v) => Some(v.map(f64::from)),
+            ColumnData::F64(v) => Some(*v),
+            ColumnData::Numeric(v) => Some(v.map(f64::from)),
+            _ => None,
+        }
+    }
+}
+
+impl FromColumn<'_> for bool {
+    const SQL_TYPE: &'static str = "BIT";
+
+    fn from_column(data: &ColumnData<'static>) -> Option<Option<Self>> {
+        match data {
+            ColumnData::Bit(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> FromColumn<'a> for &'a str {
+    const SQL_TYPE: &'static str = "VARCHAR";
+
+    fn from_column(data: &'a ColumnData<'static>) -> Option<Option<Self>> {
+        match data {
+            ColumnData::String(v) => Some(v.as_deref()),
+            _ => None,
+        }
+    }
+}
+
+impl FromColumn<'_> for String {
+    const SQL_TYPE: &'static str = "VARCHAR";
+
+    fn from_column(data: &ColumnData<'static>) -> Option<Option<Self>> {
+        <&str>::from_column(data).map(|v| v.map(str::to_string))
+    }
+}
+
+impl FromColumn<'_> for NaiveDateTime {
+    const SQL_TYPE: &'static str = "DATETIME";
+
+    fn from_column(data: &ColumnData<'static>) -> Option<Option<Self>> {
+        NaiveDateTime::from_sql(data).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_column() {
+        assert_eq!(i32::from_column(&ColumnData::I16(Some(7))), Some(Some(7)));
+        assert_eq!(i32::from_column(&ColumnData::F64(Some(7.0))), None);
+        assert_eq!(f64::from_column(&ColumnData::F64(None)), Some(None));
+        assert_eq!(
+            <Option<f64>>::from_column(&ColumnData::F64(None)),
+            Some(None)
+        );
+        assert_eq!(<Option<f64>>::from_null(), Some(None));
+        assert_eq!(f64::from_null(), None);
+        assert_eq!(
+            String::from_column(&ColumnData::String(Some("B1".into()))),
+            Some(Some("B1".to_string()))
+        );
+        assert_eq!(sql_type(&ColumnData::I64(Some(1))), "BIGINT");
+    }
+
+    #[test]
+    fn test_error_names_column_and_types() {
+        let err = RowError::TypeMismatch {
+            column: "StandardQty".to_string(),
+            expected: f64::SQL_TYPE,
+            actual: "INT",
+        };
+        assert_eq!(
+            err.to_string(),
+            "Column StandardQty has SQL type INT, expected FLOAT or DECIMAL"
+        );
+    }
+}
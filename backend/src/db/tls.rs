@@ -0,0 +1,259 @@
+//! Encryption of SQL Server Connections
+//!
+//! Connections are encrypted and the server certificate is checked against
+//! the system trust store, plus `DB_CA_CERT` when set, and against the host
+//! name. Skipping the check with `DB_TRUST_SERVER_CERTIFICATE` must be asked
+//! for explicitly and is logged as a warning.
+//!
+//! | Variable | Description | Default |
+//! |----------|-------------|---------|
+//! | `DB_ENCRYPT` | `required`, `on` (if the server supports it) or `off` (login only) | `required` |
+//! | `DB_CA_CERT` | CA certificate for the server certificate (`.pem`, `.crt` or `.der`) | (system store) |
+//! | `DB_TLS_SERVER_NAME` | Name expected on the certificate when `DB_SERVER` is an IP or alias | `DB_SERVER` |
+//! | `DB_TRUST_SERVER_CERTIFICATE` | `true` accepts any certificate | `false` |
+//!
+//! Each can be overridden per plant with the `PLANT_<CODE>_` prefix.
+
+use anyhow::{bail, Result};
+use log::warn;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tiberius::{Config, EncryptionLevel};
+
+use crate::db::mssql::prefixed_var;
+
+/// How much of the connection is encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbEncryption {
+    /// Only the login packet
+    Off,
+    /// Everything if the server supports encryption
+    On,
+    /// Everything; fail if the server does not support encryption
+    Required,
+}
+
+impl FromStr for DbEncryption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" | "false" | "no" => Ok(DbEncryption::Off),
+            "on" | "true" | "yes" => Ok(DbEncryption::On),
+            "required" | "strict" => Ok(DbEncryption::Required),
+            other => bail!("Unknown database encryption mode: {}", other),
+        }
+    }
+}
+
+impl fmt::Display for DbEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DbEncryption::Off => "off",
+            DbEncryption::On => "on",
+            DbEncryption::Required => "required",
+        })
+    }
+}
+
+impl From<DbEncryption> for EncryptionLevel {
+    fn from(mode: DbEncryption) -> Self {
+        match mode {
+            DbEncryption::Off => EncryptionLevel::Off,
+            DbEncryption::On => EncryptionLevel::On,
+            DbEncryption::Required => EncryptionLevel::Required,
+        }
+    }
+}
+
+/// Encryption and certificate checks for one database
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbTls {
+    pub encryption: DbEncryption,
+
+    /// Extra CA for the server certificate, in addition to the system store
+    pub ca_cert: Option<PathBuf>,
+
+    /// Name checked against the certificate, `None` for the server address
+    pub server_name: Option<String>,
+
+    /// Accept any certificate without checking it
+    pub trust_server_certificate: bool,
+}
+
+impl Default for DbTls {
+    fn default() -> Self {
+        Self {
+            encryption: DbEncryption::Required,
+            ca_cert: None,
+            server_name: None,
+            trust_server_certificate: false,
+        }
+    }
+}
+
+impl DbTls {
+    /// Environment variable names
+    const ENV_ENCRYPT: &'static str = "DB_ENCRYPT";
+    const ENV_CA_CERT: &'static str = "DB_CA_CERT";
+    const ENV_SERVER_NAME: &'static str = "DB_TLS_SERVER_NAME";
+    const ENV_TRUST_SERVER_CERTIFICATE: &'static str = "DB_TRUST_SERVER_CERTIFICATE";
+
+    /// Load the settings from `{prefix}DB_*`, falling back to `DB_*`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for an unknown mode, a CA file that cannot be read or
+    /// has an unsupported extension, or a CA combined with trusting any
+    /// certificate.
+    pub fn from_env(prefix: &str) -> Result<Self> {
+        let var = |name: &str| {
+            prefixed_var(prefix, name)
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+
+        let encryption = match var(Self::ENV_ENCRYPT) {
+            Some(mode) => mode.parse()?,
+            None => DbEncryption::Required,
+        };
+        let trust_server_certificate = match var(Self::ENV_TRUST_SERVER_CERTIFICATE) {
+            Some(value) => parse_flag(&value).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{}{} must be true or false, got: {}",
+                    prefix,
+                    Self::ENV_TRUST_SERVER_CERTIFICATE,
+                    value
+                )
+            })?,
+            None => false,
+        };
+
+        let tls = Self {
+            encryption,
+            ca_cert: var(Self::ENV_CA_CERT).map(PathBuf::from),
+            server_name: var(Self::ENV_SERVER_NAME),
+            trust_server_certificate,
+        };
+        tls.validate()?;
+        Ok(tls)
+    }
+
+    /// Check the settings before the first connection needs them
+    pub fn validate(&self) -> Result<()> {
+        if let Some(path) = &self.ca_cert {
+            if self.trust_server_certificate {
+                bail!(
+                    "{} and {} cannot be used together",
+                    Self::ENV_CA_CERT,
+                    Self::ENV_TRUST_SERVER_CERTIFICATE
+                );
+            }
+            let extension = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_ascii_lowercase());
+            if !matches!(extension.as_deref(), Some("pem" | "crt" | "der")) {
+                bail!(
+                    "{} must be a .pem, .crt or .der file, got: {}",
+                    Self::ENV_CA_CERT,
+                    path.display()
+                );
+            }
+            if let Err(e) = std::fs::metadata(path) {
+                bail!(
+                    "Cannot read {} {}: {}",
+                    Self::ENV_CA_CERT,
+                    path.display(),
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the settings to a connection config for `server`
+    pub fn apply(&self, config: &mut Config, server: &str) {
+        config.encryption(self.encryption.into());
+        if let Some(name) = &self.server_name {
+            // The TCP connection still goes to `server`; this only changes
+            // the name the certificate must carry
+            config.host(name);
+        }
+        if self.trust_server_certificate {
+            warn!(
+                "{} is set: the certificate of database server {} is not checked",
+                Self::ENV_TRUST_SERVER_CERTIFICATE,
+                server
+            );
+            config.trust_cert();
+        } else if let Some(path) = &self.ca_cert {
+            config.trust_cert_ca(path.display());
+        }
+    }
+
+    /// How the server certificate is checked, for the startup log
+    pub fn verification(&self) -> String {
+        if self.trust_server_certificate {
+            return "certificate not verified".to_string();
+        }
+        let store = match &self.ca_cert {
+            Some(path) => format!("system store and {}", path.display()),
+            None => "system store".to_string(),
+        };
+        match &self.server_name {
+            Some(name) => format!("certificate verified against {} for {}", store, name),
+            None => format!("certificate verified against {}", store),
+        }
+    }
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" => Some(true),
+        "false" | "0" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modes_and_validation() {
+        assert_eq!(
+            "Required".parse::<DbEncryption>().unwrap(),
+            DbEncryption::Required
+        );
+        assert_eq!(" on ".parse::<DbEncryption>().unwrap(), DbEncryption::On);
+        assert_eq!("off".parse::<DbEncryption>().unwrap(), DbEncryption::Off);
+        assert!("maybe".parse::<DbEncryption>().is_err());
+
+        assert!(DbTls::default().validate().is_ok());
+        assert_eq!(
+            DbTls::default().verification(),
+            "certificate verified against system store"
+        );
+
+        let ca_and_trust = DbTls {
+            ca_cert: Some(PathBuf::from("Cargo.toml")),
+            trust_server_certificate: true,
+            ..DbTls::default()
+        };
+        assert!(ca_and_trust.validate().is_err());
+
+        let wrong_extension = DbTls {
+            ca_cert: Some(PathBuf::from("Cargo.toml")),
+            ..DbTls::default()
+        };
+        assert!(wrong_extension.validate().is_err());
+
+        let missing = DbTls {
+            ca_cert: Some(PathBuf::from("does-not-exist.pem")),
+            ..DbTls::default()
+        };
+        assert!(missing.validate().is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Digest Mail
+//!
+//! Sends the digest over SMTP with the HTML as body and the CSV files as
+//! attachments.
+
+use anyhow::{bail, Context, Result};
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::db::mssql::prefixed_var;
+
+const ENV_SMTP_HOST: &str = "DIGEST_SMTP_HOST";
+const ENV_SMTP_PORT: &str = "DIGEST_SMTP_PORT";
+const ENV_SMTP_TLS: &str = "DIGEST_SMTP_TLS";
+const ENV_SMTP_USERNAME: &str = "DIGEST_SMTP_USERNAME";
+const ENV_SMTP_PASSWORD: &str = "DIGEST_SMTP_PASSWORD";
+const ENV_MAIL_FROM: &str = "DIGEST_MAIL_FROM";
+const ENV_MAIL_TO: &str = "DIGEST_MAIL_TO";
+
+/// Time allowed for connecting and each SMTP command
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the SMTP connection is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plain connection, e.g. a relay on localhost
+    None,
+    /// Upgrade with STARTTLS, which the server must offer
+    StartTls,
+    /// TLS from the start (SMTPS)
+    Tls,
+}
+
+impl SmtpTls {
+    fn default_port(self) -> u16 {
+        match self {
+            SmtpTls::None => 25,
+            SmtpTls::StartTls => 587,
+            SmtpTls::Tls => 465,
+        }
+    }
+}
+
+impl FromStr for SmtpTls {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(SmtpTls::None),
+            "starttls" => Ok(SmtpTls::StartTls),
+            "tls" => Ok(SmtpTls::Tls),
+            other => bail!(
+                "{} must be none, starttls or tls, got: {}",
+                ENV_SMTP_TLS,
+                other
+            ),
+        }
+    }
+}
+
+impl fmt::Display for SmtpTls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SmtpTls::None => "none",
+            SmtpTls::StartTls => "starttls",
+            SmtpTls::Tls => "tls",
+        })
+    }
+}
+
+/// SMTP server and addresses for mailing the digest
+#[derive(Debug, Clone)]
+pub struct MailConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub credentials: Option<(String, String)>,
+    pub from: Mailbox,
+    pub to: Vec<Mailbox>,
+}
+
+/// A file attached to the mail
+pub struct MailAttachment {
+    pub filename: String,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+fn var(prefix: &str, name: &str) -> Option<String> {
+    prefixed_var(prefix, name)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+impl MailConfig {
+    /// Settings from `{prefix}DIGEST_*`, then `DIGEST_*`; `None` without a host
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when a host is set but the sender or recipients are
+    /// missing or invalid, or the port or TLS mode cannot be parsed.
+    pub fn from_env(prefix: &str) -> Result<Option<Self>> {
+        let Some(host) = var(prefix, ENV_SMTP_HOST) else {
+            return Ok(None);
+        };
+
+        let tls = var(prefix, ENV_SMTP_TLS)
+            .map(|v| v.parse())
+            .transpose()?
+            .unwrap_or(SmtpTls::StartTls);
+        let port = match var(prefix, ENV_SMTP_PORT) {
+            Some(port) => port.parse().with_context(|| {
+                format!("{} must be a port number, got: {}", ENV_SMTP_PORT, port)
+            })?,
+            None => tls.default_port(),
+        };
+        let credentials = match (
+            var(prefix, ENV_SMTP_USERNAME),
+            prefixed_var(prefix, ENV_SMTP_PASSWORD).ok(),
+        ) {
+            (Some(username), password) => Some((username, password.unwrap_or_default())),
+            (None, _) => None,
+        };
+
+        let from = var(prefix, ENV_MAIL_FROM)
+            .with_context(|| format!("{} is required with {}", ENV_MAIL_FROM, ENV_SMTP_HOST))?;
+        let from = from
+            .parse()
+            .with_context(|| format!("Invalid {}: {}", ENV_MAIL_FROM, from))?;
+        let to = var(prefix, ENV_MAIL_TO)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(|a| {
+                a.parse::<Mailbox>()
+                    .with_context(|| format!("Invalid address in {}: {}", ENV_MAIL_TO, a))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if to.is_empty() {
+            bail!("{} is required with {}", ENV_MAIL_TO, ENV_SMTP_HOST);
+        }
+
+        Ok(Some(Self {
+            host,
+            port,
+            tls,
+            credentials,
+            from,
+            to,
+        }))
+    }
+
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+        let builder = match self.tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)?,
+        }
+        .port(self.port)
+        .timeout(Some(SMTP_TIMEOUT));
+
+        Ok(match &self.credentials {
+            Some((username, password)) => builder
+                .credentials(Credentials::new(username.clone(), password.clone()))
+                .build(),
+            None => builder.build(),
+        })
+    }
+
+    /// Mail `html` as the body with the attachments to every recipient
+    pub async fn send(
+        &self,
+        subject: &str,
+        html: String,
+        attachments: Vec<MailAttachment>,
+    ) -> Result<()> {
+        let mut body = MultiPart::mixed().singlepart(SinglePart::html(html));
+        for attachment in attachments {
+            body = body.singlepart(Attachment::new(attachment.filename).body(
+                attachment.body,
+                ContentType::parse(attachment.content_type)?,
+            ));
+        }
+
+        let mut message = Message::builder().from(self.from.clone()).subject(subject);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let message = message.multipart(body)?;
+
+        self.transport()?
+            .send(message)
+            .await
+            .with_context(|| format!("Failed to send digest via {}:{}", self.host, self.port))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Accept one SMTP session and return the commands and message it received
+    async fn smtp_stand_in(listener: TcpListener) -> (Vec<String>, String) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+
+        let (mut commands, mut data) = (vec![], String::new());
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let command = line.trim_end().to_string();
+            let verb = command.split(' ').next().unwrap_or_default().to_uppercase();
+            commands.push(command);
+            let reply: &[u8] = match verb.as_str() {
+                "EHLO" => b"250-localhost\r\n250 8BITMIME\r\n",
+                "DATA" => {
+                    writer
+                        .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                        .await
+                        .unwrap();
+                    loop {
+                        line.clear();
+                        reader.read_line(&mut line).await.unwrap();
+                        if line == ".\r\n" {
+                            break;
+                        }
+                        data.push_str(&line);
+                    }
+                    b"250 OK queued\r\n"
+                }
+                "QUIT" => {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                }
+                _ => b"250 OK\r\n",
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+        (commands, data)
+    }
+
+    #[tokio::test]
+    async fn test_send_to_local_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(smtp_stand_in(listener));
+
+        let config = MailConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmtpTls::None,
+            credentials: None,
+            from: "rm-remover@example.com".parse().unwrap(),
+            to: vec![
+                "manager@example.com".parse().unwrap(),
+                "Night Shift <night@example.com>".parse().unwrap(),
+            ],
+        };
+        config
+            .send(
+                "Partial pick removals at TFC1 on 2026-01-02",
+                "<h1>Digest</h1>".to_string(),
+                vec![MailAttachment {
+                    filename: "digest-TFC1-2026-01-02.csv".to_string(),
+                    content_type: "text/csv",
+                    body: "Section,Key\n".to_string(),
+                }],
+            )
+            .await
+            .unwrap();
+
+        let (commands, data) = server.await.unwrap();
+        assert!(commands.contains(&"MAIL FROM:<rm-remover@example.com>".to_string()));
+        assert!(commands.contains(&"RCPT TO:<manager@example.com>".to_string()));
+        assert!(commands.contains(&"RCPT TO:<night@example.com>".to_string()));
+        assert!(data.contains("Subject: Partial pick removals at TFC1 on 2026-01-02"));
+        assert!(data.contains("<h1>Digest</h1>"));
+        assert!(data.contains("filename=\"digest-TFC1-2026-01-02.csv\""));
+    }
+
+    #[test]
+    fn test_tls_modes() {
+        assert_eq!("STARTTLS".parse::<SmtpTls>().unwrap(), SmtpTls::StartTls);
+        assert_eq!("none".parse::<SmtpTls>().unwrap().default_port(), 25);
+        assert_eq!(SmtpTls::Tls.default_port(), 465);
+        assert!("ssl".parse::<SmtpTls>().is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! Daily Removal Digest
+//!
+//! Summarizes the previous plant-local day of removals per run, per user and
+//! per reason (see [`report`]). A scheduler in the server runs it on a cron
+//! expression evaluated in the plant's time zone, writes the digest to a
+//! directory as HTML, a summary CSV and a CSV of every audited line, and
+//! optionally mails it over SMTP (see [`mail`]). The support CLI can produce
+//! a digest on demand with `rm-remover-cli digest`.
+//!
+//! Every variable can be set per plant as `PLANT_<CODE>_<VARIABLE>`.
+//!
+//! | Variable | Description | Default |
+//! |----------|-------------|---------|
+//! | `DIGEST_SCHEDULE` | Cron expression, e.g. `0 6 * * *`; unset disables the scheduler | - |
+//! | `DIGEST_OUTPUT_DIR` | Directory the digest files are written to | `digests` |
+//! | `DIGEST_SMTP_HOST` | SMTP server; unset disables mailing | - |
+//! | `DIGEST_SMTP_PORT` | SMTP port | `587`, `465` with `tls`, `25` with `none` |
+//! | `DIGEST_SMTP_TLS` | `starttls`, `tls` or `none` | `starttls` |
+//! | `DIGEST_SMTP_USERNAME` | SMTP login, if the server requires one | - |
+//! | `DIGEST_SMTP_PASSWORD` | SMTP password | - |
+//! | `DIGEST_MAIL_FROM` | Sender address, required with a host | - |
+//! | `DIGEST_MAIL_TO` | Comma-separated recipients, required with a host | - |
+
+pub mod mail;
+pub mod report;
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, TimeDelta, Utc};
+use cron::Schedule;
+use log::{error, info, warn};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::db::mssql::{prefixed_var, MssqlPool};
+use crate::plant::PlantClock;
+use crate::rm::history;
+
+use mail::{MailAttachment, MailConfig};
+use report::Digest;
+
+const ENV_SCHEDULE: &str = "DIGEST_SCHEDULE";
+const ENV_OUTPUT_DIR: &str = "DIGEST_OUTPUT_DIR";
+
+const DEFAULT_OUTPUT_DIR: &str = "digests";
+
+/// When and where digests are produced for a plant
+#[derive(Debug, Clone)]
+pub struct DigestConfig {
+    /// `None` when the server should not schedule digests
+    pub schedule: Option<Schedule>,
+    pub output_dir: PathBuf,
+    /// `None` when digests are only written to disk
+    pub mail: Option<MailConfig>,
+}
+
+/// Parse a cron expression; five fields are taken as minute precision
+pub fn parse_schedule(expression: &str) -> Result<Schedule> {
+    let expression = expression.trim();
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+    Schedule::from_str(&expression)
+        .with_context(|| format!("Invalid {}: {}", ENV_SCHEDULE, expression))
+}
+
+impl DigestConfig {
+    /// Settings from `{prefix}DIGEST_*`, then `DIGEST_*`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when the schedule or the mail settings are invalid.
+    pub fn from_env(prefix: &str) -> Result<Self> {
+        let schedule = prefixed_var(prefix, ENV_SCHEDULE)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| parse_schedule(&v))
+            .transpose()?;
+        let output_dir = prefixed_var(prefix, ENV_OUTPUT_DIR)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_OUTPUT_DIR.to_string());
+
+        Ok(Self {
+            schedule,
+            output_dir: PathBuf::from(output_dir.trim()),
+            mail: MailConfig::from_env(prefix)?,
+        })
+    }
+}
+
+/// Files written for one digest
+#[derive(Debug, Clone)]
+pub struct DigestFiles {
+    pub html: PathBuf,
+    pub summary_csv: PathBuf,
+    pub removals_csv: PathBuf,
+}
+
+/// The plant-local day before the current one
+pub fn previous_day(clock: &PlantClock) -> NaiveDate {
+    let today = Utc::now().with_timezone(&clock.timezone()).date_naive();
+    today - TimeDelta::days(1)
+}
+
+fn write(path: &Path, contents: &str) -> Result<()> {
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Build the digest of `day`, write it to the output directory and, with
+/// `send_mail`, mail it when SMTP is configured
+pub async fn generate(
+    pool: &MssqlPool,
+    plant: &str,
+    clock: &PlantClock,
+    config: &DigestConfig,
+    day: NaiveDate,
+    send_mail: bool,
+) -> Result<(Digest, DigestFiles)> {
+    let entries = report::load_entries(pool, clock, day).await?;
+    let removals_csv = history::to_csv(&entries)?;
+    let digest = report::summarize(plant, day, entries);
+    let html = digest.to_html();
+    let summary_csv = digest.to_csv()?;
+
+    std::fs::create_dir_all(&config.output_dir)
+        .with_context(|| format!("Failed to create {}", config.output_dir.display()))?;
+    let stem = format!("digest-{}-{}", plant, day);
+    let files = DigestFiles {
+        html: config.output_dir.join(format!("{}.html", stem)),
+        summary_csv: config.output_dir.join(format!("{}.csv", stem)),
+        removals_csv: config.output_dir.join(format!("{}-removals.csv", stem)),
+    };
+    write(&files.html, &html)?;
+    write(&files.summary_csv, &summary_csv)?;
+    write(&files.removals_csv, &removals_csv)?;
+
+    if let Some(mail) = config.mail.as_ref().filter(|_| send_mail) {
+        mail.send(
+            &digest.title(),
+            html,
+            vec![
+                MailAttachment {
+                    filename: format!("{}.csv", stem),
+                    content_type: "text/csv",
+                    body: summary_csv,
+                },
+                MailAttachment {
+                    filename: format!("{}-removals.csv", stem),
+                    content_type: "text/csv",
+                    body: removals_csv,
+                },
+            ],
+        )
+        .await?;
+    }
+
+    Ok((digest, files))
+}
+
+/// Produce the previous day's digest on every tick of the plant's schedule;
+/// does nothing without a schedule
+pub fn spawn_scheduler(pool: MssqlPool, plant: String, clock: PlantClock, config: DigestConfig) {
+    let Some(schedule) = config.schedule.clone() else {
+        return;
+    };
+
+    info!(
+        "Digest for plant {} scheduled at '{}' ({}), writing to {}{}",
+        plant,
+        schedule,
+        clock.timezone(),
+        config.output_dir.display(),
+        if config.mail.is_some() {
+            " and mailing"
+        } else {
+            ""
+        }
+    );
+
+    tokio::spawn(async move {
+        loop {
+            let now = Utc::now().with_timezone(&clock.timezone());
+            let Some(next) = schedule.after(&now).next() else {
+                warn!("Digest schedule for plant {} has no further runs", plant);
+                return;
+            };
+            let wait = (next.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            let day = previous_day(&clock);
+            match generate(&pool, &plant, &clock, &config, day, true).await {
+                Ok((digest, files)) => info!(
+                    "Digest for plant {} on {}: {} lines removed, written to {}",
+                    plant,
+                    day,
+                    digest.total.lines,
+                    files.html.display()
+                ),
+                Err(e) => error!("Digest for plant {} on {} failed: {:#}", plant, day, e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Timelike};
+
+    #[test]
+    fn test_parse_schedule() {
+        let schedule = parse_schedule("30 6 * * *").unwrap();
+        let tz = chrono_tz::Asia::Bangkok;
+        let after = tz.with_ymd_and_hms(2026, 1, 2, 7, 0, 0).unwrap();
+
+        let next = schedule.after(&after).next().unwrap();
+        assert_eq!(
+            next.date_naive(),
+            NaiveDate::from_ymd_opt(2026, 1, 3).unwrap()
+        );
+        assert_eq!((next.hour(), next.minute(), next.second()), (6, 30, 0));
+
+        // Seconds may be given explicitly
+        assert!(parse_schedule("0 0 6 * * Mon-Fri").is_ok());
+        assert!(parse_schedule("every morning").is_err());
+    }
+}
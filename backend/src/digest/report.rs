@@ -0,0 +1,354 @@
+//! Digest Report
+//!
+//! Summarizes one plant-local day of `cust_PartialPickRemovalAudit` per run,
+//! per user and per reason, and renders it as HTML and CSV. Restores made
+//! with the support CLI are counted on their own and left out of the groups.
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+
+use crate::db::mssql::MssqlPool;
+use crate::models::qty::Qty;
+use crate::models::rm::RemovalAuditEntry;
+use crate::plant::PlantClock;
+use crate::rm::history::{map_audit_entry, AUDIT_ENTRY_COLUMNS};
+use crate::rm::restore::RESTORE_REASON_CODE;
+
+/// Removals sharing a run, user or reason
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestGroup {
+    pub key: String,
+    pub description: String,
+    pub lines: usize,
+    /// Quantity taken off ToPickedPartialQty
+    pub qty: Qty,
+}
+
+/// Summary of one day of removals at a plant
+#[derive(Debug, Clone)]
+pub struct Digest {
+    pub plant: String,
+    pub day: NaiveDate,
+    pub total: DigestGroup,
+    pub runs: usize,
+    pub restored: DigestGroup,
+    pub by_run: Vec<DigestGroup>,
+    pub by_user: Vec<DigestGroup>,
+    pub by_reason: Vec<DigestGroup>,
+    /// Every audit entry of the day, oldest first
+    pub entries: Vec<RemovalAuditEntry>,
+}
+
+/// Audit entries recorded on `day` in the plant's time zone, oldest first
+pub async fn load_entries(
+    pool: &MssqlPool,
+    clock: &PlantClock,
+    day: NaiveDate,
+) -> Result<Vec<RemovalAuditEntry>> {
+    let (start, end) = clock.day_range(day);
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM cust_PartialPickRemovalAudit a
+        LEFT JOIN cust_PartialPickReasonCode r ON r.ReasonCode = a.ReasonCode
+        WHERE a.RemovedDate >= @P1 AND a.RemovedDate < @P2
+        ORDER BY a.RemovedDate, a.AuditId
+        "#,
+        AUDIT_ENTRY_COLUMNS
+    );
+
+    pool.execute_read_with_params(
+        &sql,
+        |query| {
+            query.bind(start);
+            query.bind(end);
+        },
+        |row| map_audit_entry(row, clock),
+    )
+    .await
+}
+
+fn group(key: &str, description: &str) -> DigestGroup {
+    DigestGroup {
+        key: key.to_string(),
+        description: description.to_string(),
+        lines: 0,
+        qty: Qty::ZERO,
+    }
+}
+
+fn add(group: &mut DigestGroup, qty: Qty) {
+    group.lines += 1;
+    group.qty = group.qty + qty;
+}
+
+/// Group the entries of a day
+pub fn summarize(plant: &str, day: NaiveDate, entries: Vec<RemovalAuditEntry>) -> Digest {
+    let mut total = group("", "Removed");
+    let mut restored = group("", "Restored");
+    let mut by_run: BTreeMap<i32, DigestGroup> = BTreeMap::new();
+    let mut by_user: BTreeMap<String, DigestGroup> = BTreeMap::new();
+    let mut by_reason: BTreeMap<String, DigestGroup> = BTreeMap::new();
+
+    for entry in &entries {
+        if entry.reason_code == RESTORE_REASON_CODE {
+            add(&mut restored, entry.qty_after - entry.qty_before);
+            continue;
+        }
+
+        let qty = entry.qty_before - entry.qty_after;
+        add(&mut total, qty);
+        add(
+            by_run
+                .entry(entry.run_no)
+                .or_insert_with(|| group(&entry.run_no.to_string(), "")),
+            qty,
+        );
+        add(
+            by_user
+                .entry(entry.removed_by.to_lowercase())
+                .or_insert_with(|| group(&entry.removed_by, "")),
+            qty,
+        );
+        add(
+            by_reason
+                .entry(entry.reason_code.clone())
+                .or_insert_with(|| group(&entry.reason_code, &entry.reason_description)),
+            qty,
+        );
+    }
+
+    Digest {
+        plant: plant.to_string(),
+        day,
+        runs: by_run.len(),
+        total,
+        restored,
+        by_run: by_run.into_values().collect(),
+        by_user: by_user.into_values().collect(),
+        by_reason: by_reason.into_values().collect(),
+        entries,
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_table(out: &mut String, title: &str, headers: &[&str], rows: Vec<Vec<String>>) {
+    out.push_str(&format!("<h2>{}</h2>\n", escape(title)));
+    if rows.is_empty() {
+        out.push_str("<p>None</p>\n");
+        return;
+    }
+    out.push_str("<table>\n<tr>");
+    for header in headers {
+        out.push_str(&format!("<th>{}</th>", escape(header)));
+    }
+    out.push_str("</tr>\n");
+    for row in rows {
+        out.push_str("<tr>");
+        for cell in row {
+            out.push_str(&format!("<td>{}</td>", escape(&cell)));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+}
+
+fn group_rows(groups: &[DigestGroup], with_description: bool) -> Vec<Vec<String>> {
+    groups
+        .iter()
+        .map(|g| {
+            let mut row = vec![g.key.clone()];
+            if with_description {
+                row.push(g.description.clone());
+            }
+            row.extend([g.lines.to_string(), g.qty.to_string()]);
+            row
+        })
+        .collect()
+}
+
+impl Digest {
+    pub fn title(&self) -> String {
+        format!("Partial pick removals at {} on {}", self.plant, self.day)
+    }
+
+    /// Standalone HTML page, also used as the mail body
+    pub fn to_html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+             <style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+             th,td{{border:1px solid #ccc;padding:4px 8px;text-align:left}}</style>\n\
+             </head>\n<body>\n<h1>{title}</h1>\n",
+            title = escape(&self.title())
+        );
+        out.push_str(&format!(
+            "<p>{} lines removed from {} runs, total quantity {}.",
+            self.total.lines, self.runs, self.total.qty
+        ));
+        if self.restored.lines > 0 {
+            out.push_str(&format!(
+                " {} lines restored, quantity {}.",
+                self.restored.lines, self.restored.qty
+            ));
+        }
+        out.push_str("</p>\n");
+
+        html_table(
+            &mut out,
+            "Per run",
+            &["Run", "Lines", "Quantity"],
+            group_rows(&self.by_run, false),
+        );
+        html_table(
+            &mut out,
+            "Per user",
+            &["User", "Lines", "Quantity"],
+            group_rows(&self.by_user, false),
+        );
+        html_table(
+            &mut out,
+            "Per reason",
+            &["Reason", "Description", "Lines", "Quantity"],
+            group_rows(&self.by_reason, true),
+        );
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// Summary as `Section,Key,Description,Lines,Qty` rows
+    pub fn to_csv(&self) -> Result<String> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(["Section", "Key", "Description", "Lines", "Qty"])?;
+        let sections = [
+            ("total", std::slice::from_ref(&self.total)),
+            ("restored", std::slice::from_ref(&self.restored)),
+            ("run", &self.by_run[..]),
+            ("user", &self.by_user[..]),
+            ("reason", &self.by_reason[..]),
+        ];
+        for (section, groups) in sections {
+            for group in groups {
+                writer.write_record([
+                    section,
+                    &group.key,
+                    &group.description,
+                    &group.lines.to_string(),
+                    &group.qty.to_string(),
+                ])?;
+            }
+        }
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        run_no: i32,
+        user: &str,
+        reason: &str,
+        before: &str,
+        after: &str,
+    ) -> RemovalAuditEntry {
+        RemovalAuditEntry {
+            audit_id: 1,
+            run_no,
+            row_num: 1,
+            line_id: 1,
+            batch_no: "B1".to_string(),
+            item_key: "SUGAR".to_string(),
+            qty_before: before.parse().unwrap(),
+            qty_after: after.parse().unwrap(),
+            reason_code: reason.to_string(),
+            reason_description: format!("{} <desc>", reason),
+            comment: None,
+            removed_by: user.to_string(),
+            approved_by: None,
+            approval_request_id: None,
+            removed_date: "2026-01-02T10:04:05+07:00".to_string(),
+        }
+    }
+
+    fn digest() -> Digest {
+        summarize(
+            "TFC1",
+            NaiveDate::from_ymd_opt(2026, 1, 2).unwrap(),
+            vec![
+                entry(1001, "alice", "QA_HOLD", "12.5", "0"),
+                entry(1001, "Alice", "OTHER", "10", "7.5"),
+                entry(1002, "bob", "QA_HOLD", "0.3", "0.1"),
+                entry(1001, "bob", RESTORE_REASON_CODE, "0", "12.5"),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_summarize_groups() {
+        let digest = digest();
+        let qty = |s: &str| s.parse::<Qty>().unwrap();
+
+        assert_eq!((digest.total.lines, digest.total.qty), (3, qty("15.2")));
+        assert_eq!(digest.runs, 2);
+        assert_eq!(
+            (digest.restored.lines, digest.restored.qty),
+            (1, qty("12.5"))
+        );
+
+        let summary = |groups: &[DigestGroup]| {
+            groups
+                .iter()
+                .map(|g| (g.key.clone(), g.lines, g.qty))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summary(&digest.by_run),
+            vec![
+                ("1001".to_string(), 2, qty("15")),
+                ("1002".to_string(), 1, qty("0.2"))
+            ]
+        );
+        // Users are grouped regardless of case
+        assert_eq!(
+            summary(&digest.by_user),
+            vec![
+                ("alice".to_string(), 2, qty("15")),
+                ("bob".to_string(), 1, qty("0.2"))
+            ]
+        );
+        assert_eq!(
+            summary(&digest.by_reason),
+            vec![
+                ("OTHER".to_string(), 1, qty("2.5")),
+                ("QA_HOLD".to_string(), 2, qty("12.7"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let digest = digest();
+
+        let html = digest.to_html();
+        assert!(html.contains("<h1>Partial pick removals at TFC1 on 2026-01-02</h1>"));
+        assert!(html.contains("3 lines removed from 2 runs, total quantity 15.2."));
+        assert!(
+            html.contains("<td>QA_HOLD</td><td>QA_HOLD &lt;desc&gt;</td><td>2</td><td>12.7</td>")
+        );
+
+        let csv = digest.to_csv().unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "Section,Key,Description,Lines,Qty");
+        assert_eq!(lines[1], "total,,Removed,3,15.2");
+        assert_eq!(lines[2], "restored,,Restored,1,12.5");
+        assert!(lines.contains(&"reason,OTHER,OTHER <desc>,1,2.5"));
+    }
+}
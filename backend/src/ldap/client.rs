@@ -1,26 +1,65 @@
 //! LDAP Client
 //!
-//! Provides a high-level async LDAP client with connection pooling support
-//! and comprehensive error handling for Active Directory authentication.
+//! Provides a high-level async LDAP client for Active Directory
+//! authentication. A client is built once per plant and shared. Each login
+//! binds on its own connection, since a bind changes the identity of the
+//! whole connection. With a service account (`LDAP_BIND_DN`), user details
+//! are then read over a pooled connection bound as that account (see
+//! [`crate::ldap::pool`]); without one, on the login's connection.
 
 use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
 use log::{debug, error, info, warn};
+use std::fmt;
 use tokio::time::timeout;
 
 use crate::ldap::config::LdapConfig;
 use crate::ldap::error::{LdapError, LdapResult};
+use crate::ldap::pool::LdapPool;
 use crate::ldap::user::LdapUser;
 
 /// High-level LDAP client for authentication operations
-#[derive(Debug)]
 pub struct LdapClient {
     config: LdapConfig,
+    /// Service-bound connections for searches, with `LDAP_BIND_DN` set
+    pool: Option<LdapPool>,
+}
+
+impl fmt::Debug for LdapClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LdapClient")
+            .field("config", &self.config)
+            .field("pooled", &self.pool.is_some())
+            .finish()
+    }
 }
 
 impl LdapClient {
     /// Create a new LDAP client with the given configuration
     pub fn new(config: LdapConfig) -> Self {
-        Self { config }
+        let pool = config
+            .bind_dn
+            .is_some()
+            .then(|| LdapPool::new(config.clone()));
+        Self { config, pool }
+    }
+
+    pub fn config(&self) -> &LdapConfig {
+        &self.config
+    }
+
+    /// Close pooled connections that have been idle too long
+    pub fn reap_idle(&self) -> usize {
+        self.pool.as_ref().map_or(0, LdapPool::reap_idle)
+    }
+
+    /// Connect, and bind the service account when one is configured
+    pub async fn check(&self) -> LdapResult<()> {
+        let mut ldap = self.connect().await?;
+        let _ = ldap.unbind().await;
+        if let Some(pool) = &self.pool {
+            pool.get().await?;
+        }
+        Ok(())
     }
 
     /// Authenticate a user against LDAP/Active Directory
@@ -50,7 +89,7 @@ impl LdapClient {
             clean_username, upn
         );
 
-        // Connect to LDAP server with timeout
+        // Connect to LDAP server with timeout; every login binds on its own connection
         let mut ldap = self.connect().await.map_err(|e| {
             error!("Failed to connect to LDAP server {}: {}", self.config.url, e);
             e
@@ -60,17 +99,25 @@ impl LdapClient {
 
         // Attempt bind with user credentials
         // This is the actual authentication step - if bind succeeds, credentials are valid
-        let bind_result = self.bind_with_timeout(&mut ldap, &upn, password).await;
+        let bind_result = bind(&self.config, &mut ldap, &upn, password).await;
 
         match bind_result {
             Ok(_) => {
                 info!("LDAP bind successful for user: {}", clean_username);
 
                 // Bind succeeded, now retrieve user details
-                let user = self.search_user_details(&mut ldap, &clean_username).await?;
-
-                // Unbind (clean disconnect)
-                let _ = ldap.unbind().await;
+                let user = match &self.pool {
+                    Some(pool) => {
+                        let _ = ldap.unbind().await;
+                        self.search_pooled(pool, &clean_username).await
+                    }
+                    None => {
+                        let user =
+                            search_user_details(&self.config, &mut ldap, &clean_username).await;
+                        let _ = ldap.unbind().await;
+                        user
+                    }
+                }?;
 
                 Ok(user)
             }
@@ -96,126 +143,146 @@ impl LdapClient {
     }
 
     /// Connect to the LDAP server
-    /// Uses LdapConnSettings to configure TLS certificate verification
     pub async fn connect(&self) -> LdapResult<Ldap> {
-        debug!(
-            "Connecting to LDAP server: {} (verify_certs: {})",
-            self.config.url, self.config.verify_certs
-        );
-
-        // Build connection settings with TLS configuration
-        let settings = if !self.config.verify_certs {
-            info!("TLS certificate verification DISABLED (internal network)");
-            let tls_connector = native_tls::TlsConnector::builder()
-                .danger_accept_invalid_certs(true)
-                .danger_accept_invalid_hostnames(true)
-                .build()
-                .map_err(|e| LdapError::ConnectionError(format!("TLS connector error: {}", e)))?;
-            LdapConnSettings::new()
-                .set_connector(tls_connector)
-                .set_no_tls_verify(true)
-        } else {
-            LdapConnSettings::new()
-        };
-
-        let connect_future = LdapConnAsync::with_settings(settings, &self.config.url);
-
-        let (conn, ldap) = match timeout(self.config.timeout, connect_future).await {
-            Ok(Ok(result)) => result,
-            Ok(Err(e)) => {
-                return Err(LdapError::ConnectionError(format!(
-                    "Failed to establish LDAP connection: {}",
-                    e
-                )))
-            }
-            Err(_) => {
-                return Err(LdapError::TimeoutError(self.config.timeout));
-            }
-        };
-
-        // Spawn the connection handler
-        tokio::spawn(async move {
-            if let Err(e) = conn.drive().await {
-                error!("LDAP connection error: {}", e);
-            }
-        });
-
-        Ok(ldap)
+        open_connection(&self.config).await
     }
 
-    /// Bind to LDAP with credentials (this performs the authentication)
-    async fn bind_with_timeout(
-        &self,
-        ldap: &mut Ldap,
-        dn: &str,
-        password: &str,
-    ) -> LdapResult<()> {
-        debug!("Attempting LDAP bind for DN: {}", dn);
-
-        let bind_future = ldap.simple_bind(dn, password);
-
-        let result = match timeout(self.config.timeout, bind_future).await {
-            Ok(Ok(result)) => result,
-            Ok(Err(e)) => return Err(LdapError::BindError(e.to_string())),
-            Err(_) => return Err(LdapError::TimeoutError(self.config.timeout)),
-        };
-
-        match result.success() {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                let error_msg = format!("Bind failed: {:?}", e);
-                Err(LdapError::AuthError(error_msg))
+    /// Read user details over a pooled connection, retrying once on a fresh
+    /// connection when the pooled one turns out to be broken
+    async fn search_pooled(&self, pool: &LdapPool, username: &str) -> LdapResult<LdapUser> {
+        let mut conn = pool.get().await?;
+        match search_user_details(&self.config, conn.ldap(), username).await {
+            Err(e @ (LdapError::SearchError(_) | LdapError::TimeoutError(_))) => {
+                warn!("Pooled LDAP connection failed, reconnecting: {}", e);
+                conn.discard();
+                let mut conn = pool.get().await?;
+                search_user_details(&self.config, conn.ldap(), username).await
             }
+            result => result,
         }
     }
+}
 
-    /// Search for user details after successful authentication
-    async fn search_user_details(
-        &self,
-        ldap: &mut Ldap,
-        username: &str,
-    ) -> LdapResult<LdapUser> {
-        let filter = self.config.build_search_filter(username);
-
-        debug!(
-            "Searching for user details: {} with filter: {}",
-            username, filter
-        );
-
-        let search_future = ldap.search(
-            &self.config.base_dn,
-            Scope::Subtree,
-            &filter,
-            vec!["sAMAccountName", "displayName", "mail", "cn", "givenName", "sn"],
-        );
+/// Connect to the LDAP server
+/// Uses LdapConnSettings to configure TLS certificate verification
+pub(crate) async fn open_connection(config: &LdapConfig) -> LdapResult<Ldap> {
+    debug!(
+        "Connecting to LDAP server: {} (verify_certs: {})",
+        config.url, config.verify_certs
+    );
+
+    // Build connection settings with TLS configuration
+    let settings = if !config.verify_certs {
+        info!("TLS certificate verification DISABLED (internal network)");
+        let tls_connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build()
+            .map_err(|e| LdapError::ConnectionError(format!("TLS connector error: {}", e)))?;
+        LdapConnSettings::new()
+            .set_connector(tls_connector)
+            .set_no_tls_verify(true)
+    } else {
+        LdapConnSettings::new()
+    };
+
+    let connect_future = LdapConnAsync::with_settings(settings, &config.url);
+
+    let (conn, ldap) = match timeout(config.timeout, connect_future).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => {
+            return Err(LdapError::ConnectionError(format!(
+                "Failed to establish LDAP connection: {}",
+                e
+            )))
+        }
+        Err(_) => {
+            return Err(LdapError::TimeoutError(config.timeout));
+        }
+    };
 
-        let result = match timeout(self.config.timeout, search_future).await {
-            Ok(Ok(result)) => result,
-            Ok(Err(e)) => return Err(LdapError::SearchError(e.to_string())),
-            Err(_) => return Err(LdapError::TimeoutError(self.config.timeout)),
-        };
+    // Spawn the connection handler; it ends when every handle is dropped
+    tokio::spawn(async move {
+        if let Err(e) = conn.drive().await {
+            error!("LDAP connection error: {}", e);
+        }
+    });
 
-        let (entries, _) = result.success().map_err(|e| {
-            LdapError::SearchError(format!("Search failed: {:?}", e))
-        })?;
+    Ok(ldap)
+}
 
-        if entries.is_empty() {
-            warn!("User {} not found in LDAP after successful bind", username);
-            return Err(LdapError::UserNotFound);
+/// Bind to LDAP with credentials (this performs the authentication)
+pub(crate) async fn bind(
+    config: &LdapConfig,
+    ldap: &mut Ldap,
+    dn: &str,
+    password: &str,
+) -> LdapResult<()> {
+    debug!("Attempting LDAP bind for DN: {}", dn);
+
+    let bind_future = ldap.simple_bind(dn, password);
+
+    let result = match timeout(config.timeout, bind_future).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => return Err(LdapError::BindError(e.to_string())),
+        Err(_) => return Err(LdapError::TimeoutError(config.timeout)),
+    };
+
+    match result.success() {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let error_msg = format!("Bind failed: {:?}", e);
+            Err(LdapError::AuthError(error_msg))
         }
+    }
+}
 
-        // Parse the first entry
-        let entry = SearchEntry::construct(entries.into_iter().next().unwrap());
+/// Search for user details after successful authentication
+async fn search_user_details(
+    config: &LdapConfig,
+    ldap: &mut Ldap,
+    username: &str,
+) -> LdapResult<LdapUser> {
+    let filter = config.build_search_filter(username);
+
+    debug!(
+        "Searching for user details: {} with filter: {}",
+        username, filter
+    );
+
+    let search_future = ldap.search(
+        &config.base_dn,
+        Scope::Subtree,
+        &filter,
+        vec!["sAMAccountName", "displayName", "mail", "cn", "givenName", "sn"],
+    );
+
+    let result = match timeout(config.timeout, search_future).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => return Err(LdapError::SearchError(e.to_string())),
+        Err(_) => return Err(LdapError::TimeoutError(config.timeout)),
+    };
+
+    let (entries, _) = result.success().map_err(|e| {
+        LdapError::SearchError(format!("Search failed: {:?}", e))
+    })?;
+
+    if entries.is_empty() {
+        warn!("User {} not found in LDAP after successful bind", username);
+        return Err(LdapError::UserNotFound);
+    }
 
-        let user = LdapUser::from_search_entry(entry, username.to_string());
+    // Parse the first entry
+    let entry = SearchEntry::construct(entries.into_iter().next().unwrap());
 
-        info!(
-            "Successfully retrieved LDAP details for user: {} (display_name: {})",
-            username, user.display_name
-        );
+    let user = LdapUser::from_search_entry(entry, username.to_string());
 
-        Ok(user)
-    }
+    info!(
+        "Successfully retrieved LDAP details for user: {} (display_name: {})",
+        username, user.display_name
+    );
+
+    Ok(user)
 }
 
 #[cfg(test)]
@@ -228,7 +295,7 @@ mod tests {
     #[tokio::test]
     #[ignore = "Requires LDAP server"]
     async fn test_connect() {
-        let config = LdapConfig::from_env().unwrap();
+        let config = LdapConfig::from_env("").unwrap();
         let client = LdapClient::new(config);
 
         // This will fail if no LDAP server is available
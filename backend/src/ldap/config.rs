@@ -4,12 +4,13 @@
 //! All timeouts and connection settings are configurable.
 
 use std::env;
+use std::fmt;
 use std::time::Duration;
 
 use crate::ldap::error::{LdapError, LdapResult};
 
 /// LDAP configuration loaded from environment variables
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct LdapConfig {
     /// LDAP server URL (e.g., ldaps://ldap.example.com:636)
     pub url: String,
@@ -28,6 +29,37 @@ pub struct LdapConfig {
 
     /// User search filter template (e.g., "(sAMAccountName={})")
     pub user_filter: String,
+
+    /// Service account (DN or UPN) that pooled search connections bind as;
+    /// without one, user details are read on the login's own connection
+    pub bind_dn: Option<String>,
+
+    /// Password of the service account
+    pub bind_password: Option<String>,
+
+    /// Most pooled search connections kept open
+    pub pool_size: usize,
+
+    /// Idle time after which a pooled connection is closed
+    pub pool_idle_timeout: Duration,
+}
+
+/// Written by hand so the service account password never reaches a log
+impl fmt::Debug for LdapConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LdapConfig")
+            .field("url", &self.url)
+            .field("domain", &self.domain)
+            .field("base_dn", &self.base_dn)
+            .field("timeout", &self.timeout)
+            .field("verify_certs", &self.verify_certs)
+            .field("user_filter", &self.user_filter)
+            .field("bind_dn", &self.bind_dn)
+            .field("bind_password", &self.bind_password.as_ref().map(|_| "***"))
+            .field("pool_size", &self.pool_size)
+            .field("pool_idle_timeout", &self.pool_idle_timeout)
+            .finish()
+    }
 }
 
 impl LdapConfig {
@@ -38,6 +70,10 @@ impl LdapConfig {
     const ENV_TIMEOUT_SECS: &'static str = "LDAP_TIMEOUT_SECS";
     const ENV_VERIFY_CERTS: &'static str = "LDAP_VERIFY_CERTS";
     const ENV_USER_FILTER: &'static str = "LDAP_USER_FILTER";
+    const ENV_BIND_DN: &'static str = "LDAP_BIND_DN";
+    const ENV_BIND_PASSWORD: &'static str = "LDAP_BIND_PASSWORD";
+    const ENV_POOL_SIZE: &'static str = "LDAP_POOL_SIZE";
+    const ENV_POOL_IDLE_SECS: &'static str = "LDAP_POOL_IDLE_SECS";
 
     /// Default values
     const DEFAULT_URL: &'static str = "ldaps://ldap.nwfth.com:636";
@@ -45,6 +81,8 @@ impl LdapConfig {
     const DEFAULT_BASE_DN: &'static str = "DC=NWFTH,DC=com";
     const DEFAULT_TIMEOUT_SECS: u64 = 5;
     const DEFAULT_USER_FILTER: &'static str = "(sAMAccountName={})";
+    const DEFAULT_POOL_SIZE: usize = 4;
+    const DEFAULT_POOL_IDLE_SECS: u64 = 300;
 
     /// Load configuration from environment variables
     ///
@@ -62,34 +100,67 @@ impl LdapConfig {
     /// env::set_var("LDAP_DOMAIN", "COMPANY.com");
     ///
     /// // Then in your code:
-    /// // let config = LdapConfig::from_env()?;
+    /// // let config = LdapConfig::from_env("")?;
     /// ```
-    pub fn from_env() -> LdapResult<Self> {
-        let url = env::var(Self::ENV_URL)
+    ///
+    /// With a prefix, `{prefix}LDAP_*` overrides `LDAP_*`; plants with their
+    /// own directory use this, e.g. `PLANT_B_LDAP_URL`.
+    pub fn from_env(prefix: &str) -> LdapResult<Self> {
+        let var = |name: &str| {
+            env::var(format!("{}{}", prefix, name)).or_else(|_| env::var(name))
+        };
+
+        let url = var(Self::ENV_URL)
             .unwrap_or_else(|_| Self::DEFAULT_URL.to_string());
 
-        let domain = env::var(Self::ENV_DOMAIN)
+        let domain = var(Self::ENV_DOMAIN)
             .unwrap_or_else(|_| Self::DEFAULT_DOMAIN.to_string());
 
-        let base_dn = env::var(Self::ENV_BASE_DN)
+        let base_dn = var(Self::ENV_BASE_DN)
             .unwrap_or_else(|_| Self::DEFAULT_BASE_DN.to_string());
 
-        let timeout_secs = env::var(Self::ENV_TIMEOUT_SECS)
+        let timeout_secs = var(Self::ENV_TIMEOUT_SECS)
             .ok()
             .and_then(|s| s.parse::<u64>().ok())
             .unwrap_or(Self::DEFAULT_TIMEOUT_SECS);
 
         let timeout = Duration::from_secs(timeout_secs);
 
-        let verify_certs = env::var(Self::ENV_VERIFY_CERTS)
+        let verify_certs = var(Self::ENV_VERIFY_CERTS)
             .ok()
             .map(|s| s.to_lowercase() != "false")
             .unwrap_or(true);
 
-        let user_filter = env::var(Self::ENV_USER_FILTER)
+        let user_filter = var(Self::ENV_USER_FILTER)
             .unwrap_or_else(|_| Self::DEFAULT_USER_FILTER.to_string());
 
+        let bind_dn = var(Self::ENV_BIND_DN)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+
+        let bind_password = var(Self::ENV_BIND_PASSWORD).ok();
+
+        let pool_size = var(Self::ENV_POOL_SIZE)
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(Self::DEFAULT_POOL_SIZE);
+
+        let pool_idle_timeout = Duration::from_secs(
+            var(Self::ENV_POOL_IDLE_SECS)
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(Self::DEFAULT_POOL_IDLE_SECS),
+        );
+
         // Validate configuration
+        if bind_dn.is_some() && bind_password.is_none() {
+            return Err(LdapError::ConfigError(
+                "LDAP_BIND_PASSWORD is required with LDAP_BIND_DN".to_string()
+            ));
+        }
+
         if !url.starts_with("ldap://") && !url.starts_with("ldaps://") {
             return Err(LdapError::ConfigError(
                 format!("LDAP_URL must start with ldap:// or ldaps://, got: {}", url)
@@ -103,6 +174,10 @@ impl LdapConfig {
             timeout,
             verify_certs,
             user_filter,
+            bind_dn,
+            bind_password,
+            pool_size,
+            pool_idle_timeout,
         })
     }
 
@@ -114,7 +189,7 @@ impl LdapConfig {
     /// # Examples
     ///
     /// ```rust
-    /// use crate::ldap::config::LdapConfig;
+    /// use rm_partial_pick_remover_api::ldap::LdapConfig;
     ///
     /// let config = LdapConfig {
     ///     url: "ldaps://test".to_string(),
@@ -123,6 +198,10 @@ impl LdapConfig {
     ///     timeout: std::time::Duration::from_secs(5),
     ///     verify_certs: true,
     ///     user_filter: "(sAMAccountName={})".to_string(),
+    ///     bind_dn: None,
+    ///     bind_password: None,
+    ///     pool_size: 4,
+    ///     pool_idle_timeout: std::time::Duration::from_secs(300),
     /// };
     ///
     /// assert_eq!(config.build_upn("deachawat"), "deachawat@NWFTH.com");
@@ -141,7 +220,7 @@ impl LdapConfig {
     /// # Examples
     ///
     /// ```rust
-    /// use crate::ldap::config::LdapConfig;
+    /// use rm_partial_pick_remover_api::ldap::LdapConfig;
     ///
     /// let config = LdapConfig {
     ///     url: "ldaps://test".to_string(),
@@ -150,6 +229,10 @@ impl LdapConfig {
     ///     timeout: std::time::Duration::from_secs(5),
     ///     verify_certs: true,
     ///     user_filter: "(sAMAccountName={})".to_string(),
+    ///     bind_dn: None,
+    ///     bind_password: None,
+    ///     pool_size: 4,
+    ///     pool_idle_timeout: std::time::Duration::from_secs(300),
     /// };
     ///
     /// assert_eq!(config.extract_username("deachawat@NWFTH.com"), "deachawat");
@@ -182,6 +265,10 @@ mod tests {
             timeout: Duration::from_secs(5),
             verify_certs: true,
             user_filter: "(sAMAccountName={})".to_string(),
+            bind_dn: None,
+            bind_password: None,
+            pool_size: 4,
+            pool_idle_timeout: Duration::from_secs(300),
         };
 
         assert_eq!(config.build_upn("deachawat"), "deachawat@NWFTH.com");
@@ -198,6 +285,10 @@ mod tests {
             timeout: Duration::from_secs(5),
             verify_certs: true,
             user_filter: "(sAMAccountName={})".to_string(),
+            bind_dn: None,
+            bind_password: None,
+            pool_size: 4,
+            pool_idle_timeout: Duration::from_secs(300),
         };
 
         assert_eq!(config.extract_username("deachawat@NWFTH.com"), "deachawat");
@@ -213,6 +304,10 @@ mod tests {
             timeout: Duration::from_secs(5),
             verify_certs: true,
             user_filter: "(sAMAccountName={})".to_string(),
+            bind_dn: None,
+            bind_password: None,
+            pool_size: 4,
+            pool_idle_timeout: Duration::from_secs(300),
         };
 
         assert_eq!(config.build_search_filter("deachawat"), "(sAMAccountName=deachawat)");
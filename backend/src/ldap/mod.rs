@@ -9,28 +9,31 @@
 //! - **Timeout Handling**: Configurable timeouts for all operations
 //! - **Error Handling**: Comprehensive error types with `thiserror`
 //! - **User Details**: Retrieves user attributes (display name, email, groups)
+//! - **Shared Clients**: One client per plant, built at startup and shared
+//!   through `web::Data`, with a small pool of service-bound connections for
+//!   directory searches
 //!
 //! # Usage
 //!
 //! ```rust,no_run
-//! use rm_partial_pick_remover_api::ldap::{authenticate, LdapConfig, LdapClient};
+//! use rm_partial_pick_remover_api::ldap::{LdapConfig, LdapClient};
 //!
 //! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
-//! // Simple authentication
-//! let user = authenticate("deachawat", "password").await?;
-//! println!("Welcome, {}", user.display_name);
-//!
-//! // Or use the client directly
-//! let config = LdapConfig::from_env()?;
+//! let config = LdapConfig::from_env("")?;
 //! let client = LdapClient::new(config);
 //! let user = client.authenticate("deachawat", "password").await?;
+//! println!("Welcome, {}", user.display_name);
 //! # Ok(())
 //! # }
 //! ```
 //!
+//! The server builds an [`LdapDirectory`] holding every plant's client once
+//! at startup.
+//!
 //! # Configuration
 //!
-//! Configuration is loaded from environment variables:
+//! Configuration is loaded from environment variables. A plant can override
+//! any of them with a `PLANT_<CODE>_` prefix (see `crate::plant`):
 //!
 //! | Variable | Description | Default |
 //! |----------|-------------|---------|
@@ -40,12 +43,22 @@
 //! | `LDAP_TIMEOUT_SECS` | Connection timeout | `5` |
 //! | `LDAP_VERIFY_CERTS` | Verify TLS certificates | `true` |
 //! | `LDAP_USER_FILTER` | Search filter template | `(sAMAccountName={})` |
+//! | `LDAP_BIND_DN` | Service account for directory searches | none |
+//! | `LDAP_BIND_PASSWORD` | Service account password | none |
+//! | `LDAP_POOL_SIZE` | Most service-bound connections open at once | `4` |
+//! | `LDAP_POOL_IDLE_SECS` | Close pooled connections idle this long | `300` |
+
+use log::{debug, error};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
-use log::error;
+use crate::plant::PlantRegistry;
 
 pub mod client;
 pub mod config;
 pub mod error;
+pub mod pool;
 pub mod user;
 
 // Re-export commonly used types
@@ -54,62 +67,75 @@ pub use config::LdapConfig;
 pub use error::{LdapError, LdapResult};
 pub use user::LdapUser;
 
-/// Authenticate a user against LDAP/Active Directory
-///
-/// This is the high-level convenience function for LDAP authentication.
-/// It loads configuration from environment variables and performs
-/// the authentication.
-///
-/// # Arguments
-///
-/// * `username` - The username (sAMAccountName or UPN format like `user@domain.com`)
-/// * `password` - The user's password
-///
-/// # Returns
-///
-/// Returns `Ok(LdapUser)` on successful authentication containing user details,
-/// or an `LdapError` if authentication fails.
-///
-/// # Errors
-///
-/// * `LdapError::ConfigError` - Required environment variables missing or invalid
-/// * `LdapError::ConnectionError` - Failed to connect to LDAP server
-/// * `LdapError::AuthError` - Invalid credentials
-/// * `LdapError::UserNotFound` - User authenticated but not found in directory
-/// * `LdapError::TimeoutError` - Operation exceeded timeout
-///
-/// # Examples
-///
-/// ```rust,no_run
-/// use rm_partial_pick_remover_api::ldap::authenticate;
-///
-/// # async fn example() {
-/// match authenticate("deachawat", "password123").await {
-///     Ok(user) => {
-///         println!("Welcome, {}!", user.display_name);
-///         if let Some(email) = &user.email {
-///             println!("Email: {}", email);
-///         }
-///     }
-///     Err(e) => {
-///         println!("Authentication failed: {}", e);
-///     }
-/// }
-/// # }
-/// ```
-///
-/// # Security Notes
+/// How often idle pooled connections are checked
+const REAP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The LDAP client of every plant, built once at startup
 ///
-/// - Always use LDAPS (ldaps://) in production
-/// - Keep TLS certificate validation enabled (default)
-/// - Never log passwords
-/// - Use strong service account passwords if using bind DN
-pub async fn authenticate(username: &str, password: &str) -> LdapResult<LdapUser> {
-    let config = LdapConfig::from_env().map_err(|e| {
-        error!("Failed to load LDAP configuration: {}", e);
-        e
-    })?;
+/// A plant whose configuration is invalid keeps the error, so its logins
+/// fail with `LdapError::ConfigError` and fall back to SQL authentication.
+pub struct LdapDirectory {
+    clients: HashMap<String, Result<Arc<LdapClient>, String>>,
+}
+
+impl LdapDirectory {
+    /// Load each plant's LDAP configuration from its environment
+    pub fn from_registry(registry: &PlantRegistry) -> Self {
+        let clients = registry
+            .plants()
+            .iter()
+            .map(|plant| {
+                let client = LdapConfig::from_env(plant.env_prefix())
+                    .map(|config| Arc::new(LdapClient::new(config)))
+                    .map_err(|e| {
+                        error!(
+                            "Failed to load LDAP configuration for plant {}: {}",
+                            plant.code, e
+                        );
+                        match e {
+                            LdapError::ConfigError(message) => message,
+                            e => e.to_string(),
+                        }
+                    });
+                (plant.code.clone(), client)
+            })
+            .collect();
+        Self { clients }
+    }
+
+    /// The client for a plant
+    ///
+    /// # Errors
+    ///
+    /// * `LdapError::ConfigError` - The plant is unknown or its configuration is invalid
+    pub fn client(&self, plant_code: &str) -> LdapResult<&LdapClient> {
+        match self.clients.get(plant_code) {
+            Some(Ok(client)) => Ok(client),
+            Some(Err(e)) => Err(LdapError::ConfigError(e.clone())),
+            None => Err(LdapError::ConfigError(format!(
+                "No LDAP configuration for plant {}",
+                plant_code
+            ))),
+        }
+    }
+
+    /// Close idle pooled connections in the background
+    pub fn spawn_reaper(&self) {
+        let clients: Vec<Arc<LdapClient>> = self
+            .clients
+            .values()
+            .filter_map(|client| client.as_ref().ok().cloned())
+            .collect();
 
-    let client = LdapClient::new(config);
-    client.authenticate(username, password).await
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(REAP_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let closed: usize = clients.iter().map(|client| client.reap_idle()).sum();
+                if closed > 0 {
+                    debug!("Closed {} idle LDAP connections", closed);
+                }
+            }
+        });
+    }
 }
@@ -0,0 +1,205 @@
+//! LDAP Connection Pool
+//!
+//! Keeps up to `LDAP_POOL_SIZE` TLS connections bound as the service account
+//! for directory searches, so a burst of logins does not open a connection
+//! and bind the service account for each one. Connections idle for longer
+//! than `LDAP_POOL_IDLE_SECS` or closed by the server are dropped on
+//! checkout and by [`LdapPool::reap_idle`].
+
+use ldap3::Ldap;
+use log::debug;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::timeout;
+
+use crate::ldap::client::{bind, open_connection};
+use crate::ldap::config::LdapConfig;
+use crate::ldap::error::{LdapError, LdapResult};
+
+struct IdleConnection {
+    ldap: Ldap,
+    since: Instant,
+}
+
+/// Pool of service-bound connections
+pub struct LdapPool {
+    config: LdapConfig,
+    /// Most recently returned last, so older connections age out
+    idle: Mutex<Vec<IdleConnection>>,
+    /// One permit per connection that may be open at a time
+    permits: Semaphore,
+}
+
+/// A connection checked out of the pool, returned when dropped
+pub struct PooledConnection<'a> {
+    pool: &'a LdapPool,
+    ldap: Option<Ldap>,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl PooledConnection<'_> {
+    pub fn ldap(&mut self) -> &mut Ldap {
+        self.ldap.as_mut().expect("present until dropped")
+    }
+
+    /// Close the connection instead of returning it, e.g. after an error
+    pub fn discard(mut self) {
+        self.ldap = None;
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(ldap) = self.ldap.take() {
+            self.pool.put_idle(ldap, Instant::now());
+        }
+    }
+}
+
+impl LdapPool {
+    /// A pool for `config`, which must name a service account
+    pub fn new(config: LdapConfig) -> Self {
+        let permits = Semaphore::new(config.pool_size);
+        Self {
+            config,
+            idle: Mutex::new(Vec::new()),
+            permits,
+        }
+    }
+
+    /// Check out an idle connection, or open and bind a new one
+    ///
+    /// Waits for a connection to be returned when all are in use, up to the
+    /// configured timeout.
+    pub async fn get(&self) -> LdapResult<PooledConnection<'_>> {
+        let permit = timeout(self.config.timeout, self.permits.acquire())
+            .await
+            .map_err(|_| LdapError::TimeoutError(self.config.timeout))?
+            .expect("pool semaphore is never closed");
+
+        let ldap = match self.take_idle(Instant::now()) {
+            Some(ldap) => ldap,
+            None => self.open().await?,
+        };
+
+        Ok(PooledConnection {
+            pool: self,
+            ldap: Some(ldap),
+            _permit: permit,
+        })
+    }
+
+    /// Connect and bind as the service account
+    async fn open(&self) -> LdapResult<Ldap> {
+        let dn = self.config.bind_dn.as_deref().unwrap_or_default();
+        let password = self.config.bind_password.as_deref().unwrap_or_default();
+
+        let mut ldap = open_connection(&self.config).await?;
+        if let Err(e) = bind(&self.config, &mut ldap, dn, password).await {
+            let _ = ldap.unbind().await;
+            return Err(match e {
+                LdapError::TimeoutError(d) => LdapError::TimeoutError(d),
+                e => LdapError::ConnectionError(format!("Service account bind failed: {}", e)),
+            });
+        }
+        debug!("Opened pooled LDAP connection to {}", self.config.url);
+        Ok(ldap)
+    }
+
+    fn is_fresh(&self, connection: &mut IdleConnection, now: Instant) -> bool {
+        now.saturating_duration_since(connection.since) < self.config.pool_idle_timeout
+            && !connection.ldap.is_closed()
+    }
+
+    /// The most recently used connection that is still usable
+    fn take_idle(&self, now: Instant) -> Option<Ldap> {
+        let mut idle = self.idle.lock().unwrap();
+        while let Some(mut connection) = idle.pop() {
+            if self.is_fresh(&mut connection, now) {
+                return Some(connection.ldap);
+            }
+        }
+        None
+    }
+
+    fn put_idle(&self, mut ldap: Ldap, now: Instant) {
+        if !ldap.is_closed() {
+            let mut idle = self.idle.lock().unwrap();
+            idle.push(IdleConnection { ldap, since: now });
+        }
+    }
+
+    /// Close connections idle too long or closed by the server; returns how
+    /// many were dropped
+    pub fn reap_idle(&self) -> usize {
+        self.reap_idle_at(Instant::now())
+    }
+
+    fn reap_idle_at(&self, now: Instant) -> usize {
+        let mut idle = self.idle.lock().unwrap();
+        let before = idle.len();
+        idle.retain_mut(|connection| self.is_fresh(connection, now));
+        before - idle.len()
+    }
+
+    /// Connections waiting in the pool
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+
+    fn config(url: String) -> LdapConfig {
+        LdapConfig {
+            url,
+            domain: "NWFTH.com".to_string(),
+            base_dn: "DC=NWFTH,DC=com".to_string(),
+            timeout: Duration::from_secs(5),
+            verify_certs: true,
+            user_filter: "(sAMAccountName={})".to_string(),
+            bind_dn: Some("svc-rm@NWFTH.com".to_string()),
+            bind_password: Some("secret".to_string()),
+            pool_size: 2,
+            pool_idle_timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// A plain `ldap://` connection to a local listener that never answers,
+    /// enough to exercise the pool without a directory. The server side must
+    /// be kept for the connection to stay open.
+    async fn connection(config: &LdapConfig, listener: &TcpListener) -> (Ldap, TcpStream) {
+        let (ldap, accepted) = tokio::join!(open_connection(config), listener.accept());
+        (ldap.unwrap(), accepted.unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn test_idle_connections_are_reused_and_reaped() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        let pool = LdapPool::new(config(url));
+        let start = Instant::now();
+        let idle_timeout = pool.config.pool_idle_timeout;
+
+        let (first, _server1) = connection(&pool.config, &listener).await;
+        let (second, _server2) = connection(&pool.config, &listener).await;
+        pool.put_idle(first, start);
+        pool.put_idle(second, start + idle_timeout / 2);
+        assert_eq!(pool.idle_count(), 2);
+
+        // The older connection has outlived the idle timeout
+        assert_eq!(pool.reap_idle_at(start + idle_timeout), 1);
+        assert!(pool.take_idle(start + idle_timeout).is_some());
+        assert_eq!(pool.idle_count(), 0);
+
+        let (third, _server3) = connection(&pool.config, &listener).await;
+        pool.put_idle(third, start);
+        assert!(pool.take_idle(start + idle_timeout * 2).is_none());
+        assert_eq!(pool.idle_count(), 0);
+    }
+}
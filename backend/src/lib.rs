@@ -0,0 +1,14 @@
+//! RM Partial Pick Remover
+//!
+//! Modules shared by the API server (`src/main.rs`) and the support CLI
+//! (`rm-remover-cli`, see `src/bin/rm-remover-cli`).
+
+pub mod auth;
+pub mod db;
+pub mod digest;
+pub mod ldap;
+pub mod models;
+pub mod plant;
+pub mod rm;
+pub mod routes;
+pub mod server;
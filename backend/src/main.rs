@@ -1,52 +1,145 @@
-use actix_cors::Cors;
 use actix_web::{middleware, web, App, HttpServer};
 use dotenv::dotenv;
-use log::info;
-use std::env;
+use log::{info, warn};
+use std::sync::Arc;
 
-mod db;
-mod ldap;
-mod models;
-mod routes;
-
-use db::mssql::MssqlPool;
+use rm_partial_pick_remover_api::digest::{self, DigestConfig};
+use rm_partial_pick_remover_api::ldap::LdapDirectory;
+use rm_partial_pick_remover_api::plant::PlantRegistry;
+use rm_partial_pick_remover_api::rm::events::{poll_interval_from_env, spawn_poller};
+use rm_partial_pick_remover_api::rm::idempotency::IdempotencyStore;
+use rm_partial_pick_remover_api::rm::webhook::{self, WebhookConfig};
+use rm_partial_pick_remover_api::routes;
+use rm_partial_pick_remover_api::server::tls::{self, ReloadingCertResolver};
+use rm_partial_pick_remover_api::server::{SecurityConfig, ServerConfig};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
     env_logger::init();
 
-    let server_port = env::var("SERVER_PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse::<u16>()
-        .expect("SERVER_PORT must be a valid port number");
+    let server_config = ServerConfig::from_env().expect("Invalid server configuration");
+    let security = SecurityConfig::from_env().expect("Invalid CORS or security configuration");
+    security.warn_if_permissive();
+    let tls_enabled = server_config.tls_enabled();
 
     info!(
-        "Starting RM Partial Pick Remover API server on port {}",
-        server_port
+        "Starting RM Partial Pick Remover API server on {} ({})",
+        server_config.address(),
+        if server_config.tls_enabled() {
+            "HTTPS"
+        } else {
+            "HTTP"
+        }
     );
 
-    // Initialize MSSQL connection pool
-    let db_pool = MssqlPool::new()
+    // One MSSQL connection pool, lease table and event hub per plant
+    let plants = PlantRegistry::from_env()
         .await
-        .expect("Failed to create MSSQL connection pool");
+        .expect("Failed to configure plants");
+
+    for plant in plants.plants() {
+        info!(
+            "Serving plant {} ({}), time zone {}",
+            plant.code,
+            plant.name,
+            plant.clock.timezone()
+        );
+
+        // Report what the server agreed to without holding up startup
+        let (code, pool) = (plant.code.clone(), plant.pool.clone());
+        tokio::spawn(async move {
+            let requested = pool.tls().encryption;
+            match pool.negotiated_encryption().await {
+                Ok(true) => info!(
+                    "Database connection for plant {} is encrypted ({})",
+                    code, requested
+                ),
+                Ok(false) => warn!(
+                    "Database connection for plant {} is not encrypted beyond login ({})",
+                    code, requested
+                ),
+                Err(e) => warn!(
+                    "Could not check database encryption for plant {}: {}",
+                    code, e
+                ),
+            }
+        });
+    }
+
+    // Stored responses for retried remove requests
+    let idempotency = web::Data::new(IdempotencyStore::from_env());
+
+    // Live run events, optionally fed by polling for changes made elsewhere
+    if let Some(interval) = poll_interval_from_env() {
+        for plant in plants.plants() {
+            spawn_poller(plant.events.clone(), plant.pool.clone(), interval);
+        }
+    }
+
+    // Deliver queued removal events to webhook subscribers
+    if let Some(config) = WebhookConfig::from_env() {
+        for plant in plants.plants() {
+            webhook::spawn_worker(
+                plant.pool.clone(),
+                plant.code.clone(),
+                plant.clock,
+                config.clone(),
+            )
+            .expect("Failed to start webhook worker");
+        }
+    }
 
-    info!("MSSQL connection pool initialized successfully");
+    // Daily removal digests on each plant's schedule
+    for plant in plants.plants() {
+        let config =
+            DigestConfig::from_env(plant.env_prefix()).expect("Invalid digest configuration");
+        digest::spawn_scheduler(plant.pool.clone(), plant.code.clone(), plant.clock, config);
+    }
 
-    HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header()
-            .max_age(3600);
+    // LDAP clients with their pooled search connections, shared by all workers
+    let ldap = web::Data::new(LdapDirectory::from_registry(&plants));
+    ldap.spawn_reaper();
 
+    let plants = web::Data::new(plants);
+
+    let server = HttpServer::new(move || {
         App::new()
+            .wrap(security.cors())
+            .wrap(security.security_headers(tls_enabled))
             .wrap(middleware::Logger::default())
-            .wrap(cors)
-            .app_data(web::Data::new(db_pool.clone()))
+            .app_data(security.json_config())
+            .app_data(plants.clone())
+            .app_data(ldap.clone())
+            .app_data(idempotency.clone())
             .configure(routes::config)
     })
-    .bind(format!("0.0.0.0:{}", server_port))?
-    .run()
-    .await
+    .keep_alive(server_config.keep_alive)
+    .client_request_timeout(server_config.client_request_timeout)
+    .client_disconnect_timeout(server_config.client_disconnect_timeout)
+    // SIGTERM stops accepting connections and waits this long for in-flight
+    // requests, so a running removal batch can commit before the container stops
+    .shutdown_timeout(server_config.shutdown_timeout.as_secs());
+
+    let server = match server_config.workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+
+    let server = match &server_config.tls {
+        Some(tls_config) => {
+            let resolver = Arc::new(
+                ReloadingCertResolver::load(tls_config).expect("Failed to load TLS certificate"),
+            );
+            tls::spawn_reloader(resolver.clone(), tls_config.clone());
+            let rustls_config = tls::server_config(resolver).expect("Failed to configure TLS");
+            server.bind_rustls_0_23(server_config.address(), rustls_config)?
+        }
+        None => server.bind(server_config.address())?,
+    };
+
+    server.run().await?;
+
+    info!("Server stopped");
+    Ok(())
 }
@@ -4,6 +4,9 @@ use serde::{Deserialize, Serialize};
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
+    /// Plant to sign in to; the default plant when omitted
+    #[serde(default)]
+    pub plant: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,6 +21,12 @@ pub struct LoginResponse {
 pub struct UserInfo {
     pub username: String,
     pub display_name: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub plant: String,
+    #[serde(default)]
+    pub plants: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,6 +35,104 @@ pub struct Claims {
     /// Display name of the user (for UI display)
     #[serde(default)]
     pub display_name: String,
+    /// Roles granted at login (see `crate::auth::roles_for`)
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Plant chosen at login, used when a request sends no `X-Plant` header
+    #[serde(default)]
+    pub plant: String,
+    /// Plants the user may reach (see `crate::plant::PlantRegistry::plants_for`)
+    #[serde(default)]
+    pub plants: Vec<String>,
     pub exp: usize,
     pub iat: usize,
 }
+
+/// A plant offered on the login page
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlantInfo {
+    pub code: String,
+    pub name: String,
+    pub default: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlantListResponse {
+    pub success: bool,
+    pub data: Vec<PlantInfo>,
+    pub message: String,
+}
+
+/// LOCAL account in `tbl_user` with its settings from `cust_PartialPickUserAccount`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LocalUser {
+    pub username: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub display_name: String,
+    /// Roles stored for the account, on top of `RM_SUPERVISOR_USERS` / `RM_ADMIN_USERS`
+    pub roles: Vec<String>,
+    pub disabled: bool,
+    /// False for passwords still stored in plaintext
+    pub password_hashed: bool,
+    pub modified_by: Option<String>,
+    pub modified_date: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocalUserListResponse {
+    pub success: bool,
+    pub data: Vec<LocalUser>,
+    pub message: String,
+}
+
+/// Body of `POST /api/users`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CreateLocalUserRequest {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub first_name: String,
+    #[serde(default)]
+    pub last_name: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// Body of `PUT /api/users/{username}`; omitted fields are kept
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct UpdateLocalUserRequest {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub roles: Option<Vec<String>>,
+}
+
+/// Body of `POST /api/users/{username}/password`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PasswordResetRequest {
+    pub password: String,
+}
+
+/// One change in `cust_PartialPickUserAudit`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct UserAuditEntry {
+    pub audit_id: i32,
+    pub username: String,
+    /// `CREATED`, `UPDATED`, `DISABLED`, `ENABLED` or `PASSWORD_RESET`
+    pub action: String,
+    pub detail: Option<String>,
+    pub changed_by: String,
+    pub changed_date: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserAuditListResponse {
+    pub success: bool,
+    pub data: Vec<UserAuditEntry>,
+    pub message: String,
+}
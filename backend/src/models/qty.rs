@@ -0,0 +1,304 @@
+//! Exact Quantities
+//!
+//! Weighed quantities are fixed-point decimals with [`QTY_SCALE`] places,
+//! read from SQL `DECIMAL` columns without going through a float and bound
+//! back as `DECIMAL`. Sums, differences and pack rounding are exact.
+//!
+//! In JSON a quantity is a plain number such as `12.5`. Values are rounded to
+//! six places and the number is written with the shortest digits that read
+//! back as the same value, so the decimal digits come out unchanged
+//! (`0.3`, never `0.30000000000000004`). Requests may send numbers or
+//! decimal strings; more than six places are rounded half away from zero.
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+use tiberius::numeric::Numeric;
+use tiberius::{ColumnData, IntoSql};
+
+use crate::db::row::FromColumn;
+
+/// Decimal places kept for every quantity
+pub const QTY_SCALE: u8 = 6;
+
+/// SQL type used for quantities in batches, e.g. `DECLARE @Before DECIMAL(38, 6)`
+pub const QTY_SQL_TYPE: &str = "DECIMAL(38, 6)";
+
+const UNIT: i128 = 10i128.pow(QTY_SCALE as u32);
+
+/// A quantity with exactly [`QTY_SCALE`] decimal places
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Qty(i128);
+
+impl Qty {
+    pub const ZERO: Qty = Qty(0);
+
+    /// `value / 10^scale`, rounded half away from zero to [`QTY_SCALE`] places
+    fn from_scaled(value: i128, scale: u8) -> Self {
+        if scale <= QTY_SCALE {
+            return Qty(value * 10i128.pow(u32::from(QTY_SCALE - scale)));
+        }
+        let divisor = 10i128.pow(u32::from(scale - QTY_SCALE));
+        let (quotient, remainder) = (value / divisor, value % divisor);
+        if remainder.abs() * 2 >= divisor {
+            Qty(quotient + value.signum())
+        } else {
+            Qty(quotient)
+        }
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// Largest multiple of `step` not above `self`; `self` when `step` is not positive
+    pub fn floor_to_multiple(self, step: Qty) -> Qty {
+        if !step.is_positive() {
+            return self;
+        }
+        Qty(self.0.div_euclid(step.0) * step.0)
+    }
+
+    /// Whether `self` is more than `percent` percent of `whole`
+    pub fn exceeds_percent_of(self, whole: Qty, percent: Qty) -> bool {
+        // Both sides carry twice the scale, so the comparison is exact
+        self.0 * 100 * UNIT > percent.0 * whole.0
+    }
+
+    /// `self` as a percentage of `whole`, for messages
+    pub fn percent_of(self, whole: Qty) -> f64 {
+        if whole.is_zero() {
+            return 0.0;
+        }
+        self.0 as f64 * 100.0 / whole.0 as f64
+    }
+
+    /// Nearest `f64`; exact in its shortest decimal form up to 15 digits
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse().unwrap_or_default()
+    }
+
+    /// Convert a float read from a legacy `FLOAT` column or a JSON number
+    pub fn from_f64(value: f64) -> Option<Qty> {
+        if !value.is_finite() {
+            return None;
+        }
+        // `Display` writes the shortest digits that read back as `value`
+        value.to_string().parse().ok()
+    }
+}
+
+impl fmt::Display for Qty {
+    /// Decimal form without trailing zeros, e.g. `12.5` or `-0.3`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let (whole, fraction) = (abs / UNIT as u128, abs % UNIT as u128);
+        if fraction == 0 {
+            return write!(f, "{}{}", sign, whole);
+        }
+        let digits = format!("{:0width$}", fraction, width = QTY_SCALE as usize);
+        write!(f, "{}{}.{}", sign, whole, digits.trim_end_matches('0'))
+    }
+}
+
+/// Why a decimal string is not a quantity
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseQtyError(String);
+
+impl fmt::Display for ParseQtyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid quantity: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseQtyError {}
+
+impl FromStr for Qty {
+    type Err = ParseQtyError;
+
+    /// Parse a plain decimal such as `12.5` or `-3`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseQtyError(s.to_string());
+        let trimmed = s.trim();
+        let (negative, unsigned) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() && fraction.is_empty()
+            || !all_digits(whole)
+            || !all_digits(fraction)
+            // Beyond 30 significant digits the value would not fit
+            || whole.len() + fraction.len() > 30
+        {
+            return Err(invalid());
+        }
+
+        let digits: i128 = format!("{}{}", whole, fraction)
+            .parse()
+            .map_err(|_| invalid())?;
+        let scale = u8::try_from(fraction.len()).map_err(|_| invalid())?;
+        let qty = Qty::from_scaled(digits, scale);
+        Ok(if negative { Qty(-qty.0) } else { qty })
+    }
+}
+
+impl From<i32> for Qty {
+    fn from(value: i32) -> Self {
+        Qty(i128::from(value) * UNIT)
+    }
+}
+
+impl From<Numeric> for Qty {
+    fn from(value: Numeric) -> Self {
+        Qty::from_scaled(value.value(), value.scale())
+    }
+}
+
+impl From<Qty> for Numeric {
+    fn from(value: Qty) -> Self {
+        Numeric::new_with_scale(value.0, QTY_SCALE)
+    }
+}
+
+impl Add for Qty {
+    type Output = Qty;
+
+    fn add(self, rhs: Qty) -> Qty {
+        Qty(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Qty {
+    type Output = Qty;
+
+    fn sub(self, rhs: Qty) -> Qty {
+        Qty(self.0 - rhs.0)
+    }
+}
+
+impl Sum for Qty {
+    fn sum<I: Iterator<Item = Qty>>(iter: I) -> Qty {
+        iter.fold(Qty::ZERO, Add::add)
+    }
+}
+
+impl<'a> IntoSql<'a> for Qty {
+    fn into_sql(self) -> ColumnData<'a> {
+        ColumnData::Numeric(Some(self.into()))
+    }
+}
+
+impl FromColumn<'_> for Qty {
+    const SQL_TYPE: &'static str = "DECIMAL or FLOAT";
+
+    fn from_column(data: &ColumnData<'static>) -> Option<Option<Self>> {
+        match data {
+            ColumnData::Numeric(v) => Some(v.map(Qty::from)),
+            // Older audit tables store quantities as FLOAT
+            ColumnData::F64(v) => Some(v.and_then(Qty::from_f64)),
+            ColumnData::F32(v) => Some(v.and_then(|f| Qty::from_f64(f64::from(f)))),
+            ColumnData::U8(v) => Some(v.map(|v| Qty::from(i32::from(v)))),
+            ColumnData::I16(v) => Some(v.map(|v| Qty::from(i32::from(v)))),
+            ColumnData::I32(v) => Some(v.map(Qty::from)),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for Qty {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Qty {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct QtyVisitor;
+
+        impl Visitor<'_> for QtyVisitor {
+            type Value = Qty;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a decimal number or string")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Qty, E> {
+                Ok(Qty(i128::from(v) * UNIT))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Qty, E> {
+                Ok(Qty(i128::from(v) * UNIT))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Qty, E> {
+                Qty::from_f64(v).ok_or_else(|| E::custom(format!("invalid quantity {}", v)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Qty, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(QtyVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qty(s: &str) -> Qty {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(qty("12.5").to_string(), "12.5");
+        assert_eq!(qty("-0.30").to_string(), "-0.3");
+        assert_eq!(qty("7").to_string(), "7");
+        assert_eq!(qty("0.0000004").to_string(), "0");
+        assert_eq!(qty("0.0000005").to_string(), "0.000001");
+        assert!("1e3".parse::<Qty>().is_err());
+        assert!("".parse::<Qty>().is_err());
+        assert!(".".parse::<Qty>().is_err());
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        let sum = qty("0.1") + qty("0.2");
+        assert_eq!(sum, qty("0.3"));
+        assert_eq!(serde_json::to_string(&sum).unwrap(), "0.3");
+
+        assert_eq!(qty("80").floor_to_multiple(qty("25")), qty("75"));
+        assert_eq!(qty("50").floor_to_multiple(qty("12.5")), qty("50"));
+        assert_eq!(qty("30").floor_to_multiple(Qty::ZERO), qty("30"));
+
+        assert!(qty("30").exceeds_percent_of(qty("100"), qty("25")));
+        assert!(!qty("25").exceeds_percent_of(qty("100"), qty("25")));
+    }
+
+    #[test]
+    fn test_numeric_and_json() {
+        let numeric = Numeric::new_with_scale(12345, 3);
+        assert_eq!(Qty::from(numeric), qty("12.345"));
+        assert_eq!(Numeric::from(qty("12.345")).value(), 12_345_000);
+
+        let parsed: Vec<Qty> = serde_json::from_str(r#"[0.30000000000000004, "1.25", 4]"#).unwrap();
+        assert_eq!(parsed, vec![qty("0.3"), qty("1.25"), qty("4")]);
+    }
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 
+use crate::models::qty::Qty;
+use crate::rm::lease::RunLease;
+
 /// Serialize with PascalCase field names to match frontend TypeScript types
 /// e.g. run_no → RunNo, batch_no → BatchNo
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,12 +16,25 @@ pub struct RMLine {
     pub item_key: String,
     pub location: String,
     pub unit: String,
-    pub standard_qty: f64,
-    pub pack_size: f64,
-    pub to_picked_partial_qty: f64,
-    pub picked_partial_qty: Option<f64>,
+    pub standard_qty: Qty,
+    pub pack_size: Qty,
+    pub to_picked_partial_qty: Qty,
+    pub picked_partial_qty: Option<Qty>,
     pub rec_user_id: String,
     pub modified_by: String,
+    /// Hash of the mutable columns; send it back in `RemoveItem` so the
+    /// removal is refused if the line changed after it was read
+    pub concurrency_token: String,
+    /// Item master and location data, when lookups are configured (see
+    /// `rm::master`); `null` otherwise or when the key is not found
+    #[serde(default)]
+    pub item_description: Option<String>,
+    #[serde(default)]
+    pub item_class: Option<String>,
+    #[serde(default)]
+    pub storage_condition: Option<String>,
+    #[serde(default)]
+    pub location_description: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,17 +43,68 @@ pub struct SearchRequest {
     pub runno: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RemoveItem {
     pub row_num: i32,
     pub line_id: i32,
+    /// `ConcurrencyToken` of the line as last read; required by
+    /// `POST /api/rm/remove`, filled in from the current line by approvals
+    #[serde(default)]
+    pub concurrency_token: Option<String>,
+    /// New ToPickedPartialQty instead of zero
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_qty: Option<Qty>,
+    /// Amount to take off ToPickedPartialQty instead of removing all of it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reduce_by: Option<Qty>,
+    /// Round the resulting quantity down to whole packs of PackSize
+    #[serde(default)]
+    pub round_to_pack_size: bool,
+}
+
+/// Outcome of removing a single item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoveItemStatus {
+    Removed,
+    /// The line changed since the client read it
+    Conflict,
+    /// `new_qty` / `reduce_by` is negative, above the current value or no change
+    InvalidQuantity,
+    NotFound,
+    Error,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoveItemResult {
+    pub row_num: i32,
+    pub line_id: i32,
+    pub status: RemoveItemStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// ToPickedPartialQty before and after the change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qty_before: Option<Qty>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qty_after: Option<Qty>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RemoveRequest {
     pub run_no: i32,
     pub items: Vec<RemoveItem>,
+    /// Optional; must name the signed-in user, who is recorded as the remover
+    #[serde(default)]
     pub user_logon: String,
+    /// Lease id returned by `POST /api/rm/runs/{run_no}/lease`
+    #[serde(default)]
+    pub lease_id: Option<String>,
+    /// One of the active codes from `GET /api/rm/reason-codes`
+    #[serde(default)]
+    pub reason_code: Option<String>,
+    /// Free text, required for codes flagged `RequiresComment`
+    #[serde(default)]
+    pub comment: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,6 +112,45 @@ pub struct RemoveResponse {
     pub success: bool,
     pub message: String,
     pub affected_rows: usize,
+    #[serde(default)]
+    pub results: Vec<RemoveItemResult>,
+    /// Set when the removal was held for supervisor approval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval_request_id: Option<i32>,
+}
+
+/// Removes every eligible line of a run matching the optional filters
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoveByCriteriaRequest {
+    pub run_no: i32,
+    #[serde(default)]
+    pub batch_no: Option<String>,
+    #[serde(default)]
+    pub item_key: Option<String>,
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub line_typ: Option<String>,
+    /// Optional; must name the signed-in user, who is recorded as the remover
+    #[serde(default)]
+    pub user_logon: String,
+    #[serde(default)]
+    pub lease_id: Option<String>,
+    #[serde(default)]
+    pub reason_code: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemoveByCriteriaResponse {
+    pub success: bool,
+    pub message: String,
+    pub affected_rows: usize,
+    /// Lines as they were before removal
+    pub data: Vec<RMLine>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval_request_id: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,4 +158,318 @@ pub struct SearchResponse {
     pub success: bool,
     pub data: Vec<RMLine>,
     pub message: String,
+    /// Active lease on the run, so the UI can show who is working on it
+    #[serde(default)]
+    pub lease: Option<RunLease>,
+}
+
+/// Aggregated quantities over a group of `cust_PartialPicked` lines
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct QtySummary {
+    pub line_count: i32,
+    pub standard_qty: Qty,
+    /// ToPickedPartialQty still waiting to be picked
+    pub outstanding_qty: Qty,
+    /// PickedPartialQty already picked
+    pub picked_qty: Qty,
+    /// Quantity removed by this tool (User8 audit column)
+    pub removed_qty: Qty,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct BatchSummary {
+    pub batch_no: String,
+    #[serde(flatten)]
+    pub totals: QtySummary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ItemSummary {
+    pub item_key: String,
+    #[serde(flatten)]
+    pub totals: QtySummary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RunSummary {
+    pub run_no: i32,
+    pub totals: QtySummary,
+    pub batches: Vec<BatchSummary>,
+    pub items: Vec<ItemSummary>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunSummaryResponse {
+    pub success: bool,
+    pub data: Option<RunSummary>,
+    pub message: String,
+}
+
+/// Run with lines still eligible for removal, listed by `GET /api/rm/runs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct OpenRun {
+    pub run_no: i32,
+    /// Open lines, i.e. with ToPickedPartialQty left and nothing picked
+    pub line_count: i32,
+    pub item_count: i32,
+    pub outstanding_qty: Qty,
+    /// Oldest and newest ModifiedDate of the open lines, in the plant's zone
+    pub earliest_modified: Option<String>,
+    pub latest_modified: Option<String>,
+    pub batches: Vec<String>,
+    /// Active lease on the run, so the UI can show who is working on it
+    #[serde(default)]
+    pub lease: Option<RunLease>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunListResponse {
+    pub success: bool,
+    pub data: Vec<OpenRun>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeaseRequest {
+    /// Optional; must name the signed-in user, who becomes the holder
+    #[serde(default)]
+    pub user_logon: String,
+    #[serde(default)]
+    pub station: Option<String>,
+    /// Exclusive leases block removals by others; advisory ones only inform
+    #[serde(default = "default_exclusive")]
+    pub exclusive: bool,
+}
+
+fn default_exclusive() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LeaseResponse {
+    pub success: bool,
+    pub lease: Option<RunLease>,
+    pub message: String,
+    /// Id of the acquired lease, only sent to its holder by acquire; send it
+    /// back with changes to the run, renew and release
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ApprovalStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl ApprovalStatus {
+    pub fn as_db(&self) -> &'static str {
+        match self {
+            ApprovalStatus::Pending => "PENDING",
+            ApprovalStatus::Approved => "APPROVED",
+            ApprovalStatus::Rejected => "REJECTED",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value.trim().to_uppercase().as_str() {
+            "APPROVED" => ApprovalStatus::Approved,
+            "REJECTED" => ApprovalStatus::Rejected,
+            _ => ApprovalStatus::Pending,
+        }
+    }
+}
+
+/// A removal held for supervisor sign-off
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ApprovalRequest {
+    pub request_id: i32,
+    pub run_no: i32,
+    pub items: Vec<RemoveItem>,
+    pub line_count: i32,
+    pub total_qty: Qty,
+    pub reason_code: String,
+    /// Comment given with the reason code
+    pub reason: String,
+    /// Which threshold sent the removal for approval
+    pub threshold: String,
+    pub status: ApprovalStatus,
+    pub requested_by: String,
+    pub requested_date: String,
+    pub decided_by: Option<String>,
+    pub decided_date: Option<String>,
+    pub decision_comment: Option<String>,
+    pub affected_rows: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApprovalDecisionRequest {
+    #[serde(default)]
+    pub comment: Option<String>,
+    /// Lease id when the run has an exclusive lease; approving applies the
+    /// removal, so it is held to the same lease as `POST /api/rm/remove`
+    #[serde(default)]
+    pub lease_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApprovalListResponse {
+    pub success: bool,
+    pub data: Vec<ApprovalRequest>,
+    pub message: String,
+}
+
+/// Reason an operator can give for a removal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ReasonCode {
+    pub reason_code: String,
+    pub description: String,
+    #[serde(default)]
+    pub requires_comment: bool,
+    #[serde(default = "default_active")]
+    pub active: bool,
+    #[serde(default)]
+    pub sort_order: i32,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReasonCodeListResponse {
+    pub success: bool,
+    pub data: Vec<ReasonCode>,
+    pub message: String,
+}
+
+/// One removed line in `cust_PartialPickRemovalAudit`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RemovalAuditEntry {
+    pub audit_id: i32,
+    pub run_no: i32,
+    pub row_num: i32,
+    pub line_id: i32,
+    pub batch_no: String,
+    pub item_key: String,
+    pub qty_before: Qty,
+    pub qty_after: Qty,
+    pub reason_code: String,
+    pub reason_description: String,
+    pub comment: Option<String>,
+    pub removed_by: String,
+    pub approved_by: Option<String>,
+    pub approval_request_id: Option<i32>,
+    pub removed_date: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryResponse {
+    pub success: bool,
+    pub data: Vec<RemovalAuditEntry>,
+    pub message: String,
+}
+
+/// Webhook subscription in `cust_PartialPickWebhook`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Webhook {
+    #[serde(default)]
+    pub webhook_id: i32,
+    pub name: String,
+    pub url: String,
+    /// HMAC key for the signature header, never returned by the API
+    #[serde(default, skip_serializing)]
+    pub secret: String,
+    /// Event types to deliver, `*` for all
+    #[serde(default = "default_event_types")]
+    pub event_types: Vec<String>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_event_types() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookListResponse {
+    pub success: bool,
+    pub data: Vec<Webhook>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Gave up after the maximum number of attempts
+    Dead,
+}
+
+impl DeliveryStatus {
+    pub fn as_db(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "PENDING",
+            DeliveryStatus::Delivered => "DELIVERED",
+            DeliveryStatus::Dead => "DEAD",
+        }
+    }
+
+    pub fn from_db(value: &str) -> Self {
+        match value.trim().to_uppercase().as_str() {
+            "DELIVERED" => DeliveryStatus::Delivered,
+            "DEAD" => DeliveryStatus::Dead,
+            _ => DeliveryStatus::Pending,
+        }
+    }
+}
+
+/// One event queued for one webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct WebhookDelivery {
+    pub delivery_id: i32,
+    pub webhook_id: i32,
+    pub webhook_name: String,
+    pub outbox_id: i32,
+    pub event_type: String,
+    pub run_no: i32,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_date: String,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_date: String,
+    pub delivered_date: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookDeliveryListResponse {
+    pub success: bool,
+    pub data: Vec<WebhookDelivery>,
+    pub message: String,
+}
+
+/// Body POSTed to webhook URLs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct WebhookEvent {
+    pub event_id: i32,
+    pub event_type: String,
+    /// Plant whose database recorded the removal
+    pub plant: String,
+    pub created_date: String,
+    pub data: RemovalAuditEntry,
 }
@@ -0,0 +1,170 @@
+//! Plant Time
+//!
+//! Timestamps are taken on the API server and passed to SQL as parameters,
+//! so the database server's clock and time zone do not matter. They are
+//! stored in UTC; the legacy `User9` column gets the date at the plant, and
+//! API responses show times in the plant's zone with the offset included.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::env;
+
+const ENV_PLANT_TIMEZONE: &str = "PLANT_TIMEZONE";
+
+/// A moment as written by a removal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlantTime {
+    /// Stored in `ModifiedDate` and the audit trail
+    pub utc: NaiveDateTime,
+    /// Plant-local date as `YYYYMMDD`, stored in `User9`
+    pub legacy_date: i32,
+}
+
+/// Converts between UTC and the time zone of a plant
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlantClock {
+    tz: Tz,
+}
+
+impl Default for PlantClock {
+    fn default() -> Self {
+        Self::new(Tz::UTC)
+    }
+}
+
+impl PlantClock {
+    pub fn new(tz: Tz) -> Self {
+        Self { tz }
+    }
+
+    /// Zone from `{prefix}TIMEZONE`, then `PLANT_TIMEZONE`, else UTC
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for a name that is not in the IANA database,
+    /// e.g. `Asia/Bangkok`.
+    pub fn from_env(prefix: &str) -> Result<Self> {
+        let name = Some(prefix)
+            .filter(|p| !p.is_empty())
+            .and_then(|p| env::var(format!("{}TIMEZONE", p)).ok())
+            .or_else(|| env::var(ENV_PLANT_TIMEZONE).ok())
+            .filter(|n| !n.trim().is_empty());
+
+        match name {
+            Some(name) => name
+                .trim()
+                .parse::<Tz>()
+                .map(Self::new)
+                .map_err(|_| anyhow!("Unknown time zone: {}", name)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.tz
+    }
+
+    pub fn now(&self) -> PlantTime {
+        self.at(Utc::now())
+    }
+
+    pub fn at(&self, moment: DateTime<Utc>) -> PlantTime {
+        let local = moment.with_timezone(&self.tz).date_naive();
+        PlantTime {
+            utc: moment.naive_utc(),
+            legacy_date: local
+                .format("%Y%m%d")
+                .to_string()
+                .parse()
+                .expect("YYYYMMDD fits in i32"),
+        }
+    }
+
+    /// UTC bounds `[start, end)` of a calendar day at the plant
+    pub fn day_range(&self, day: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+        let start_of = |day: NaiveDate| {
+            let midnight = day.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+            // Where DST starts at midnight the day begins an hour later
+            self.tz
+                .from_local_datetime(&midnight)
+                .earliest()
+                .or_else(|| {
+                    self.tz
+                        .from_local_datetime(&(midnight + TimeDelta::hours(1)))
+                        .earliest()
+                })
+                .map(|start| start.naive_utc())
+                .unwrap_or(midnight)
+        };
+        let next = day.succ_opt().unwrap_or(day);
+        (start_of(day), start_of(next))
+    }
+
+    /// ISO-8601 in the plant's zone for a stored UTC time, e.g.
+    /// `2024-01-01T07:30:00+07:00`
+    pub fn format(&self, utc: NaiveDateTime) -> String {
+        Utc.from_utc_datetime(&utc)
+            .with_timezone(&self.tz)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_date_uses_plant_day() {
+        let clock = PlantClock::new(chrono_tz::Asia::Bangkok);
+        // 23:30 UTC is already the next morning in Bangkok
+        let moment = Utc.with_ymd_and_hms(2024, 1, 31, 23, 30, 0).unwrap();
+
+        let time = clock.at(moment);
+        assert_eq!(time.legacy_date, 20240201);
+        assert_eq!(time.utc, moment.naive_utc());
+        assert_eq!(PlantClock::default().at(moment).legacy_date, 20240131);
+    }
+
+    #[test]
+    fn test_day_range_in_plant_zone() {
+        let day = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        let utc = |d, h| {
+            NaiveDate::from_ymd_opt(2024, 3, d)
+                .unwrap()
+                .and_hms_opt(h, 0, 0)
+                .unwrap()
+        };
+
+        assert_eq!(
+            PlantClock::new(chrono_tz::Asia::Bangkok).day_range(day),
+            (utc(30, 17), utc(31, 17))
+        );
+        // The day clocks go forward in Berlin has 23 hours
+        assert_eq!(
+            PlantClock::new(chrono_tz::Europe::Berlin).day_range(day),
+            (utc(30, 23), utc(31, 22))
+        );
+    }
+
+    #[test]
+    fn test_format_includes_offset() {
+        let utc = Utc
+            .with_ymd_and_hms(2024, 7, 1, 12, 0, 0)
+            .unwrap()
+            .naive_utc();
+
+        assert_eq!(
+            PlantClock::new(chrono_tz::Asia::Bangkok).format(utc),
+            "2024-07-01T19:00:00+07:00"
+        );
+        assert_eq!(
+            PlantClock::new(chrono_tz::Europe::Berlin).format(utc),
+            "2024-07-01T14:00:00+02:00"
+        );
+        assert_eq!(
+            PlantClock::default().format(utc),
+            "2024-07-01T12:00:00+00:00"
+        );
+    }
+}
@@ -0,0 +1,400 @@
+//! Plants
+//!
+//! One API instance can serve several plants. Each plant has its own
+//! database holding `cust_PartialPicked`, its own run leases and live events,
+//! and optionally its own directory settings.
+//!
+//! # Selecting a plant
+//!
+//! Handlers take a [`CurrentPlant`]. The plant is taken from the `X-Plant`
+//! header, then from the `plant` claim chosen at login, then the default
+//! plant. With more than one plant configured a token is required and must
+//! list the plant in its `plants` claim. A single plant is still read without
+//! a token, as before plants existed; handlers that change data also take an
+//! [`AuthenticatedUser`](crate::auth::AuthenticatedUser), so they need one in
+//! either mode.
+//!
+//! # Configuration
+//!
+//! Without `PLANTS` a single plant is served from the `DB_*` and `LDAP_*`
+//! variables, as before.
+//!
+//! | Variable | Description | Default |
+//! |----------|-------------|---------|
+//! | `PLANTS` | Comma-separated plant codes | unset |
+//! | `PLANT_DEFAULT` | Plant used when a request names none | first of `PLANTS` |
+//! | `PLANT_<CODE>_NAME` | Display name | the code |
+//! | `PLANT_<CODE>_DB_DATABASE` | Database of the plant | required |
+//! | `PLANT_<CODE>_DB_SERVER`, `_DB_PORT`, `_DB_USERNAME`, `_DB_PASSWORD` | Connection overrides | `DB_*` |
+//! | `PLANT_<CODE>_LDAP_URL`, `_LDAP_DOMAIN`, `_LDAP_BASE_DN`, ... | Directory overrides | `LDAP_*` |
+//! | `PLANT_<CODE>_RM_ITEM_MASTER_TABLE`, `_RM_LOCATION_TABLE`, ... | Master data lookups, see [`crate::rm::master`] | `RM_*` |
+//! | `PLANT_<CODE>_USERS` | Users allowed at the plant, empty for everyone | empty |
+//! | `PLANT_TIMEZONE` | IANA time zone of the plants, e.g. `Asia/Bangkok` | `UTC` |
+//! | `PLANT_<CODE>_TIMEZONE` | Time zone of one plant | `PLANT_TIMEZONE` |
+
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse};
+use anyhow::{bail, Context, Result};
+use serde_json::json;
+use std::env;
+use std::future::{ready, Ready};
+use std::ops::Deref;
+use std::sync::Arc;
+
+pub mod clock;
+
+pub use clock::{PlantClock, PlantTime};
+
+use crate::auth::{authenticate_request, roles_for, ROLE_ADMIN};
+use crate::db::mssql::MssqlPool;
+use crate::models::rm::RMLine;
+use crate::rm::master::MasterData;
+use crate::rm::{LeaseManager, RunEventHub};
+
+/// Header naming the plant a request is for
+pub const PLANT_HEADER: &str = "X-Plant";
+
+const ENV_PLANTS: &str = "PLANTS";
+const ENV_PLANT_DEFAULT: &str = "PLANT_DEFAULT";
+
+/// Code of the plant served when `PLANTS` is not set
+const SINGLE_PLANT_CODE: &str = "DEFAULT";
+
+/// A plant with its database and in-memory run state
+pub struct Plant {
+    pub code: String,
+    pub name: String,
+    pub pool: MssqlPool,
+    pub leases: LeaseManager,
+    pub events: Arc<RunEventHub>,
+    pub clock: PlantClock,
+    /// Item and location lookups for search results, when configured
+    pub master: Option<MasterData>,
+    /// Users allowed at the plant; empty allows everyone
+    pub users: Vec<String>,
+    /// Prefix of the plant's environment overrides, e.g. `PLANT_B_`
+    env_prefix: String,
+}
+
+impl Plant {
+    fn new(
+        code: String,
+        name: String,
+        pool: MssqlPool,
+        clock: PlantClock,
+        master: Option<MasterData>,
+        users: Vec<String>,
+        env_prefix: String,
+    ) -> Self {
+        Self {
+            code,
+            name,
+            pool,
+            leases: LeaseManager::from_env(),
+            events: Arc::new(RunEventHub::new()),
+            clock,
+            master,
+            users,
+            env_prefix,
+        }
+    }
+
+    /// Prefix of the plant's `DB_*` and `LDAP_*` overrides
+    pub fn env_prefix(&self) -> &str {
+        &self.env_prefix
+    }
+
+    /// Add item master and location data to lines, when lookups are configured
+    pub async fn enrich(&self, lines: &mut [RMLine]) {
+        if let Some(master) = &self.master {
+            master.enrich(&self.pool, lines).await;
+        }
+    }
+
+    /// Whether a user may work at this plant
+    pub fn allows(&self, username: &str) -> bool {
+        self.users.is_empty() || self.users.iter().any(|u| u.eq_ignore_ascii_case(username))
+    }
+}
+
+/// Prefix of the environment variables of a plant
+fn env_prefix(code: &str) -> String {
+    let segment: String = code
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("PLANT_{}_", segment)
+}
+
+fn list_var(var: &str) -> Vec<String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// All configured plants
+pub struct PlantRegistry {
+    plants: Vec<Arc<Plant>>,
+    default_code: String,
+}
+
+impl PlantRegistry {
+    /// Build the registry from `PLANTS`, or a single plant from `DB_*`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when a plant's database settings are missing, its
+    /// time zone is unknown or the default plant is not one of the
+    /// configured plants.
+    pub async fn from_env() -> Result<Self> {
+        let codes: Vec<String> = list_var(ENV_PLANTS)
+            .iter()
+            .map(|c| c.to_uppercase())
+            .collect();
+
+        if codes.is_empty() {
+            let pool = MssqlPool::from_env("").await?;
+            let plant = Plant::new(
+                SINGLE_PLANT_CODE.to_string(),
+                SINGLE_PLANT_CODE.to_string(),
+                pool,
+                PlantClock::from_env("")?,
+                MasterData::from_env("")?,
+                vec![],
+                String::new(),
+            );
+            return Self::new(vec![plant], None);
+        }
+
+        let mut plants = Vec::with_capacity(codes.len());
+        for code in codes {
+            let prefix = env_prefix(&code);
+            let pool = MssqlPool::from_env(&prefix)
+                .await
+                .with_context(|| format!("Invalid database settings for plant {}", code))?;
+            let clock = PlantClock::from_env(&prefix)
+                .with_context(|| format!("Invalid time zone for plant {}", code))?;
+            let master = MasterData::from_env(&prefix)
+                .with_context(|| format!("Invalid master data settings for plant {}", code))?;
+            let name = env::var(format!("{}NAME", prefix)).unwrap_or_else(|_| code.clone());
+            let users = list_var(&format!("{}USERS", prefix));
+            plants.push(Plant::new(code, name, pool, clock, master, users, prefix));
+        }
+
+        Self::new(plants, env::var(ENV_PLANT_DEFAULT).ok())
+    }
+
+    /// Build a registry from plants, defaulting to the first one
+    pub fn new(plants: Vec<Plant>, default_code: Option<String>) -> Result<Self> {
+        let Some(first) = plants.first() else {
+            bail!("At least one plant must be configured");
+        };
+        let default_code = default_code
+            .filter(|c| !c.trim().is_empty())
+            .map(|c| c.trim().to_uppercase())
+            .unwrap_or_else(|| first.code.clone());
+
+        for (i, plant) in plants.iter().enumerate() {
+            if plants[..i].iter().any(|p| p.code == plant.code) {
+                bail!("Plant {} is configured twice", plant.code);
+            }
+        }
+        if !plants.iter().any(|p| p.code == default_code) {
+            bail!(
+                "{} is not a configured plant: {}",
+                ENV_PLANT_DEFAULT,
+                default_code
+            );
+        }
+
+        Ok(Self {
+            plants: plants.into_iter().map(Arc::new).collect(),
+            default_code,
+        })
+    }
+
+    pub fn plants(&self) -> &[Arc<Plant>] {
+        &self.plants
+    }
+
+    /// Whether tokens must name the plants a user may reach
+    pub fn is_multi_plant(&self) -> bool {
+        self.plants.len() > 1
+    }
+
+    pub fn get(&self, code: &str) -> Option<&Arc<Plant>> {
+        self.plants
+            .iter()
+            .find(|p| p.code.eq_ignore_ascii_case(code.trim()))
+    }
+
+    pub fn default_plant(&self) -> &Arc<Plant> {
+        self.get(&self.default_code)
+            .expect("default plant is validated on construction")
+    }
+
+    /// Plant for a login, the default one when none is named
+    pub fn for_login(&self, code: Option<&str>) -> Option<&Arc<Plant>> {
+        match code.filter(|c| !c.trim().is_empty()) {
+            Some(code) => self.get(code),
+            None => Some(self.default_plant()),
+        }
+    }
+
+    /// Codes of the plants a user may reach; admins reach every plant
+    pub fn plants_for(&self, username: &str) -> Vec<String> {
+        let is_admin = roles_for(username).iter().any(|r| r == ROLE_ADMIN);
+        self.plants
+            .iter()
+            .filter(|p| is_admin || p.allows(username))
+            .map(|p| p.code.clone())
+            .collect()
+    }
+}
+
+/// The plant a request is for, checked against the caller's token
+#[derive(Clone)]
+pub struct CurrentPlant(pub Arc<Plant>);
+
+impl Deref for CurrentPlant {
+    type Target = Plant;
+
+    fn deref(&self) -> &Plant {
+        &self.0
+    }
+}
+
+fn plant_error(status: StatusCode, message: String) -> actix_web::Error {
+    InternalError::from_response(
+        message.clone(),
+        HttpResponse::build(status).json(json!({
+            "success": false,
+            "message": message,
+        })),
+    )
+    .into()
+}
+
+fn resolve_plant(req: &HttpRequest) -> Result<CurrentPlant, actix_web::Error> {
+    let registry = req.app_data::<web::Data<PlantRegistry>>().ok_or_else(|| {
+        plant_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Plants are not configured".to_string(),
+        )
+    })?;
+
+    let requested = req
+        .headers()
+        .get(PLANT_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty());
+
+    // A single plant keeps working without a token, as before plants existed;
+    // handlers that change data require one through AuthenticatedUser
+    if !registry.is_multi_plant() {
+        let plant = registry.default_plant();
+        return match requested {
+            Some(code) if !plant.code.eq_ignore_ascii_case(code) => Err(plant_error(
+                StatusCode::NOT_FOUND,
+                format!("Unknown plant: {}", code),
+            )),
+            _ => Ok(CurrentPlant(Arc::clone(plant))),
+        };
+    }
+
+    let user = authenticate_request(req)?;
+    let code = requested
+        .map(str::to_string)
+        .or_else(|| Some(user.plant.clone()).filter(|p| !p.is_empty()))
+        .unwrap_or_else(|| registry.default_plant().code.clone());
+
+    let plant = registry
+        .get(&code)
+        .ok_or_else(|| plant_error(StatusCode::NOT_FOUND, format!("Unknown plant: {}", code)))?;
+
+    if !user.can_access_plant(&plant.code) {
+        return Err(plant_error(
+            StatusCode::FORBIDDEN,
+            format!(
+                "{} is not authorized for plant {}",
+                user.username, plant.code
+            ),
+        ));
+    }
+
+    Ok(CurrentPlant(Arc::clone(plant)))
+}
+
+impl FromRequest for CurrentPlant {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(resolve_plant(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::connection::ConnectionSettings;
+    use crate::db::mssql::DbPolicy;
+    use crate::db::tls::DbTls;
+
+    fn plant(code: &str, users: &[&str]) -> Plant {
+        let settings = ConnectionSettings {
+            address: "localhost".parse().unwrap(),
+            database: code.to_string(),
+            username: "sa".to_string(),
+            password: "secret".to_string(),
+            application_name: "test".to_string(),
+            tls: DbTls::default(),
+        };
+        Plant::new(
+            code.to_string(),
+            code.to_string(),
+            MssqlPool::new(settings, DbPolicy::default()),
+            PlantClock::default(),
+            None,
+            users.iter().map(|u| u.to_string()).collect(),
+            env_prefix(code),
+        )
+    }
+
+    #[test]
+    fn test_env_prefix() {
+        assert_eq!(env_prefix("TH1"), "PLANT_TH1_");
+        assert_eq!(env_prefix("north-2"), "PLANT_NORTH_2_");
+    }
+
+    #[test]
+    fn test_registry_lookup_and_access() {
+        let registry = PlantRegistry::new(
+            vec![plant("TH1", &[]), plant("TH2", &["alice"])],
+            Some("th2".to_string()),
+        )
+        .unwrap();
+
+        assert!(registry.is_multi_plant());
+        assert_eq!(registry.default_plant().code, "TH2");
+        assert_eq!(registry.get("th1").unwrap().code, "TH1");
+        assert!(registry.for_login(Some("TH3")).is_none());
+        assert_eq!(registry.plants_for("alice"), vec!["TH1", "TH2"]);
+        assert_eq!(registry.plants_for("bob"), vec!["TH1"]);
+
+        assert!(PlantRegistry::new(vec![plant("TH1", &[])], Some("TH9".to_string())).is_err());
+    }
+}
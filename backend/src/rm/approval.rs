@@ -0,0 +1,387 @@
+//! Four-Eyes Approval
+//!
+//! Removals above a configured threshold are not applied directly. They are
+//! stored as pending requests in `cust_PartialPickRemovalApproval` and run
+//! through [`remove_items`](crate::rm::removal::remove_items) only after a
+//! different user with the supervisor role approves them.
+//!
+//! | Variable | Threshold | Default |
+//! |----------|-----------|---------|
+//! | `RM_APPROVAL_MAX_QTY` | Total ToPickedPartialQty removed in one request | off |
+//! | `RM_APPROVAL_MAX_PERCENT` | Removed qty as percent of a line's StandardQty | off |
+//! | `RM_APPROVAL_MAX_LINES` | Number of lines in one request | off |
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDateTime, Utc};
+use std::env;
+
+use crate::db::mssql::MssqlPool;
+use crate::db::row::column;
+use crate::models::qty::Qty;
+use crate::models::rm::{ApprovalRequest, ApprovalStatus, RemoveItem};
+use crate::plant::PlantClock;
+use crate::rm::lines::{
+    map_rm_line, CONCURRENCY_TOKEN_EXPR, ELIGIBLE_FOR_REMOVAL, RM_LINE_COLUMNS,
+};
+use crate::rm::reason::RemovalReason;
+use crate::rm::removal::{plan, PlannedRemoval};
+
+const ENV_MAX_QTY: &str = "RM_APPROVAL_MAX_QTY";
+const ENV_MAX_PERCENT: &str = "RM_APPROVAL_MAX_PERCENT";
+const ENV_MAX_LINES: &str = "RM_APPROVAL_MAX_LINES";
+
+/// Thresholds above which a removal needs supervisor sign-off
+#[derive(Debug, Clone, Default)]
+pub struct ApprovalPolicy {
+    pub max_qty: Option<Qty>,
+    pub max_percent: Option<Qty>,
+    pub max_lines: Option<usize>,
+}
+
+impl ApprovalPolicy {
+    /// Load thresholds from the environment; unset or invalid values disable them
+    pub fn from_env() -> Self {
+        fn parse<T: std::str::FromStr>(var: &str) -> Option<T> {
+            env::var(var).ok().and_then(|v| v.trim().parse::<T>().ok())
+        }
+
+        Self {
+            max_qty: parse::<Qty>(ENV_MAX_QTY).filter(|v| !v.is_negative()),
+            max_percent: parse::<Qty>(ENV_MAX_PERCENT).filter(|v| !v.is_negative()),
+            max_lines: parse::<usize>(ENV_MAX_LINES),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_qty.is_some() || self.max_percent.is_some() || self.max_lines.is_some()
+    }
+
+    /// Return a description of the first threshold the removal exceeds
+    pub fn evaluate(&self, planned: &[PlannedRemoval]) -> Option<String> {
+        if let Some(max_lines) = self.max_lines {
+            if planned.len() > max_lines {
+                return Some(format!(
+                    "{} lines exceeds limit of {}",
+                    planned.len(),
+                    max_lines
+                ));
+            }
+        }
+
+        if let Some(max_qty) = self.max_qty {
+            let total: Qty = planned.iter().map(|p| p.remove_qty).sum();
+            if total > max_qty {
+                return Some(format!(
+                    "total quantity {} exceeds limit of {}",
+                    total, max_qty
+                ));
+            }
+        }
+
+        if let Some(max_percent) = self.max_percent {
+            for p in planned.iter().filter(|p| p.line.standard_qty.is_positive()) {
+                if p.remove_qty
+                    .exceeds_percent_of(p.line.standard_qty, max_percent)
+                {
+                    return Some(format!(
+                        "line (Row: {}, Line: {}) removes {:.1}% of StandardQty, limit is {}%",
+                        p.line.row_num,
+                        p.line.line_id,
+                        p.remove_qty.percent_of(p.line.standard_qty),
+                        max_percent
+                    ));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Current state of the lines a removal targets, with the planned change;
+/// items whose line is gone or no longer eligible are left out, see
+/// [`unplanned`](crate::rm::removal::unplanned)
+pub async fn plan_for_items(
+    pool: &MssqlPool,
+    run_no: i32,
+    items: &[RemoveItem],
+) -> Result<Vec<PlannedRemoval>> {
+    let sql = format!(
+        r#"
+        SELECT {}, {} AS ConcurrencyToken
+        FROM cust_PartialPicked
+        WHERE RunNo = @P1 AND {}
+        "#,
+        RM_LINE_COLUMNS, CONCURRENCY_TOKEN_EXPR, ELIGIBLE_FOR_REMOVAL
+    );
+
+    let lines = pool
+        .execute_read_with_params(
+            &sql,
+            |query| {
+                query.bind(run_no);
+            },
+            map_rm_line,
+        )
+        .await?;
+
+    Ok(plan(&lines, items))
+}
+
+const APPROVAL_COLUMNS: &str = r#"
+            RequestId, RunNo, ItemsJson, LineCount, TotalQty, ReasonCode, Reason, Threshold, Status,
+            RequestedBy, RequestedDate, DecidedBy, DecidedDate, DecisionComment, AffectedRows"#;
+
+fn map_approval(row: &tiberius::Row, clock: &PlantClock) -> Result<ApprovalRequest> {
+    let items: Vec<RemoveItem> = serde_json::from_str(column(row, "ItemsJson")?)
+        .context("Invalid ItemsJson in approval request")?;
+
+    Ok(ApprovalRequest {
+        request_id: column(row, "RequestId")?,
+        run_no: column(row, "RunNo")?,
+        items,
+        line_count: column(row, "LineCount")?,
+        total_qty: column(row, "TotalQty")?,
+        // Added by sql/002 as nullable for requests stored before reason codes
+        reason_code: column::<Option<String>>(row, "ReasonCode")?.unwrap_or_default(),
+        reason: column(row, "Reason")?,
+        threshold: column(row, "Threshold")?,
+        status: ApprovalStatus::from_db(column(row, "Status")?),
+        requested_by: column(row, "RequestedBy")?,
+        requested_date: clock.format(column(row, "RequestedDate")?),
+        decided_by: column(row, "DecidedBy")?,
+        decided_date: column::<Option<NaiveDateTime>>(row, "DecidedDate")?.map(|d| clock.format(d)),
+        decision_comment: column(row, "DecisionComment")?,
+        affected_rows: column(row, "AffectedRows")?,
+    })
+}
+
+/// Store a pending removal and return its id
+pub async fn create_request(
+    pool: &MssqlPool,
+    run_no: i32,
+    planned: &[PlannedRemoval],
+    reason: &RemovalReason,
+    threshold: &str,
+    requested_by: &str,
+) -> Result<i32> {
+    // Items carry the tokens so an approval refuses lines that changed meanwhile
+    let items: Vec<&RemoveItem> = planned.iter().map(|p| &p.item).collect();
+    let items_json = serde_json::to_string(&items)?;
+    let total_qty: Qty = planned.iter().map(|p| p.remove_qty).sum();
+    let line_count = planned.len() as i32;
+
+    let sql = r#"
+        INSERT INTO cust_PartialPickRemovalApproval
+            (RunNo, ItemsJson, LineCount, TotalQty, ReasonCode, Reason, Threshold, Status,
+             RequestedBy, RequestedDate)
+        OUTPUT inserted.RequestId
+        VALUES (@P1, @P2, @P3, @P4, @P8, @P5, @P6, 'PENDING', @P7, @P9)
+    "#;
+
+    let ids = pool
+        .execute_query_with_params(
+            sql,
+            |query| {
+                query.bind(run_no);
+                query.bind(items_json);
+                query.bind(line_count);
+                query.bind(total_qty);
+                query.bind(reason.comment.clone().unwrap_or_default());
+                query.bind(threshold.to_string());
+                query.bind(requested_by.to_string());
+                query.bind(reason.code.clone());
+                query.bind(Utc::now().naive_utc());
+            },
+            |row| Ok(column::<i32>(row, "RequestId")?),
+        )
+        .await?;
+
+    ids.into_iter()
+        .next()
+        .context("Insert did not return a RequestId")
+}
+
+/// List approval requests, optionally filtered by status and run
+pub async fn list_requests(
+    pool: &MssqlPool,
+    clock: &PlantClock,
+    status: Option<ApprovalStatus>,
+    run_no: Option<i32>,
+) -> Result<Vec<ApprovalRequest>> {
+    let sql = format!(
+        r#"
+        SELECT TOP 500 {}
+        FROM cust_PartialPickRemovalApproval
+        WHERE (@P1 IS NULL OR Status = @P1)
+          AND (@P2 IS NULL OR RunNo = @P2)
+        ORDER BY RequestId DESC
+        "#,
+        APPROVAL_COLUMNS
+    );
+
+    pool.execute_read_with_params(
+        &sql,
+        |query| {
+            query.bind(status.map(|s| s.as_db().to_string()));
+            query.bind(run_no);
+        },
+        |row| map_approval(row, clock),
+    )
+    .await
+}
+
+pub async fn get_request(
+    pool: &MssqlPool,
+    clock: &PlantClock,
+    request_id: i32,
+) -> Result<Option<ApprovalRequest>> {
+    let sql = format!(
+        "SELECT {} FROM cust_PartialPickRemovalApproval WHERE RequestId = @P1",
+        APPROVAL_COLUMNS
+    );
+
+    let rows = pool
+        .execute_read_with_params(
+            &sql,
+            |query| {
+                query.bind(request_id);
+            },
+            |row| map_approval(row, clock),
+        )
+        .await?;
+
+    Ok(rows.into_iter().next())
+}
+
+/// Move a pending request to `status`; returns false if it was no longer pending
+pub async fn decide_request(
+    pool: &MssqlPool,
+    request_id: i32,
+    status: ApprovalStatus,
+    decided_by: &str,
+    comment: Option<String>,
+) -> Result<bool> {
+    let sql = r#"
+        UPDATE cust_PartialPickRemovalApproval
+        SET Status = @P2, DecidedBy = @P3, DecidedDate = @P5, DecisionComment = @P4
+        WHERE RequestId = @P1 AND Status = 'PENDING'
+    "#;
+
+    let affected = pool
+        .execute_update(sql, |query| {
+            query.bind(request_id);
+            query.bind(status.as_db().to_string());
+            query.bind(decided_by.to_string());
+            query.bind(comment);
+            query.bind(Utc::now().naive_utc());
+        })
+        .await?;
+
+    Ok(affected > 0)
+}
+
+/// Record how many rows the approved removal changed
+pub async fn record_result(pool: &MssqlPool, request_id: i32, affected_rows: u64) -> Result<()> {
+    let sql = "UPDATE cust_PartialPickRemovalApproval SET AffectedRows = @P2 WHERE RequestId = @P1";
+
+    pool.execute_idempotent_update(sql, |query| {
+        query.bind(request_id);
+        query.bind(affected_rows as i32);
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::rm::RMLine;
+    use crate::rm::removal::plan_full;
+
+    fn line(row_num: i32, standard_qty: Qty, to_pick: Qty) -> RMLine {
+        RMLine {
+            run_no: 1001,
+            row_num,
+            batch_no: "B1".to_string(),
+            line_typ: "FI".to_string(),
+            line_id: 1,
+            item_key: "ITEM".to_string(),
+            location: "TFC1".to_string(),
+            unit: "KG".to_string(),
+            standard_qty,
+            pack_size: Qty::from(25),
+            to_picked_partial_qty: to_pick,
+            picked_partial_qty: None,
+            rec_user_id: "".to_string(),
+            modified_by: "".to_string(),
+            concurrency_token: "".to_string(),
+            item_description: None,
+            item_class: None,
+            storage_condition: None,
+            location_description: None,
+        }
+    }
+
+    #[test]
+    fn test_disabled_policy() {
+        let policy = ApprovalPolicy::default();
+        assert!(!policy.is_enabled());
+        assert!(policy
+            .evaluate(&plan_full(&[line(1, Qty::from(10), Qty::from(10))]))
+            .is_none());
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let lines = plan_full(&[
+            line(1, Qty::from(100), Qty::from(30)),
+            line(2, Qty::from(100), Qty::from(30)),
+        ]);
+
+        let by_lines = ApprovalPolicy {
+            max_lines: Some(1),
+            ..Default::default()
+        };
+        assert!(by_lines.evaluate(&lines).is_some());
+
+        let by_qty = ApprovalPolicy {
+            max_qty: Some(Qty::from(50)),
+            ..Default::default()
+        };
+        assert!(by_qty.evaluate(&lines).is_some());
+        assert!(by_qty.evaluate(&lines[..1]).is_none());
+
+        let by_percent = ApprovalPolicy {
+            max_percent: Some(Qty::from(25)),
+            ..Default::default()
+        };
+        assert!(by_percent.evaluate(&lines).is_some());
+        assert!(by_percent
+            .evaluate(&plan_full(&[line(1, Qty::from(100), Qty::from(20))]))
+            .is_none());
+    }
+
+    #[test]
+    fn test_partial_reduction_counts_only_removed_qty() {
+        let lines = vec![line(1, Qty::from(100), Qty::from(80))];
+        let items = vec![RemoveItem {
+            row_num: 1,
+            line_id: 1,
+            concurrency_token: None,
+            new_qty: Some(Qty::from(70)),
+            reduce_by: None,
+            round_to_pack_size: false,
+        }];
+
+        let planned = plan(&lines, &items);
+        assert_eq!(planned[0].remove_qty, Qty::from(10));
+
+        let by_qty = ApprovalPolicy {
+            max_qty: Some(Qty::from(50)),
+            ..Default::default()
+        };
+        assert!(by_qty.evaluate(&planned).is_none());
+    }
+}
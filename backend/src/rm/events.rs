@@ -0,0 +1,446 @@
+//! Live Run Events
+//!
+//! Broadcasts line changes to stations watching a run over Server-Sent
+//! Events. Changes made through this API are published by the handlers that
+//! make them. Optionally, a background poller re-reads `cust_PartialPicked`
+//! for watched runs and publishes changes made by other systems, such as a
+//! picker completing a line at the scale.
+//!
+//! | Variable | Description | Default |
+//! |----------|-------------|---------|
+//! | `RM_EVENTS_POLL_SECS` | Poll interval for external changes, `0` disables | `0` |
+
+use chrono::{DateTime, Utc};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::db::mssql::MssqlPool;
+use crate::db::row::column;
+use crate::models::qty::{Qty, QTY_SQL_TYPE};
+use crate::models::rm::{RMLine, RemoveItemStatus};
+use crate::rm::removal::RemovalOutcome;
+
+const ENV_POLL_SECS: &str = "RM_EVENTS_POLL_SECS";
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunEventKind {
+    /// ToPickedPartialQty was lowered or zeroed
+    Removed,
+    /// ToPickedPartialQty went back up
+    Restored,
+    /// PickedPartialQty changed, i.e. the line was picked
+    PickStatusChanged,
+}
+
+impl RunEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunEventKind::Removed => "removed",
+            RunEventKind::Restored => "restored",
+            RunEventKind::PickStatusChanged => "pick_status_changed",
+        }
+    }
+}
+
+/// Where a change was observed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunEventSource {
+    Api,
+    Poll,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunEvent {
+    pub kind: RunEventKind,
+    pub source: RunEventSource,
+    pub run_no: i32,
+    pub row_num: i32,
+    pub line_id: i32,
+    pub qty_before: Option<Qty>,
+    pub qty_after: Option<Qty>,
+    pub picked_qty: Option<Qty>,
+    pub user: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+/// ToPickedPartialQty and PickedPartialQty of one line
+type LineState = (Qty, Option<Qty>);
+type RunSnapshot = HashMap<(i32, i32), LineState>;
+
+/// Fan-out of run events to all connected stations
+#[derive(Debug)]
+pub struct RunEventHub {
+    sender: broadcast::Sender<RunEvent>,
+    /// Open subscriptions per run; the poller only reads watched runs
+    watchers: Mutex<HashMap<i32, usize>>,
+    /// Last known line state per watched run, used to detect external changes
+    snapshots: Mutex<HashMap<i32, RunSnapshot>>,
+}
+
+impl Default for RunEventHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunEventHub {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            sender,
+            watchers: Mutex::new(HashMap::new()),
+            snapshots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn publish(&self, event: RunEvent) {
+        // Remember API changes so the poller does not report them again
+        if let Some(snapshot) = self.snapshots.lock().unwrap().get_mut(&event.run_no) {
+            if let Some(state) = snapshot.get_mut(&(event.row_num, event.line_id)) {
+                if let Some(after) = event.qty_after {
+                    state.0 = after;
+                }
+                if event.picked_qty.is_some() {
+                    state.1 = event.picked_qty;
+                }
+            }
+        }
+
+        // No receivers is not an error, nobody is watching
+        let _ = self.sender.send(event);
+    }
+
+    /// Publish a `removed` event for every line a removal changed
+    pub fn publish_removals(&self, run_no: i32, outcome: &RemovalOutcome, user: &str) {
+        let now = Utc::now();
+        for result in outcome
+            .results
+            .iter()
+            .filter(|r| r.status == RemoveItemStatus::Removed)
+        {
+            self.publish(RunEvent {
+                kind: RunEventKind::Removed,
+                source: RunEventSource::Api,
+                run_no,
+                row_num: result.row_num,
+                line_id: result.line_id,
+                qty_before: result.qty_before,
+                qty_after: result.qty_after.or(Some(Qty::ZERO)),
+                picked_qty: None,
+                user: Some(user.to_string()),
+                at: now,
+            });
+        }
+    }
+
+    /// Publish a `removed` event for every line a bulk removal zeroed
+    pub fn publish_zeroed_lines(&self, run_no: i32, lines: &[RMLine], user: &str) {
+        let now = Utc::now();
+        for line in lines {
+            self.publish(RunEvent {
+                kind: RunEventKind::Removed,
+                source: RunEventSource::Api,
+                run_no,
+                row_num: line.row_num,
+                line_id: line.line_id,
+                qty_before: Some(line.to_picked_partial_qty),
+                qty_after: Some(Qty::ZERO),
+                picked_qty: None,
+                user: Some(user.to_string()),
+                at: now,
+            });
+        }
+    }
+
+    /// Start receiving events for a run; dropping the subscription unregisters it
+    pub fn subscribe(self: &Arc<Self>, run_no: i32) -> RunSubscription {
+        *self.watchers.lock().unwrap().entry(run_no).or_insert(0) += 1;
+        RunSubscription {
+            hub: Arc::clone(self),
+            run_no,
+            receiver: Some(self.sender.subscribe()),
+        }
+    }
+
+    fn unsubscribe(&self, run_no: i32) {
+        let mut watchers = self.watchers.lock().unwrap();
+        if let Some(count) = watchers.get_mut(&run_no) {
+            *count -= 1;
+            if *count == 0 {
+                watchers.remove(&run_no);
+                self.snapshots.lock().unwrap().remove(&run_no);
+            }
+        }
+    }
+
+    fn watched_runs(&self) -> Vec<i32> {
+        self.watchers.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Compare a fresh read of a run with the last one and publish the differences
+    fn apply_snapshot(&self, run_no: i32, current: RunSnapshot) {
+        // Held while storing so a read finishing after the last station left
+        // cannot bring back the snapshot `unsubscribe` just dropped
+        let watchers = self.watchers.lock().unwrap();
+        if !watchers.contains_key(&run_no) {
+            return;
+        }
+        let previous = self
+            .snapshots
+            .lock()
+            .unwrap()
+            .insert(run_no, current.clone());
+        drop(watchers);
+
+        // The first read only establishes the baseline
+        if let Some(previous) = previous {
+            for event in diff_snapshots(run_no, &previous, &current, Utc::now()) {
+                let _ = self.sender.send(event);
+            }
+        }
+    }
+}
+
+/// A station's registration for one run
+pub struct RunSubscription {
+    hub: Arc<RunEventHub>,
+    run_no: i32,
+    receiver: Option<broadcast::Receiver<RunEvent>>,
+}
+
+impl RunSubscription {
+    pub fn run_no(&self) -> i32 {
+        self.run_no
+    }
+
+    /// Take the receiver out; the subscription must be kept alive alongside it
+    pub fn take_receiver(&mut self) -> broadcast::Receiver<RunEvent> {
+        self.receiver
+            .take()
+            .expect("receiver is only taken once per subscription")
+    }
+}
+
+impl Drop for RunSubscription {
+    fn drop(&mut self) {
+        self.hub.unsubscribe(self.run_no);
+    }
+}
+
+/// Events describing how a run changed between two reads
+fn diff_snapshots(
+    run_no: i32,
+    previous: &RunSnapshot,
+    current: &RunSnapshot,
+    at: DateTime<Utc>,
+) -> Vec<RunEvent> {
+    let mut events = vec![];
+
+    for (&(row_num, line_id), &(to_pick, picked)) in current {
+        let Some(&(old_to_pick, old_picked)) = previous.get(&(row_num, line_id)) else {
+            continue;
+        };
+
+        let event = |kind| RunEvent {
+            kind,
+            source: RunEventSource::Poll,
+            run_no,
+            row_num,
+            line_id,
+            qty_before: Some(old_to_pick),
+            qty_after: Some(to_pick),
+            picked_qty: picked,
+            user: None,
+            at,
+        };
+
+        if picked != old_picked {
+            events.push(event(RunEventKind::PickStatusChanged));
+        } else if to_pick < old_to_pick {
+            events.push(event(RunEventKind::Removed));
+        } else if to_pick > old_to_pick {
+            events.push(event(RunEventKind::Restored));
+        }
+    }
+
+    events.sort_by_key(|e| (e.row_num, e.line_id));
+    events
+}
+
+async fn read_run_state(pool: &MssqlPool, run_no: i32) -> anyhow::Result<RunSnapshot> {
+    let sql = format!(
+        r#"
+        SELECT
+            RowNum,
+            LineId,
+            CAST(ToPickedPartialQty AS {qty}) AS ToPickedPartialQty,
+            CAST(PickedPartialQty AS {qty}) AS PickedPartialQty
+        FROM cust_PartialPicked
+        WHERE RunNo = @P1
+        "#,
+        qty = QTY_SQL_TYPE
+    );
+
+    let rows = pool
+        .execute_read_with_params(
+            &sql,
+            |query| {
+                query.bind(run_no);
+            },
+            |row| {
+                Ok((
+                    (column(row, "RowNum")?, column(row, "LineId")?),
+                    (
+                        column(row, "ToPickedPartialQty")?,
+                        column(row, "PickedPartialQty")?,
+                    ),
+                ))
+            },
+        )
+        .await?;
+
+    Ok(rows.into_iter().collect())
+}
+
+/// Poll interval from `RM_EVENTS_POLL_SECS`, `None` when polling is off
+pub fn poll_interval_from_env() -> Option<Duration> {
+    env::var(ENV_POLL_SECS)
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+}
+
+/// Detect changes made outside this API on watched runs
+pub fn spawn_poller(hub: Arc<RunEventHub>, pool: MssqlPool, interval: Duration) {
+    info!(
+        "Polling watched runs for external changes every {} seconds",
+        interval.as_secs()
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            for run_no in hub.watched_runs() {
+                match read_run_state(&pool, run_no).await {
+                    Ok(state) => hub.apply_snapshot(run_no, state),
+                    Err(e) => error!("Failed to poll RunNo {} for changes: {}", run_no, e),
+                }
+            }
+            debug!("Polled watched runs for changes");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_snapshots() {
+        let previous: RunSnapshot = [
+            ((1, 1), (Qty::from(10), None)),
+            ((2, 1), (Qty::from(5), None)),
+            ((3, 1), (Qty::ZERO, None)),
+            ((4, 1), (Qty::from(7), None)),
+        ]
+        .into_iter()
+        .collect();
+        let current: RunSnapshot = [
+            ((1, 1), (Qty::from(10), Some(Qty::from(10)))),
+            ((2, 1), (Qty::ZERO, None)),
+            ((3, 1), (Qty::from(4), None)),
+            ((4, 1), (Qty::from(7), None)),
+            ((5, 1), (Qty::from(1), None)),
+        ]
+        .into_iter()
+        .collect();
+
+        let events = diff_snapshots(1001, &previous, &current, Utc::now());
+        let kinds: Vec<_> = events.iter().map(|e| (e.row_num, e.kind)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (1, RunEventKind::PickStatusChanged),
+                (2, RunEventKind::Removed),
+                (3, RunEventKind::Restored),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_receives_run_events() {
+        let hub = Arc::new(RunEventHub::new());
+        let mut subscription = hub.subscribe(1001);
+        let mut receiver = subscription.take_receiver();
+        assert_eq!(hub.watched_runs(), vec![1001]);
+
+        hub.publish(RunEvent {
+            kind: RunEventKind::Removed,
+            source: RunEventSource::Api,
+            run_no: 1001,
+            row_num: 1,
+            line_id: 1,
+            qty_before: Some(Qty::from(10)),
+            qty_after: Some(Qty::ZERO),
+            picked_qty: None,
+            user: Some("alice".to_string()),
+            at: Utc::now(),
+        });
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.kind, RunEventKind::Removed);
+
+        drop(subscription);
+        assert!(hub.watched_runs().is_empty());
+    }
+
+    #[test]
+    fn test_api_changes_update_snapshot() {
+        let hub = Arc::new(RunEventHub::new());
+        let _subscription = hub.subscribe(1001);
+        hub.apply_snapshot(
+            1001,
+            [((1, 1), (Qty::from(10), None))].into_iter().collect(),
+        );
+
+        hub.publish(RunEvent {
+            kind: RunEventKind::Removed,
+            source: RunEventSource::Api,
+            run_no: 1001,
+            row_num: 1,
+            line_id: 1,
+            qty_before: Some(Qty::from(10)),
+            qty_after: Some(Qty::ZERO),
+            picked_qty: None,
+            user: None,
+            at: Utc::now(),
+        });
+
+        let snapshots = hub.snapshots.lock().unwrap();
+        assert_eq!(snapshots[&1001][&(1, 1)], (Qty::ZERO, None));
+    }
+
+    #[test]
+    fn test_no_snapshot_after_unsubscribe() {
+        let hub = Arc::new(RunEventHub::new());
+        let subscription = hub.subscribe(1001);
+        drop(subscription);
+
+        // A poll that read the run before the station left
+        hub.apply_snapshot(
+            1001,
+            [((1, 1), (Qty::from(10), None))].into_iter().collect(),
+        );
+        assert!(hub.snapshots.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+//! Removal History
+//!
+//! Reads `cust_PartialPickRemovalAudit` for the history endpoint and renders
+//! it as CSV for exports.
+
+use anyhow::Result;
+
+use crate::db::mssql::MssqlPool;
+use crate::db::row::column;
+use crate::models::rm::RemovalAuditEntry;
+use crate::plant::PlantClock;
+
+/// Audit columns with the reason description, for `cust_PartialPickRemovalAudit a`
+/// joined to `cust_PartialPickReasonCode r`
+pub const AUDIT_ENTRY_COLUMNS: &str = r#"
+    a.AuditId, a.RunNo, a.RowNum, a.LineId, a.BatchNo, a.ItemKey,
+    a.QtyBefore, a.QtyAfter, a.ReasonCode,
+    ISNULL(r.Description, a.ReasonCode) AS ReasonDescription,
+    a.Comment, a.RemovedBy, a.ApprovedBy, a.ApprovalRequestId,
+    a.RemovedDate"#;
+
+/// Map an audit row, showing `RemovedDate` in the plant's time zone
+pub fn map_audit_entry(row: &tiberius::Row, clock: &PlantClock) -> Result<RemovalAuditEntry> {
+    Ok(RemovalAuditEntry {
+        audit_id: column(row, "AuditId")?,
+        run_no: column(row, "RunNo")?,
+        row_num: column(row, "RowNum")?,
+        line_id: column(row, "LineId")?,
+        batch_no: column::<Option<String>>(row, "BatchNo")?.unwrap_or_default(),
+        item_key: column::<Option<String>>(row, "ItemKey")?.unwrap_or_default(),
+        qty_before: column(row, "QtyBefore")?,
+        qty_after: column(row, "QtyAfter")?,
+        reason_code: column(row, "ReasonCode")?,
+        reason_description: column(row, "ReasonDescription")?,
+        comment: column(row, "Comment")?,
+        removed_by: column(row, "RemovedBy")?,
+        approved_by: column(row, "ApprovedBy")?,
+        approval_request_id: column(row, "ApprovalRequestId")?,
+        removed_date: clock.format(column(row, "RemovedDate")?),
+    })
+}
+
+/// All removals recorded for a run, newest first
+pub async fn run_history(
+    pool: &MssqlPool,
+    clock: &PlantClock,
+    run_no: i32,
+) -> Result<Vec<RemovalAuditEntry>> {
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM cust_PartialPickRemovalAudit a
+        LEFT JOIN cust_PartialPickReasonCode r ON r.ReasonCode = a.ReasonCode
+        WHERE a.RunNo = @P1
+        ORDER BY a.RemovedDate DESC, a.AuditId DESC
+        "#,
+        AUDIT_ENTRY_COLUMNS
+    );
+
+    pool.execute_read_with_params(
+        &sql,
+        |query| {
+            query.bind(run_no);
+        },
+        |row| map_audit_entry(row, clock),
+    )
+    .await
+}
+
+/// A single audit entry
+pub async fn audit_entry(
+    pool: &MssqlPool,
+    clock: &PlantClock,
+    audit_id: i32,
+) -> Result<Option<RemovalAuditEntry>> {
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM cust_PartialPickRemovalAudit a
+        LEFT JOIN cust_PartialPickReasonCode r ON r.ReasonCode = a.ReasonCode
+        WHERE a.AuditId = @P1
+        "#,
+        AUDIT_ENTRY_COLUMNS
+    );
+
+    let entries = pool
+        .execute_read_with_params(
+            &sql,
+            |query| {
+                query.bind(audit_id);
+            },
+            |row| map_audit_entry(row, clock),
+        )
+        .await?;
+
+    Ok(entries.into_iter().next())
+}
+
+/// Render history entries as CSV with a header row
+pub fn to_csv(entries: &[RemovalAuditEntry]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for entry in entries {
+        writer.serialize(entry)?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::qty::Qty;
+
+    #[test]
+    fn test_to_csv() {
+        let entry = RemovalAuditEntry {
+            audit_id: 1,
+            run_no: 1001,
+            row_num: 2,
+            line_id: 3,
+            batch_no: "B1".to_string(),
+            item_key: "SUGAR".to_string(),
+            qty_before: "12.5".parse().unwrap(),
+            qty_after: Qty::ZERO,
+            reason_code: "OTHER".to_string(),
+            reason_description: "Other".to_string(),
+            comment: Some("spilled, re-weigh".to_string()),
+            removed_by: "alice".to_string(),
+            approved_by: None,
+            approval_request_id: None,
+            removed_date: "2026-01-02T10:04:05+07:00".to_string(),
+        };
+
+        let csv = to_csv(&[entry]).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("AuditId,RunNo,RowNum,LineId"));
+        let row = lines.next().unwrap();
+        assert!(row.contains(",12.5,0.0,OTHER,"));
+        assert!(row.contains("\"spilled, re-weigh\""));
+        assert!(row.ends_with(",alice,,,2026-01-02T10:04:05+07:00"));
+    }
+}
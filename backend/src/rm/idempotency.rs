@@ -0,0 +1,229 @@
+//! Idempotency Keys
+//!
+//! Remove requests may carry an `Idempotency-Key` header. The first response
+//! for a key and signed-in user is kept for the configured window and
+//! replayed for repeats, so a retry after a dropped connection does not run
+//! the removal again. A repeat with a different body is refused. The removal
+//! runs in its own task, so it finishes and its response is stored even when
+//! the client that started it has gone away.
+//!
+//! | Variable | Description | Default |
+//! |----------|-------------|---------|
+//! | `RM_IDEMPOTENCY_TTL_SECS` | How long a stored response is replayed | `86400` |
+
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+
+const ENV_TTL_SECS: &str = "RM_IDEMPOTENCY_TTL_SECS";
+const DEFAULT_TTL_SECS: u64 = 86_400;
+
+/// Longest key accepted in the header
+pub const MAX_KEY_LEN: usize = 255;
+
+/// A response kept for replay
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredResponse {
+    pub status: StatusCode,
+    pub body: Bytes,
+}
+
+#[derive(Debug, Clone)]
+enum EntryState {
+    InFlight,
+    Completed(StoredResponse),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    fingerprint: String,
+    state: EntryState,
+    expires_at: DateTime<Utc>,
+}
+
+/// Why a request with an idempotency key cannot run
+#[derive(Error, Debug, PartialEq)]
+pub enum IdempotencyError {
+    #[error("Idempotency-Key was already used with a different request")]
+    Mismatch,
+
+    #[error("A request with this Idempotency-Key is still being processed")]
+    InFlight,
+}
+
+/// What to do with a request that carries a key
+#[derive(Debug)]
+pub enum Claim {
+    /// First use of the key: run the request and complete the guard
+    New(IdempotencyGuard),
+    /// Seen before: send the stored response again
+    Replay(StoredResponse),
+}
+
+type EntryKey = (String, String);
+
+/// In-memory table of keys and their responses shared by all workers
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    entries: Arc<Mutex<HashMap<EntryKey, Entry>>>,
+    ttl: Duration,
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl,
+        }
+    }
+
+    /// Build a store with the window from `RM_IDEMPOTENCY_TTL_SECS`
+    pub fn from_env() -> Self {
+        let ttl_secs = env::var(ENV_TTL_SECS)
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TTL_SECS);
+        Self::new(Duration::from_secs(ttl_secs))
+    }
+
+    /// Hash of the request body used to detect a key reused for another request
+    pub fn fingerprint(body: &[u8]) -> String {
+        hex::encode(Sha256::digest(body))
+    }
+
+    /// Reserve a key for a user, or return the response already stored for it
+    pub fn claim(
+        &self,
+        user: &str,
+        key: &str,
+        fingerprint: &str,
+    ) -> Result<Claim, IdempotencyError> {
+        let now = Utc::now();
+        let entry_key = (user.to_string(), key.to_string());
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, e| e.expires_at > now);
+
+        if let Some(entry) = entries.get(&entry_key) {
+            if entry.fingerprint != fingerprint {
+                return Err(IdempotencyError::Mismatch);
+            }
+            return match &entry.state {
+                EntryState::InFlight => Err(IdempotencyError::InFlight),
+                EntryState::Completed(response) => Ok(Claim::Replay(response.clone())),
+            };
+        }
+
+        entries.insert(
+            entry_key.clone(),
+            Entry {
+                fingerprint: fingerprint.to_string(),
+                state: EntryState::InFlight,
+                expires_at: now
+                    + ChronoDuration::from_std(self.ttl).unwrap_or(ChronoDuration::zero()),
+            },
+        );
+
+        Ok(Claim::New(IdempotencyGuard {
+            entries: Arc::clone(&self.entries),
+            key: Some(entry_key),
+        }))
+    }
+}
+
+/// Reservation of a key while its request runs. Dropping it without
+/// [`complete`](Self::complete) frees the key so the request can be retried.
+#[derive(Debug)]
+pub struct IdempotencyGuard {
+    entries: Arc<Mutex<HashMap<EntryKey, Entry>>>,
+    key: Option<EntryKey>,
+}
+
+impl IdempotencyGuard {
+    /// Keep the response for replay
+    pub fn complete(mut self, response: StoredResponse) {
+        if let Some(key) = self.key.take() {
+            if let Some(entry) = self.entries.lock().unwrap().get_mut(&key) {
+                entry.state = EntryState::Completed(response);
+            }
+        }
+    }
+}
+
+impl Drop for IdempotencyGuard {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.entries.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(body: &'static str) -> StoredResponse {
+        StoredResponse {
+            status: StatusCode::OK,
+            body: Bytes::from_static(body.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_replays_completed_response() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let fp = IdempotencyStore::fingerprint(b"{\"run_no\":1}");
+
+        match store.claim("alice", "k1", &fp).unwrap() {
+            Claim::New(guard) => {
+                assert_eq!(
+                    store.claim("alice", "k1", &fp).unwrap_err(),
+                    IdempotencyError::InFlight
+                );
+                guard.complete(stored("first"));
+            }
+            Claim::Replay(_) => panic!("first claim must be new"),
+        }
+
+        match store.claim("alice", "k1", &fp).unwrap() {
+            Claim::Replay(response) => assert_eq!(response, stored("first")),
+            Claim::New(_) => panic!("repeat must replay"),
+        }
+
+        // Keys are scoped per user
+        assert!(matches!(store.claim("bob", "k1", &fp), Ok(Claim::New(_))));
+    }
+
+    #[test]
+    fn test_rejects_different_body() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let Ok(Claim::New(guard)) = store.claim("alice", "k1", "aaa") else {
+            panic!("first claim must be new");
+        };
+        guard.complete(stored("first"));
+
+        assert_eq!(
+            store.claim("alice", "k1", "bbb").unwrap_err(),
+            IdempotencyError::Mismatch
+        );
+    }
+
+    #[test]
+    fn test_dropped_guard_frees_key() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let Ok(Claim::New(guard)) = store.claim("alice", "k1", "aaa") else {
+            panic!("first claim must be new");
+        };
+        drop(guard);
+
+        assert!(matches!(
+            store.claim("alice", "k1", "bbb"),
+            Ok(Claim::New(_))
+        ));
+    }
+}
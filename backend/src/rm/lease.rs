@@ -0,0 +1,304 @@
+//! Run Leases
+//!
+//! Keeps track of which station is working on a RunNo. A lease is acquired
+//! for a run, renewed by heartbeat and released when the operator is done.
+//! Leases that are not renewed expire after the configured TTL.
+//!
+//! An exclusive lease blocks removals from everyone but its holder presenting
+//! its `lease_id`; an advisory lease is only shown to other stations. The id
+//! is only handed to the holder when the lease is acquired and is never
+//! serialized otherwise, and renewing or releasing also requires the holder.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+const ENV_LEASE_TTL_SECS: &str = "RM_LEASE_TTL_SECS";
+const DEFAULT_LEASE_TTL_SECS: u64 = 120;
+
+/// A lease held on one run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunLease {
+    /// Secret of the holder; returned only by acquire, see `LeaseResponse`
+    #[serde(default, skip_serializing)]
+    pub lease_id: String,
+    pub run_no: i32,
+    pub holder: String,
+    pub station: Option<String>,
+    pub exclusive: bool,
+    pub acquired_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl RunLease {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// Errors returned by lease operations
+#[derive(Error, Debug)]
+pub enum LeaseError {
+    /// Another station holds an active lease on the run
+    #[error("Run {} is leased by {} until {}", .0.run_no, .0.holder, .0.expires_at)]
+    HeldByOther(RunLease),
+
+    /// No active lease with this id exists on the run
+    #[error("Lease not found or expired")]
+    NotFound,
+
+    /// The lease id matches but the caller is not its holder
+    #[error("Run {} is leased by {}", .0.run_no, .0.holder)]
+    NotHolder(RunLease),
+}
+
+/// In-memory lease table shared by all workers
+#[derive(Debug)]
+pub struct LeaseManager {
+    leases: Mutex<HashMap<i32, RunLease>>,
+    ttl: Duration,
+}
+
+impl LeaseManager {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            leases: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Build a manager with the TTL from `RM_LEASE_TTL_SECS`
+    pub fn from_env() -> Self {
+        let ttl_secs = env::var(ENV_LEASE_TTL_SECS)
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_LEASE_TTL_SECS);
+        Self::new(Duration::from_secs(ttl_secs))
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    fn expiry_from(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now + ChronoDuration::from_std(self.ttl).unwrap_or(ChronoDuration::zero())
+    }
+
+    /// Acquire a lease on a run
+    ///
+    /// Re-acquiring by the same holder and station renews the existing lease
+    /// and keeps its id, so a reloaded page does not lock itself out.
+    pub fn acquire(
+        &self,
+        run_no: i32,
+        holder: &str,
+        station: Option<String>,
+        exclusive: bool,
+    ) -> Result<RunLease, LeaseError> {
+        let now = Utc::now();
+        let mut leases = self.leases.lock().unwrap();
+
+        if let Some(existing) = leases.get_mut(&run_no) {
+            if !existing.is_expired(now) {
+                if existing.holder.eq_ignore_ascii_case(holder) && existing.station == station {
+                    existing.exclusive = exclusive;
+                    existing.expires_at = self.expiry_from(now);
+                    return Ok(existing.clone());
+                }
+                return Err(LeaseError::HeldByOther(existing.clone()));
+            }
+        }
+
+        let lease = RunLease {
+            lease_id: Uuid::new_v4().to_string(),
+            run_no,
+            holder: holder.to_string(),
+            station,
+            exclusive,
+            acquired_at: now,
+            expires_at: self.expiry_from(now),
+        };
+        leases.insert(run_no, lease.clone());
+        Ok(lease)
+    }
+
+    /// Extend an active lease of `holder` by another TTL
+    pub fn renew(&self, run_no: i32, lease_id: &str, holder: &str) -> Result<RunLease, LeaseError> {
+        let now = Utc::now();
+        let mut leases = self.leases.lock().unwrap();
+
+        match leases.get_mut(&run_no) {
+            Some(lease) if lease.lease_id == lease_id && !lease.is_expired(now) => {
+                check_holder(lease, holder)?;
+                lease.expires_at = self.expiry_from(now);
+                Ok(lease.clone())
+            }
+            _ => Err(LeaseError::NotFound),
+        }
+    }
+
+    /// Release a lease of `holder` before it expires
+    pub fn release(&self, run_no: i32, lease_id: &str, holder: &str) -> Result<(), LeaseError> {
+        let mut leases = self.leases.lock().unwrap();
+
+        match leases.get(&run_no) {
+            Some(lease) if lease.lease_id == lease_id => {
+                check_holder(lease, holder)?;
+                leases.remove(&run_no);
+                Ok(())
+            }
+            _ => Err(LeaseError::NotFound),
+        }
+    }
+
+    /// Current active lease on a run, if any
+    pub fn current(&self, run_no: i32) -> Option<RunLease> {
+        let now = Utc::now();
+        let mut leases = self.leases.lock().unwrap();
+
+        match leases.get(&run_no) {
+            Some(lease) if lease.is_expired(now) => {
+                leases.remove(&run_no);
+                None
+            }
+            other => other.cloned(),
+        }
+    }
+
+    /// Check that a change to the run is allowed for the caller
+    ///
+    /// Passes when the run has no active exclusive lease, or when `user`
+    /// holds it and `lease_id` matches it. A copied id alone is not enough.
+    pub fn check_write(
+        &self,
+        run_no: i32,
+        lease_id: Option<&str>,
+        user: &str,
+    ) -> Result<(), LeaseError> {
+        match self.current(run_no) {
+            Some(lease)
+                if lease.exclusive
+                    && (lease_id != Some(lease.lease_id.as_str())
+                        || !lease.holder.eq_ignore_ascii_case(user)) =>
+            {
+                Err(LeaseError::HeldByOther(lease))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn check_holder(lease: &RunLease, holder: &str) -> Result<(), LeaseError> {
+    if lease.holder.eq_ignore_ascii_case(holder) {
+        Ok(())
+    } else {
+        Err(LeaseError::NotHolder(lease.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire_conflict_and_reacquire() {
+        let manager = LeaseManager::new(Duration::from_secs(60));
+
+        let lease = manager
+            .acquire(1001, "alice", Some("ST1".to_string()), true)
+            .unwrap();
+
+        // Another station is refused
+        let err = manager
+            .acquire(1001, "bob", Some("ST2".to_string()), true)
+            .unwrap_err();
+        assert!(matches!(err, LeaseError::HeldByOther(ref l) if l.holder == "alice"));
+
+        // Same holder and station keeps the same lease
+        let again = manager
+            .acquire(1001, "ALICE", Some("ST1".to_string()), true)
+            .unwrap();
+        assert_eq!(again.lease_id, lease.lease_id);
+    }
+
+    #[test]
+    fn test_check_write() {
+        let manager = LeaseManager::new(Duration::from_secs(60));
+        assert!(manager.check_write(1001, None, "bob").is_ok());
+
+        let lease = manager.acquire(1001, "alice", None, true).unwrap();
+        assert!(manager.check_write(1001, None, "alice").is_err());
+        assert!(manager.check_write(1001, Some("other"), "alice").is_err());
+        assert!(manager
+            .check_write(1001, Some(&lease.lease_id), "ALICE")
+            .is_ok());
+
+        // Advisory leases never block writes
+        let advisory = manager.acquire(2002, "alice", None, false).unwrap();
+        assert!(!advisory.exclusive);
+        assert!(manager.check_write(2002, None, "bob").is_ok());
+    }
+
+    #[test]
+    fn test_renew_and_release() {
+        let manager = LeaseManager::new(Duration::from_secs(60));
+        let lease = manager.acquire(1001, "alice", None, true).unwrap();
+
+        assert!(manager.renew(1001, "wrong", "alice").is_err());
+        let renewed = manager.renew(1001, &lease.lease_id, "alice").unwrap();
+        assert!(renewed.expires_at >= lease.expires_at);
+
+        assert!(manager.release(1001, "wrong", "alice").is_err());
+        manager.release(1001, &lease.lease_id, "alice").unwrap();
+        assert!(manager.current(1001).is_none());
+    }
+
+    #[test]
+    fn test_expired_lease() {
+        let manager = LeaseManager::new(Duration::from_secs(0));
+        let lease = manager.acquire(1001, "alice", None, true).unwrap();
+
+        assert!(manager.current(1001).is_none());
+        assert!(manager.renew(1001, &lease.lease_id, "alice").is_err());
+        assert!(manager.check_write(1001, None, "bob").is_ok());
+
+        // Expired lease can be taken over by someone else
+        assert!(manager.acquire(1001, "bob", None, true).is_ok());
+    }
+
+    #[test]
+    fn test_copied_lease_id_is_refused() {
+        let manager = LeaseManager::new(Duration::from_secs(60));
+        let lease = manager.acquire(1001, "alice", None, true).unwrap();
+
+        // bob read the id somewhere; it does not make him the holder
+        let err = manager
+            .check_write(1001, Some(&lease.lease_id), "bob")
+            .unwrap_err();
+        assert!(matches!(err, LeaseError::HeldByOther(ref l) if l.holder == "alice"));
+        assert!(matches!(
+            manager.renew(1001, &lease.lease_id, "bob"),
+            Err(LeaseError::NotHolder(..))
+        ));
+        assert!(matches!(
+            manager.release(1001, &lease.lease_id, "bob"),
+            Err(LeaseError::NotHolder(..))
+        ));
+        assert!(manager.current(1001).is_some());
+    }
+
+    #[test]
+    fn test_lease_id_is_not_serialized() {
+        let manager = LeaseManager::new(Duration::from_secs(60));
+        let lease = manager.acquire(1001, "alice", None, true).unwrap();
+
+        let json = serde_json::to_value(&lease).unwrap();
+        assert!(json.get("lease_id").is_none());
+        assert!(!json.to_string().contains(&lease.lease_id));
+    }
+}
@@ -0,0 +1,96 @@
+//! RM Line Queries
+//!
+//! SQL fragments and row mapping for `cust_PartialPicked` lines, shared by
+//! every query that reads or removes partial picks.
+
+use tiberius::Row;
+
+use crate::db::mssql::MssqlPool;
+use crate::db::row::{column, FromRow, RowError};
+use crate::models::rm::RMLine;
+
+/// Columns selected for every `RMLine` read from `cust_PartialPicked`
+pub const RM_LINE_COLUMNS: &str = r#"
+            RunNo,
+            RowNum,
+            BatchNo,
+            LineTyp,
+            LineId,
+            ItemKey,
+            Location,
+            Unit,
+            StandardQty,
+            PackSize,
+            ToPickedPartialQty,
+            PickedPartialQty,
+            RecUserId,
+            ModifiedBy"#;
+
+/// Hash of the columns a picker or another station changes, selected as
+/// `ConcurrencyToken` and compared on removal to detect stale reads
+pub const CONCURRENCY_TOKEN_EXPR: &str = r#"CONVERT(VARCHAR(40), HASHBYTES('SHA1', CONCAT(
+                ToPickedPartialQty, '|', PickedPartialQty, '|',
+                CONVERT(VARCHAR(33), ModifiedDate, 126), '|', ModifiedBy
+            )), 2)"#;
+
+/// Lines that still have a partial quantity to pick and nothing picked yet.
+/// Shared by search and bulk removal so both see the same set of lines.
+pub const ELIGIBLE_FOR_REMOVAL: &str =
+    "ToPickedPartialQty > 0 AND (PickedPartialQty IS NULL OR PickedPartialQty <= 0)";
+
+/// Keys and quantities must be present with their expected types; the
+/// descriptive columns may be NULL and then read as empty
+impl FromRow for RMLine {
+    /// Map a row selected with `RM_LINE_COLUMNS` and `ConcurrencyToken`
+    fn from_row(row: &Row) -> Result<Self, RowError> {
+        Ok(RMLine {
+            run_no: column(row, "RunNo")?,
+            row_num: column(row, "RowNum")?,
+            batch_no: column(row, "BatchNo")?,
+            line_typ: column(row, "LineTyp")?,
+            line_id: column(row, "LineId")?,
+            item_key: column(row, "ItemKey")?,
+            location: column::<Option<String>>(row, "Location")?.unwrap_or_default(),
+            unit: column::<Option<String>>(row, "Unit")?.unwrap_or_default(),
+            standard_qty: column(row, "StandardQty")?,
+            pack_size: column(row, "PackSize")?,
+            to_picked_partial_qty: column(row, "ToPickedPartialQty")?,
+            picked_partial_qty: column(row, "PickedPartialQty")?,
+            rec_user_id: column::<Option<String>>(row, "RecUserId")?.unwrap_or_default(),
+            modified_by: column::<Option<String>>(row, "ModifiedBy")?.unwrap_or_default(),
+            concurrency_token: column(row, "ConcurrencyToken")?,
+            item_description: None,
+            item_class: None,
+            storage_condition: None,
+            location_description: None,
+        })
+    }
+}
+
+/// Mapper for `MssqlPool` queries returning `RMLine`s
+pub fn map_rm_line(row: &Row) -> anyhow::Result<RMLine> {
+    Ok(RMLine::from_row(row)?)
+}
+
+/// Lines of a run eligible for removal, as shown by search
+pub async fn eligible_lines(pool: &MssqlPool, run_no: i32) -> anyhow::Result<Vec<RMLine>> {
+    let sql = format!(
+        r#"
+        SELECT {}, {} AS ConcurrencyToken
+        FROM cust_PartialPicked
+        WHERE RunNo = @P1
+          AND {}
+        ORDER BY BatchNo, LineId, ItemKey
+        "#,
+        RM_LINE_COLUMNS, CONCURRENCY_TOKEN_EXPR, ELIGIBLE_FOR_REMOVAL
+    );
+
+    pool.execute_read_with_params(
+        &sql,
+        |query| {
+            query.bind(run_no);
+        },
+        map_rm_line,
+    )
+    .await
+}
@@ -0,0 +1,414 @@
+//! Item Master Enrichment
+//!
+//! `cust_PartialPicked` only carries `ItemKey` and `Location`. When an item
+//! master and/or location table is configured, search results are filled in
+//! with the item description, item class, storage condition and location
+//! description. Table and column names differ between installations, so they
+//! are configured rather than assumed.
+//!
+//! Master data rarely changes, so lookups are cached in memory per plant for
+//! `RM_MASTER_CACHE_TTL_SECS`, including keys that were not found. A failed
+//! lookup is logged and the lines are returned without the extra fields.
+//!
+//! Every variable can be set per plant as `PLANT_<CODE>_<VARIABLE>`.
+//!
+//! | Variable | Description | Default |
+//! |----------|-------------|---------|
+//! | `RM_ITEM_MASTER_TABLE` | Item master table, e.g. `dbo.INMAST`; unset disables item lookups | - |
+//! | `RM_ITEM_MASTER_KEY_COLUMN` | Column matched against `ItemKey` | `ItemKey` |
+//! | `RM_ITEM_MASTER_DESCRIPTION_COLUMN` | Item description column | `Description` |
+//! | `RM_ITEM_MASTER_CLASS_COLUMN` | Item class column | not read |
+//! | `RM_ITEM_MASTER_STORAGE_COLUMN` | Storage condition column | not read |
+//! | `RM_LOCATION_TABLE` | Location table; unset disables location lookups | - |
+//! | `RM_LOCATION_KEY_COLUMN` | Column matched against `Location` | `Location` |
+//! | `RM_LOCATION_DESCRIPTION_COLUMN` | Location description column | `Description` |
+//! | `RM_MASTER_CACHE_TTL_SECS` | Lifetime of cached lookups | `600` |
+
+use anyhow::{bail, Result};
+use log::warn;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tiberius::Row;
+
+use crate::db::mssql::{prefixed_var, MssqlPool};
+use crate::db::row::column;
+use crate::models::rm::RMLine;
+
+const ENV_ITEM_TABLE: &str = "RM_ITEM_MASTER_TABLE";
+const ENV_ITEM_KEY_COLUMN: &str = "RM_ITEM_MASTER_KEY_COLUMN";
+const ENV_ITEM_DESCRIPTION_COLUMN: &str = "RM_ITEM_MASTER_DESCRIPTION_COLUMN";
+const ENV_ITEM_CLASS_COLUMN: &str = "RM_ITEM_MASTER_CLASS_COLUMN";
+const ENV_ITEM_STORAGE_COLUMN: &str = "RM_ITEM_MASTER_STORAGE_COLUMN";
+const ENV_LOCATION_TABLE: &str = "RM_LOCATION_TABLE";
+const ENV_LOCATION_KEY_COLUMN: &str = "RM_LOCATION_KEY_COLUMN";
+const ENV_LOCATION_DESCRIPTION_COLUMN: &str = "RM_LOCATION_DESCRIPTION_COLUMN";
+const ENV_CACHE_TTL_SECS: &str = "RM_MASTER_CACHE_TTL_SECS";
+
+const DEFAULT_CACHE_TTL_SECS: u64 = 600;
+
+/// Keys looked up per query, well below the 2100 parameters SQL Server allows
+const LOOKUP_BATCH: usize = 500;
+
+/// A table and the columns read from it, as bracket-quoted SQL identifiers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupTable {
+    pub table: String,
+    pub key: String,
+    /// Output name and column, e.g. `("Description", "[Desc1]")`
+    pub columns: Vec<(&'static str, String)>,
+}
+
+impl LookupTable {
+    /// `SELECT` of the configured columns for `count` keys bound as `@P1..`
+    fn select_sql(&self, count: usize) -> String {
+        let mut selected = vec![format!("RTRIM({}) AS MasterKey", self.key)];
+        selected.extend(
+            self.columns
+                .iter()
+                .map(|(name, col)| format!("{} AS {}", col, name)),
+        );
+        let params: Vec<String> = (1..=count).map(|i| format!("@P{}", i)).collect();
+        format!(
+            "SELECT {} FROM {} WHERE {} IN ({})",
+            selected.join(", "),
+            self.table,
+            self.key,
+            params.join(", ")
+        )
+    }
+}
+
+/// Quote a table or column name, optionally schema-qualified, as `[a].[b]`
+///
+/// Names come from configuration and are spliced into SQL, so only letters,
+/// digits and `_` are accepted in each part.
+pub fn quote_identifier(name: &str) -> Result<String> {
+    let parts: Vec<&str> = name.trim().split('.').collect();
+    if parts.len() > 3
+        || parts
+            .iter()
+            .any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+    {
+        bail!("Not a valid SQL name: {}", name);
+    }
+    Ok(parts
+        .iter()
+        .map(|p| format!("[{}]", p))
+        .collect::<Vec<_>>()
+        .join("."))
+}
+
+fn var(prefix: &str, name: &str) -> Option<String> {
+    prefixed_var(prefix, name)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn identifier(prefix: &str, name: &str) -> Result<Option<String>> {
+    var(prefix, name)
+        .map(|v| quote_identifier(&v).map_err(|e| e.context(format!("Invalid {}", name))))
+        .transpose()
+}
+
+fn identifier_or(prefix: &str, name: &str, default: &str) -> Result<String> {
+    Ok(identifier(prefix, name)?.unwrap_or_else(|| format!("[{}]", default)))
+}
+
+/// Master data found for an item
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ItemInfo {
+    pub description: Option<String>,
+    pub class: Option<String>,
+    pub storage_condition: Option<String>,
+}
+
+/// Cached lookups for one table; `None` records a key that was not found
+struct Cache<T> {
+    entries: Mutex<HashMap<String, (Instant, Option<T>)>>,
+}
+
+impl<T: Clone> Cache<T> {
+    fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Keys without a fresh entry
+    fn missing(&self, keys: &BTreeSet<String>, ttl: Duration, now: Instant) -> Vec<String> {
+        let entries = self.entries.lock().unwrap();
+        keys.iter()
+            .filter(|k| {
+                entries
+                    .get(*k)
+                    .is_none_or(|(at, _)| now.duration_since(*at) >= ttl)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Record the values found for `keys`, and that the rest do not exist
+    fn store(&self, keys: &[String], found: HashMap<String, T>, now: Instant) {
+        let mut entries = self.entries.lock().unwrap();
+        let mut found = found;
+        for key in keys {
+            entries.insert(key.clone(), (now, found.remove(key)));
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<T> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(key).and_then(|(_, value)| value.clone())
+    }
+}
+
+/// Cache keys ignore case and trailing spaces, as SQL Server comparisons do
+fn cache_key(key: &str) -> String {
+    key.trim().to_uppercase()
+}
+
+/// Item and location lookups of one plant
+pub struct MasterData {
+    item: Option<LookupTable>,
+    location: Option<LookupTable>,
+    ttl: Duration,
+    items: Cache<ItemInfo>,
+    locations: Cache<String>,
+}
+
+impl MasterData {
+    pub fn new(item: Option<LookupTable>, location: Option<LookupTable>, ttl: Duration) -> Self {
+        Self {
+            item,
+            location,
+            ttl,
+            items: Cache::new(),
+            locations: Cache::new(),
+        }
+    }
+
+    /// Lookups configured with `{prefix}RM_*`, then `RM_*`; `None` when
+    /// neither table is set
+    ///
+    /// # Errors
+    ///
+    /// Returns an error when a table or column name is not a valid SQL name.
+    pub fn from_env(prefix: &str) -> Result<Option<Self>> {
+        let item = match identifier(prefix, ENV_ITEM_TABLE)? {
+            None => None,
+            Some(table) => {
+                let mut columns = vec![(
+                    "Description",
+                    identifier_or(prefix, ENV_ITEM_DESCRIPTION_COLUMN, "Description")?,
+                )];
+                if let Some(col) = identifier(prefix, ENV_ITEM_CLASS_COLUMN)? {
+                    columns.push(("ItemClass", col));
+                }
+                if let Some(col) = identifier(prefix, ENV_ITEM_STORAGE_COLUMN)? {
+                    columns.push(("StorageCondition", col));
+                }
+                Some(LookupTable {
+                    table,
+                    key: identifier_or(prefix, ENV_ITEM_KEY_COLUMN, "ItemKey")?,
+                    columns,
+                })
+            }
+        };
+        let location = match identifier(prefix, ENV_LOCATION_TABLE)? {
+            None => None,
+            Some(table) => Some(LookupTable {
+                table,
+                key: identifier_or(prefix, ENV_LOCATION_KEY_COLUMN, "Location")?,
+                columns: vec![(
+                    "Description",
+                    identifier_or(prefix, ENV_LOCATION_DESCRIPTION_COLUMN, "Description")?,
+                )],
+            }),
+        };
+        if item.is_none() && location.is_none() {
+            return Ok(None);
+        }
+
+        let ttl = var(prefix, ENV_CACHE_TTL_SECS)
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_CACHE_TTL_SECS);
+        Ok(Some(Self::new(item, location, Duration::from_secs(ttl))))
+    }
+
+    /// Tables read, for startup logs and the support CLI's config check
+    pub fn tables(&self) -> Vec<&str> {
+        [&self.item, &self.location]
+            .into_iter()
+            .flatten()
+            .map(|t| t.table.as_str())
+            .collect()
+    }
+
+    /// Read no rows but every configured column, to check the settings
+    pub async fn probe(&self, pool: &MssqlPool) -> Result<()> {
+        for table in [&self.item, &self.location].into_iter().flatten() {
+            let sql = table.select_sql(1).replacen("SELECT ", "SELECT TOP 0 ", 1);
+            pool.execute_read_with_params(
+                &sql,
+                |query| {
+                    query.bind("");
+                },
+                |_| Ok(()),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Fill in the master data fields of `lines`; lookup errors are logged
+    pub async fn enrich(&self, pool: &MssqlPool, lines: &mut [RMLine]) {
+        if let Some(table) = &self.item {
+            let keys = lines.iter().map(|l| cache_key(&l.item_key)).collect();
+            if let Err(e) = refresh(pool, table, &self.items, &keys, self.ttl, |row| {
+                Ok(ItemInfo {
+                    description: column(row, "Description")?,
+                    class: optional_column(row, "ItemClass")?,
+                    storage_condition: optional_column(row, "StorageCondition")?,
+                })
+            })
+            .await
+            {
+                warn!("Item master lookup in {} failed: {:#}", table.table, e);
+            }
+        }
+        if let Some(table) = &self.location {
+            let keys = lines.iter().map(|l| cache_key(&l.location)).collect();
+            if let Err(e) = refresh(pool, table, &self.locations, &keys, self.ttl, |row| {
+                Ok(column::<Option<String>>(row, "Description")?.unwrap_or_default())
+            })
+            .await
+            {
+                warn!("Location lookup in {} failed: {:#}", table.table, e);
+            }
+        }
+
+        self.apply(lines);
+    }
+
+    /// Copy cached master data onto the lines
+    fn apply(&self, lines: &mut [RMLine]) {
+        for line in lines {
+            if self.item.is_some() {
+                let info = self
+                    .items
+                    .get(&cache_key(&line.item_key))
+                    .unwrap_or_default();
+                line.item_description = info.description;
+                line.item_class = info.class;
+                line.storage_condition = info.storage_condition;
+            }
+            if self.location.is_some() {
+                line.location_description = self
+                    .locations
+                    .get(&cache_key(&line.location))
+                    .filter(|d| !d.is_empty());
+            }
+        }
+    }
+}
+
+/// A column that is only selected when configured
+fn optional_column(row: &Row, name: &str) -> Result<Option<String>> {
+    if row.columns().iter().any(|c| c.name() == name) {
+        Ok(column(row, name)?)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Look up the keys missing from `cache` and store what was found
+async fn refresh<T: Clone>(
+    pool: &MssqlPool,
+    table: &LookupTable,
+    cache: &Cache<T>,
+    keys: &BTreeSet<String>,
+    ttl: Duration,
+    mapper: impl Fn(&Row) -> Result<T>,
+) -> Result<()> {
+    let now = Instant::now();
+    let missing: Vec<String> = cache
+        .missing(keys, ttl, now)
+        .into_iter()
+        .filter(|k| !k.is_empty())
+        .collect();
+
+    for chunk in missing.chunks(LOOKUP_BATCH) {
+        let rows = pool
+            .execute_read_with_params(
+                &table.select_sql(chunk.len()),
+                |query| {
+                    for key in chunk {
+                        query.bind(key.clone());
+                    }
+                },
+                |row| {
+                    Ok((
+                        cache_key(&column::<String>(row, "MasterKey")?),
+                        mapper(row)?,
+                    ))
+                },
+            )
+            .await?;
+        cache.store(chunk, rows.into_iter().collect(), now);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("INMAST").unwrap(), "[INMAST]");
+        assert_eq!(quote_identifier(" dbo.INMAST ").unwrap(), "[dbo].[INMAST]");
+        assert!(quote_identifier("INMAST; DROP TABLE x").is_err());
+        assert!(quote_identifier("dbo..INMAST").is_err());
+        assert!(quote_identifier("[INMAST]").is_err());
+    }
+
+    #[test]
+    fn test_select_sql() {
+        let table = LookupTable {
+            table: "[dbo].[INMAST]".to_string(),
+            key: "[Itemkey]".to_string(),
+            columns: vec![
+                ("Description", "[Desc1]".to_string()),
+                ("ItemClass", "[Itemclass]".to_string()),
+            ],
+        };
+        assert_eq!(
+            table.select_sql(2),
+            "SELECT RTRIM([Itemkey]) AS MasterKey, [Desc1] AS Description, [Itemclass] AS ItemClass \
+             FROM [dbo].[INMAST] WHERE [Itemkey] IN (@P1, @P2)"
+        );
+    }
+
+    #[test]
+    fn test_cache_expires_and_remembers_misses() {
+        let cache: Cache<String> = Cache::new();
+        let ttl = Duration::from_secs(60);
+        let now = Instant::now();
+        let keys: BTreeSet<String> = ["SUGAR", "SALT"].map(String::from).into();
+
+        assert_eq!(cache.missing(&keys, ttl, now).len(), 2);
+        cache.store(
+            &["SALT".to_string(), "SUGAR".to_string()],
+            HashMap::from([("SUGAR".to_string(), "Sugar 25kg".to_string())]),
+            now,
+        );
+
+        // SALT was not found, which is cached too
+        assert!(cache.missing(&keys, ttl, now).is_empty());
+        assert_eq!(cache.get("SUGAR").as_deref(), Some("Sugar 25kg"));
+        assert_eq!(cache.get("SALT"), None);
+        assert_eq!(cache.missing(&keys, ttl, now + ttl).len(), 2);
+    }
+}
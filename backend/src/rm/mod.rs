@@ -0,0 +1,33 @@
+//! RM Partial Pick Domain Logic
+//!
+//! State and rules behind the `/api/rm` routes that are not plain SQL.
+//!
+//! # Components
+//!
+//! - **Lines**: Shared SQL and row mapping for `cust_PartialPicked`
+//! - **Master data**: Item and location descriptions added to search results
+//! - **Runs**: Discovery of runs with open partial picks
+//! - **Removal**: The partial-pick removal update
+//! - **Restore**: Undoing an audited removal, used by the support CLI
+//! - **Leases**: Per-run locks so only one station edits a run at a time
+//! - **Approval**: Four-eyes sign-off for removals above a threshold
+//! - **Reasons**: Mandatory reason codes and the removal audit history
+//! - **Events**: Live line changes pushed to stations watching a run
+//! - **Idempotency**: Replay of stored responses for retried remove requests
+//! - **Webhooks**: Removal events delivered to external systems from an outbox
+
+pub mod approval;
+pub mod events;
+pub mod history;
+pub mod idempotency;
+pub mod lease;
+pub mod lines;
+pub mod master;
+pub mod reason;
+pub mod removal;
+pub mod restore;
+pub mod runs;
+pub mod webhook;
+
+pub use events::RunEventHub;
+pub use lease::{LeaseError, LeaseManager};
@@ -0,0 +1,155 @@
+//! Removal Reason Codes
+//!
+//! Every removal must name one of the active codes in
+//! `cust_PartialPickReasonCode`. Codes flagged `RequiresComment` also need a
+//! free-text comment. The list is maintained through the admin endpoints.
+
+use anyhow::Result;
+use thiserror::Error;
+
+use crate::db::mssql::MssqlPool;
+use crate::db::row::column;
+use crate::models::rm::ReasonCode;
+
+/// Why a removal's reason was refused
+#[derive(Error, Debug)]
+pub enum ReasonError {
+    #[error("A reason code is required")]
+    Missing,
+
+    #[error("Unknown or inactive reason code: {0}")]
+    Unknown(String),
+
+    #[error("Reason code {0} requires a comment")]
+    CommentRequired(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] anyhow::Error),
+}
+
+/// Validated reason attached to a removal
+#[derive(Debug, Clone)]
+pub struct RemovalReason {
+    pub code: String,
+    pub comment: Option<String>,
+}
+
+const REASON_COLUMNS: &str = "ReasonCode, Description, RequiresComment, Active, SortOrder";
+
+fn map_reason_code(row: &tiberius::Row) -> Result<ReasonCode> {
+    Ok(ReasonCode {
+        reason_code: column(row, "ReasonCode")?,
+        description: column(row, "Description")?,
+        requires_comment: column(row, "RequiresComment")?,
+        active: column(row, "Active")?,
+        sort_order: column(row, "SortOrder")?,
+    })
+}
+
+pub async fn list_codes(pool: &MssqlPool, include_inactive: bool) -> Result<Vec<ReasonCode>> {
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM cust_PartialPickReasonCode
+        WHERE @P1 = 1 OR Active = 1
+        ORDER BY SortOrder, ReasonCode
+        "#,
+        REASON_COLUMNS
+    );
+
+    pool.execute_read_with_params(
+        &sql,
+        |query| {
+            query.bind(include_inactive);
+        },
+        map_reason_code,
+    )
+    .await
+}
+
+/// Check the code and comment sent with a removal
+pub async fn validate(
+    pool: &MssqlPool,
+    code: Option<&str>,
+    comment: Option<&str>,
+) -> Result<RemovalReason, ReasonError> {
+    let code = code
+        .map(|c| c.trim().to_uppercase())
+        .filter(|c| !c.is_empty())
+        .ok_or(ReasonError::Missing)?;
+    let comment = comment
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(str::to_string);
+
+    let sql = format!(
+        "SELECT {} FROM cust_PartialPickReasonCode WHERE ReasonCode = @P1 AND Active = 1",
+        REASON_COLUMNS
+    );
+    let code_for_query = code.clone();
+    let found = pool
+        .execute_read_with_params(
+            &sql,
+            |query| {
+                query.bind(code_for_query.clone());
+            },
+            map_reason_code,
+        )
+        .await?;
+
+    let reason_code = found
+        .into_iter()
+        .next()
+        .ok_or_else(|| ReasonError::Unknown(code.clone()))?;
+
+    if reason_code.requires_comment && comment.is_none() {
+        return Err(ReasonError::CommentRequired(code));
+    }
+
+    Ok(RemovalReason { code, comment })
+}
+
+/// Insert or update a code
+pub async fn upsert_code(pool: &MssqlPool, code: &ReasonCode, modified_by: &str) -> Result<()> {
+    let sql = r#"
+        MERGE cust_PartialPickReasonCode AS target
+        USING (SELECT @P1 AS ReasonCode) AS source
+            ON target.ReasonCode = source.ReasonCode
+        WHEN MATCHED THEN
+            UPDATE SET Description = @P2, RequiresComment = @P3, Active = @P4, SortOrder = @P5,
+                       ModifiedBy = @P6, ModifiedDate = GETUTCDATE()
+        WHEN NOT MATCHED THEN
+            INSERT (ReasonCode, Description, RequiresComment, Active, SortOrder, ModifiedBy, ModifiedDate)
+            VALUES (@P1, @P2, @P3, @P4, @P5, @P6, GETUTCDATE());
+    "#;
+
+    pool.execute_idempotent_update(sql, |query| {
+        query.bind(code.reason_code.trim().to_uppercase());
+        query.bind(code.description.clone());
+        query.bind(code.requires_comment);
+        query.bind(code.active);
+        query.bind(code.sort_order);
+        query.bind(modified_by.to_string());
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Deactivate a code; codes stay in the table because the audit trail refers to them
+pub async fn deactivate_code(pool: &MssqlPool, code: &str, modified_by: &str) -> Result<bool> {
+    let sql = r#"
+        UPDATE cust_PartialPickReasonCode
+        SET Active = 0, ModifiedBy = @P2, ModifiedDate = GETUTCDATE()
+        WHERE ReasonCode = @P1
+    "#;
+
+    let affected = pool
+        .execute_idempotent_update(sql, |query| {
+            query.bind(code.trim().to_uppercase());
+            query.bind(modified_by.to_string());
+        })
+        .await?;
+
+    Ok(affected > 0)
+}
@@ -0,0 +1,529 @@
+//! Partial Pick Removal
+//!
+//! The update behind `POST /api/rm/remove`, shared with the approval
+//! workflow so an approved request runs exactly the same logic.
+//!
+//! The legacy audit columns on `cust_PartialPicked` are kept as before:
+//! `User8` holds the total quantity removed, `User9` the plant-local date and
+//! `User3` the user. `ModifiedDate` and the audit trail are written in UTC.
+
+use log::{error, warn};
+use tiberius::numeric::Numeric;
+
+use crate::db::mssql::MssqlPool;
+use crate::db::row::column;
+use crate::models::qty::{Qty, QTY_SQL_TYPE};
+use crate::models::rm::{RMLine, RemoveItem, RemoveItemResult, RemoveItemStatus};
+use crate::plant::PlantTime;
+use crate::rm::lines::{CONCURRENCY_TOKEN_EXPR, ELIGIBLE_FOR_REMOVAL};
+use crate::rm::reason::RemovalReason;
+use crate::rm::webhook::OUTBOX_OUTPUT;
+
+/// Result of removing a list of items
+#[derive(Debug, Default)]
+pub struct RemovalOutcome {
+    pub total_affected: u64,
+    pub results: Vec<RemoveItemResult>,
+    pub errors: Vec<String>,
+}
+
+impl RemovalOutcome {
+    pub fn conflicts(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.status == RemoveItemStatus::Conflict)
+            .count()
+    }
+}
+
+/// Who removes the lines and why
+#[derive(Debug, Clone)]
+pub struct RemovalContext {
+    /// Written to `User3` and the audit trail
+    pub removed_by: String,
+    /// Supervisor who signed off; written to `ModifiedBy` instead of `removed_by`
+    pub approved_by: Option<String>,
+    pub approval_request_id: Option<i32>,
+    pub reason: RemovalReason,
+    /// Time of the removal, taken once for all items
+    pub removed_at: PlantTime,
+}
+
+/// SQL for `User8` after `delta` more has been taken off the line
+///
+/// `User8` keeps the running total removed so the run summary adds up over
+/// several partial removals; a total of zero clears it. It is written as
+/// plain digits, e.g. `12.5`, with `en-US` fixed so the session language
+/// cannot change the decimal separator. `user8` is the column as qualified
+/// in the surrounding statement.
+pub fn user8_plus(user8: &str, delta: &str) -> String {
+    let total = format!(
+        "ISNULL(TRY_CAST({user8} AS {qty}), 0) + CAST({delta} AS {qty})",
+        user8 = user8,
+        delta = delta,
+        qty = QTY_SQL_TYPE
+    );
+    format!(
+        "CASE WHEN {total} > 0 THEN FORMAT({total}, '0.######', 'en-US') END",
+        total = total
+    )
+}
+
+/// Quantity left to pick after applying the change requested by `item`
+///
+/// With neither `new_qty` nor `reduce_by` the line is fully removed. When
+/// `round_to_pack_size` is set the target is rounded down to whole packs.
+/// The same rule runs in SQL against the locked row; this copy validates
+/// requests up front and sizes removals for the approval thresholds.
+pub fn target_qty(current: Qty, pack_size: Qty, item: &RemoveItem) -> Result<Qty, String> {
+    validate_item(item)?;
+
+    let mut target = match (item.new_qty, item.reduce_by) {
+        (Some(new_qty), _) => new_qty,
+        (None, Some(reduce_by)) => current - reduce_by,
+        (None, None) => Qty::ZERO,
+    };
+
+    if item.round_to_pack_size && pack_size.is_positive() {
+        target = target.floor_to_multiple(pack_size);
+    }
+
+    if target.is_negative() {
+        return Err(format!(
+            "Reduction exceeds the current quantity {}",
+            current
+        ));
+    }
+    if target > current {
+        return Err(format!(
+            "New quantity {} is more than the current quantity {}",
+            target, current
+        ));
+    }
+    if target == current {
+        return Err("Requested change leaves the quantity unchanged".to_string());
+    }
+
+    Ok(target)
+}
+
+/// Checks that do not depend on the current row
+pub fn validate_item(item: &RemoveItem) -> Result<(), String> {
+    match (item.new_qty, item.reduce_by) {
+        (Some(_), Some(_)) => Err("Specify either new_qty or reduce_by, not both".to_string()),
+        (Some(q), None) if q.is_negative() => {
+            Err("new_qty must be a non-negative number".to_string())
+        }
+        (None, Some(r)) if !r.is_positive() => {
+            Err("reduce_by must be a positive number".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A line together with the change a removal is going to make to it
+#[derive(Debug, Clone)]
+pub struct PlannedRemoval {
+    pub line: RMLine,
+    /// The requested item, with the line's concurrency token filled in
+    pub item: RemoveItem,
+    /// Quantity that will come off ToPickedPartialQty
+    pub remove_qty: Qty,
+}
+
+/// Match requested items to their current lines; items without a line are skipped
+pub fn plan(lines: &[RMLine], items: &[RemoveItem]) -> Vec<PlannedRemoval> {
+    items
+        .iter()
+        .filter_map(|item| {
+            let line = lines
+                .iter()
+                .find(|l| l.row_num == item.row_num && l.line_id == item.line_id)?;
+            let target = target_qty(line.to_picked_partial_qty, line.pack_size, item)
+                .unwrap_or(line.to_picked_partial_qty);
+            let mut item = item.clone();
+            if item.concurrency_token.is_none() {
+                item.concurrency_token = Some(line.concurrency_token.clone());
+            }
+            Some(PlannedRemoval {
+                line: line.clone(),
+                item,
+                remove_qty: line.to_picked_partial_qty - target,
+            })
+        })
+        .collect()
+}
+
+/// Requested items `plan` found no current line for
+pub fn unplanned<'a>(items: &'a [RemoveItem], planned: &[PlannedRemoval]) -> Vec<&'a RemoveItem> {
+    items
+        .iter()
+        .filter(|item| {
+            !planned
+                .iter()
+                .any(|p| p.item.row_num == item.row_num && p.item.line_id == item.line_id)
+        })
+        .collect()
+}
+
+/// Full removal of each line, as done by remove-by-criteria
+pub fn plan_full(lines: &[RMLine]) -> Vec<PlannedRemoval> {
+    lines
+        .iter()
+        .map(|line| PlannedRemoval {
+            line: line.clone(),
+            item: RemoveItem {
+                row_num: line.row_num,
+                line_id: line.line_id,
+                concurrency_token: Some(line.concurrency_token.clone()),
+                new_qty: None,
+                reduce_by: None,
+                round_to_pack_size: false,
+            },
+            remove_qty: line.to_picked_partial_qty,
+        })
+        .collect()
+}
+
+/// Reduce `ToPickedPartialQty` for each item (to zero unless a target is
+/// given), keeping the legacy audit columns and writing one row per changed
+/// line to `cust_PartialPickRemovalAudit`
+pub async fn remove_items(
+    pool: &MssqlPool,
+    run_no: i32,
+    items: &[RemoveItem],
+    ctx: &RemovalContext,
+) -> RemovalOutcome {
+    // Lock the line, compute the target from its current value, update it and
+    // report what happened in one round trip: a missing row, a row changed
+    // since it was read and an invalid target all need different handling
+    let sql = format!(
+        r#"
+        SET XACT_ABORT ON;
+        BEGIN TRANSACTION;
+
+        DECLARE @Before {qty}, @Pack {qty}, @Target {qty}, @Affected INT = 0, @Status VARCHAR(20);
+
+        SELECT
+            @Before = CAST(ToPickedPartialQty AS {qty}),
+            @Pack = CAST(PackSize AS {qty})
+        FROM cust_PartialPicked WITH (UPDLOCK, HOLDLOCK)
+        WHERE RunNo = @P2
+          AND RowNum = @P3
+          AND LineId = @P4
+          AND {eligible}
+          AND (@P5 IS NULL OR {token} = @P5);
+
+        IF @Before IS NULL
+        BEGIN
+            SET @Status = CASE
+                WHEN EXISTS (
+                    SELECT 1 FROM cust_PartialPicked
+                    WHERE RunNo = @P2 AND RowNum = @P3 AND LineId = @P4
+                      AND {eligible}
+                ) THEN 'conflict'
+                ELSE 'not_found'
+            END;
+        END
+        ELSE
+        BEGIN
+            SET @Target = CASE
+                WHEN @P11 IS NOT NULL THEN @P11
+                WHEN @P12 IS NOT NULL THEN @Before - @P12
+                ELSE 0
+            END;
+
+            IF @P13 = 1 AND @Pack > 0
+                SET @Target = FLOOR(@Target / @Pack) * @Pack;
+
+            IF @Target < 0 OR @Target >= @Before
+                SET @Status = 'invalid_qty';
+            ELSE
+            BEGIN
+                UPDATE cust_PartialPicked
+                SET
+                    User8 = {user8},
+                    User9 = @P15,
+                    User3 = LEFT(@P1, 8),
+                    ToPickedPartialQty = @Target,
+                    ModifiedBy = LEFT(@P6, 8),
+                    ModifiedDate = @P14
+                WHERE RunNo = @P2 AND RowNum = @P3 AND LineId = @P4;
+
+                SET @Affected = @@ROWCOUNT;
+                SET @Status = 'removed';
+
+                INSERT INTO cust_PartialPickRemovalAudit
+                    (RunNo, RowNum, LineId, BatchNo, ItemKey, QtyBefore, QtyAfter, ReasonCode,
+                     Comment, RemovedBy, ApprovedBy, ApprovalRequestId, RemovedDate)
+                {outbox}
+                SELECT RunNo, RowNum, LineId, BatchNo, ItemKey, @Before, @Target, @P7,
+                       @P8, @P1, @P9, @P10, ModifiedDate
+                FROM cust_PartialPicked
+                WHERE RunNo = @P2 AND RowNum = @P3 AND LineId = @P4;
+            END
+        END
+
+        COMMIT TRANSACTION;
+
+        SELECT
+            @Affected AS Affected, @Status AS Status,
+            @Before AS QtyBefore, @Target AS QtyAfter, @Pack AS PackSize
+        "#,
+        token = CONCURRENCY_TOKEN_EXPR,
+        eligible = ELIGIBLE_FOR_REMOVAL,
+        user8 = user8_plus("User8", "(@Before - @Target)"),
+        qty = QTY_SQL_TYPE,
+        outbox = OUTBOX_OUTPUT
+    );
+
+    let modified_by = ctx.approved_by.as_deref().unwrap_or(&ctx.removed_by);
+    let mut outcome = RemovalOutcome {
+        results: Vec::with_capacity(items.len()),
+        ..Default::default()
+    };
+
+    for item in items {
+        let current_row = item.row_num;
+        let current_line = item.line_id;
+
+        if let Err(msg) = validate_item(item) {
+            let msg = format!(
+                "Item (Row: {}, Line: {}): {}",
+                current_row, current_line, msg
+            );
+            outcome.errors.push(msg.clone());
+            outcome.results.push(RemoveItemResult {
+                row_num: current_row,
+                line_id: current_line,
+                status: RemoveItemStatus::InvalidQuantity,
+                message: Some(msg),
+                qty_before: None,
+                qty_after: None,
+            });
+            continue;
+        }
+
+        let result = pool
+            .execute_query_with_params(
+                &sql,
+                |query| {
+                    query.bind(ctx.removed_by.clone());
+                    query.bind(run_no);
+                    query.bind(current_row);
+                    query.bind(current_line);
+                    query.bind(item.concurrency_token.clone());
+                    query.bind(modified_by.to_string());
+                    query.bind(ctx.reason.code.clone());
+                    query.bind(ctx.reason.comment.clone());
+                    query.bind(ctx.approved_by.clone());
+                    query.bind(ctx.approval_request_id);
+                    query.bind(item.new_qty.map(Numeric::from));
+                    query.bind(item.reduce_by.map(Numeric::from));
+                    query.bind(item.round_to_pack_size);
+                    query.bind(ctx.removed_at.utc);
+                    query.bind(ctx.removed_at.legacy_date);
+                },
+                |row| {
+                    Ok((
+                        column::<i32>(row, "Affected")?,
+                        column::<String>(row, "Status")?,
+                        column::<Option<Qty>>(row, "QtyBefore")?,
+                        column::<Option<Qty>>(row, "QtyAfter")?,
+                        column::<Option<Qty>>(row, "PackSize")?.unwrap_or_default(),
+                    ))
+                },
+            )
+            .await;
+
+        let (status, message, qty_before, qty_after) = match result {
+            Ok(rows) => match rows.into_iter().next() {
+                Some((affected, status, before, after, _)) if status == "removed" => {
+                    outcome.total_affected += affected as u64;
+                    (RemoveItemStatus::Removed, None, before, after)
+                }
+                Some((_, status, before, _, pack_size)) if status == "invalid_qty" => {
+                    let detail = before
+                        .map(|current| {
+                            target_qty(current, pack_size, item)
+                                .err()
+                                .unwrap_or_else(|| "Quantity rounds to no change".to_string())
+                        })
+                        .unwrap_or_default();
+                    let msg = format!(
+                        "Item (Row: {}, Line: {}): {}",
+                        current_row, current_line, detail
+                    );
+                    outcome.errors.push(msg.clone());
+                    (RemoveItemStatus::InvalidQuantity, Some(msg), before, None)
+                }
+                Some((_, status, _, _, _)) if status == "conflict" => {
+                    warn!(
+                        "Item (Row: {}, Line: {}) changed since it was read, not removed",
+                        current_row, current_line
+                    );
+                    let msg = format!(
+                        "Item (Row: {}, Line: {}) was changed by another user, refresh and try again",
+                        current_row, current_line
+                    );
+                    outcome.errors.push(msg.clone());
+                    (RemoveItemStatus::Conflict, Some(msg), None, None)
+                }
+                _ => {
+                    let msg = format!(
+                        "Item (Row: {}, Line: {}) not found or already processed",
+                        current_row, current_line
+                    );
+                    outcome.errors.push(msg.clone());
+                    (RemoveItemStatus::NotFound, Some(msg), None, None)
+                }
+            },
+            Err(e) => {
+                error!(
+                    "Error updating item (Row: {}, Line: {}): {}",
+                    current_row, current_line, e
+                );
+                let msg = format!("Item (Row: {}, Line: {}): {}", current_row, current_line, e);
+                outcome.errors.push(msg.clone());
+                (RemoveItemStatus::Error, Some(msg), None, None)
+            }
+        };
+
+        outcome.results.push(RemoveItemResult {
+            row_num: current_row,
+            line_id: current_line,
+            status,
+            message,
+            qty_before,
+            qty_after,
+        });
+    }
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qty(s: &str) -> Qty {
+        s.parse().unwrap()
+    }
+
+    fn item(new_qty: Option<&str>, reduce_by: Option<&str>, round: bool) -> RemoveItem {
+        RemoveItem {
+            row_num: 1,
+            line_id: 1,
+            concurrency_token: None,
+            new_qty: new_qty.map(qty),
+            reduce_by: reduce_by.map(qty),
+            round_to_pack_size: round,
+        }
+    }
+
+    fn target(current: &str, pack_size: &str, item: &RemoveItem) -> Result<Qty, String> {
+        target_qty(qty(current), qty(pack_size), item)
+    }
+
+    #[test]
+    fn test_full_removal() {
+        assert_eq!(target("30", "25", &item(None, None, false)), Ok(Qty::ZERO));
+    }
+
+    #[test]
+    fn test_new_qty_and_reduce_by() {
+        assert_eq!(
+            target("30", "25", &item(Some("12.5"), None, false)),
+            Ok(qty("12.5"))
+        );
+        assert_eq!(
+            target("30", "25", &item(None, Some("10"), false)),
+            Ok(qty("20"))
+        );
+        assert!(target("30", "25", &item(Some("1"), Some("1"), false)).is_err());
+    }
+
+    #[test]
+    fn test_reduction_is_exact() {
+        assert_eq!(
+            target("0.3", "0", &item(None, Some("0.1"), false)),
+            Ok(qty("0.2"))
+        );
+        assert_eq!(
+            target("1.005", "0", &item(None, Some("0.005"), false)),
+            Ok(qty("1"))
+        );
+    }
+
+    #[test]
+    fn test_rejects_out_of_range() {
+        assert!(target("30", "25", &item(Some("-1"), None, false)).is_err());
+        assert!(target("30", "25", &item(Some("31"), None, false)).is_err());
+        assert!(target("30", "25", &item(None, Some("31"), false)).is_err());
+        assert!(target("30", "25", &item(None, Some("-5"), false)).is_err());
+        assert!(target("30", "25", &item(Some("30"), None, false)).is_err());
+    }
+
+    #[test]
+    fn test_round_to_pack_size() {
+        assert_eq!(
+            target("80", "25", &item(Some("60"), None, true)),
+            Ok(qty("50"))
+        );
+        assert_eq!(
+            target("80", "25", &item(None, Some("5"), true)),
+            Ok(qty("75"))
+        );
+        assert_eq!(
+            target("80", "12.5", &item(Some("49.9999999999"), None, true)),
+            Ok(qty("50"))
+        );
+        assert_eq!(
+            target("80", "0.3", &item(Some("0.9"), None, true)),
+            Ok(qty("0.9"))
+        );
+        // Rounding to no change is refused
+        assert!(target("75", "25", &item(Some("75"), None, true)).is_err());
+    }
+
+    #[test]
+    fn test_unplanned_items() {
+        let line = RMLine {
+            run_no: 1001,
+            row_num: 1,
+            batch_no: "B1".to_string(),
+            line_typ: "FI".to_string(),
+            line_id: 1,
+            item_key: "ITEM".to_string(),
+            location: "TFC1".to_string(),
+            unit: "KG".to_string(),
+            standard_qty: qty("100"),
+            pack_size: qty("25"),
+            to_picked_partial_qty: qty("30"),
+            picked_partial_qty: None,
+            rec_user_id: "".to_string(),
+            modified_by: "".to_string(),
+            concurrency_token: "AB".to_string(),
+            item_description: None,
+            item_class: None,
+            storage_condition: None,
+            location_description: None,
+        };
+        let missing = RemoveItem {
+            row_num: 2,
+            ..item(None, None, false)
+        };
+        let items = vec![item(None, None, false), missing];
+
+        let planned = plan(&[line], &items);
+        assert_eq!(planned.len(), 1);
+        let unplanned = unplanned(&items, &planned);
+        assert_eq!(
+            unplanned
+                .iter()
+                .map(|i| (i.row_num, i.line_id))
+                .collect::<Vec<_>>(),
+            vec![(2, 1)]
+        );
+    }
+}
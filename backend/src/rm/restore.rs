@@ -0,0 +1,164 @@
+//! Restoring Removals
+//!
+//! Undoes one audited removal by putting `ToPickedPartialQty` back to the
+//! audit row's `QtyBefore`. Only the support CLI offers this. The restore is
+//! refused when the line changed after the removal, i.e. its quantity is no
+//! longer the removal's `QtyAfter` or it has been picked since.
+//!
+//! A restore is written to `cust_PartialPickRemovalAudit` with reason
+//! `RESTORE` and `RestoresAuditId` pointing at the removal, so it shows in
+//! the run history and is sent to webhooks as `partial_pick.restored`. The
+//! restored quantity is taken off the `User8` total, so the run summary no
+//! longer counts it as removed; `User3`/`User9` keep the last removal.
+//! Requires `sql/006_restores.sql`.
+//!
+//! Run leases live in the API server's memory, which the CLI cannot see: a
+//! restore goes through even while a station holds an exclusive lease on the
+//! run. Coordinate with the stations before restoring.
+
+use anyhow::Result;
+
+use crate::db::mssql::MssqlPool;
+use crate::db::row::column;
+use crate::models::qty::{Qty, QTY_SQL_TYPE};
+use crate::plant::PlantTime;
+use crate::rm::removal::user8_plus;
+use crate::rm::webhook::RESTORE_OUTBOX_OUTPUT;
+
+/// Reason code recorded for restores
+pub const RESTORE_REASON_CODE: &str = "RESTORE";
+
+/// What happened to a restore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreStatus {
+    Restored,
+    /// No removal with this audit id; restores themselves cannot be restored
+    NotFound,
+    AlreadyRestored,
+    /// The line changed after the removal
+    Conflict,
+}
+
+/// Result of restoring one removal
+#[derive(Debug, Clone)]
+pub struct RestoreOutcome {
+    pub status: RestoreStatus,
+    pub run_no: Option<i32>,
+    pub row_num: Option<i32>,
+    pub line_id: Option<i32>,
+    /// ToPickedPartialQty before and after the restore
+    pub qty_before: Option<Qty>,
+    pub qty_after: Option<Qty>,
+}
+
+/// Restore the removal recorded as `audit_id`
+pub async fn restore_removal(
+    pool: &MssqlPool,
+    audit_id: i32,
+    restored_by: &str,
+    comment: Option<&str>,
+    restored_at: PlantTime,
+) -> Result<RestoreOutcome> {
+    let sql = format!(
+        r#"
+        SET XACT_ABORT ON;
+        BEGIN TRANSACTION;
+
+        DECLARE @RunNo INT, @RowNum INT, @LineId INT, @Removed {qty}, @Left {qty},
+                @Current {qty}, @Status VARCHAR(20);
+
+        SELECT
+            @RunNo = RunNo, @RowNum = RowNum, @LineId = LineId,
+            @Removed = CAST(QtyBefore AS {qty}), @Left = CAST(QtyAfter AS {qty})
+        FROM cust_PartialPickRemovalAudit WITH (UPDLOCK, HOLDLOCK)
+        WHERE AuditId = @P1 AND RestoresAuditId IS NULL;
+
+        IF @RunNo IS NULL
+            SET @Status = 'not_found';
+        ELSE IF EXISTS (
+            SELECT 1 FROM cust_PartialPickRemovalAudit WITH (UPDLOCK, HOLDLOCK)
+            WHERE RestoresAuditId = @P1
+        )
+            SET @Status = 'already_restored';
+        ELSE
+        BEGIN
+            SELECT @Current = CAST(ToPickedPartialQty AS {qty})
+            FROM cust_PartialPicked WITH (UPDLOCK, HOLDLOCK)
+            WHERE RunNo = @RunNo AND RowNum = @RowNum AND LineId = @LineId
+              AND (PickedPartialQty IS NULL OR PickedPartialQty <= 0);
+
+            IF @Current IS NULL OR @Current <> @Left
+                SET @Status = 'conflict';
+            ELSE
+            BEGIN
+                UPDATE cust_PartialPicked
+                SET
+                    User8 = {user8},
+                    ToPickedPartialQty = @Removed,
+                    ModifiedBy = LEFT(@P2, 8),
+                    ModifiedDate = @P4
+                WHERE RunNo = @RunNo AND RowNum = @RowNum AND LineId = @LineId;
+
+                INSERT INTO cust_PartialPickRemovalAudit
+                    (RunNo, RowNum, LineId, BatchNo, ItemKey, QtyBefore, QtyAfter, ReasonCode,
+                     Comment, RemovedBy, RemovedDate, RestoresAuditId)
+                {outbox}
+                SELECT RunNo, RowNum, LineId, BatchNo, ItemKey, @Left, @Removed, '{reason}',
+                       @P3, @P2, ModifiedDate, @P1
+                FROM cust_PartialPicked
+                WHERE RunNo = @RunNo AND RowNum = @RowNum AND LineId = @LineId;
+
+                SET @Status = 'restored';
+            END
+        END
+
+        COMMIT TRANSACTION;
+
+        SELECT
+            @Status AS Status, @RunNo AS RunNo, @RowNum AS RowNum, @LineId AS LineId,
+            @Current AS QtyBefore, @Removed AS QtyAfter
+        "#,
+        qty = QTY_SQL_TYPE,
+        user8 = user8_plus("User8", "(@Left - @Removed)"),
+        outbox = RESTORE_OUTBOX_OUTPUT,
+        reason = RESTORE_REASON_CODE
+    );
+
+    let rows = pool
+        .execute_query_with_params(
+            &sql,
+            |query| {
+                query.bind(audit_id);
+                query.bind(restored_by.to_string());
+                query.bind(comment.map(str::to_string));
+                query.bind(restored_at.utc);
+            },
+            |row| {
+                let status = match column::<&str>(row, "Status")? {
+                    "restored" => RestoreStatus::Restored,
+                    "already_restored" => RestoreStatus::AlreadyRestored,
+                    "conflict" => RestoreStatus::Conflict,
+                    _ => RestoreStatus::NotFound,
+                };
+                let found = status != RestoreStatus::NotFound;
+                Ok(RestoreOutcome {
+                    status,
+                    run_no: column::<Option<i32>>(row, "RunNo")?.filter(|_| found),
+                    row_num: column::<Option<i32>>(row, "RowNum")?.filter(|_| found),
+                    line_id: column::<Option<i32>>(row, "LineId")?.filter(|_| found),
+                    qty_before: column(row, "QtyBefore")?,
+                    qty_after: column(row, "QtyAfter")?,
+                })
+            },
+        )
+        .await?;
+
+    Ok(rows.into_iter().next().unwrap_or(RestoreOutcome {
+        status: RestoreStatus::NotFound,
+        run_no: None,
+        row_num: None,
+        line_id: None,
+        qty_before: None,
+        qty_after: None,
+    }))
+}
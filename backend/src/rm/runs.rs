@@ -0,0 +1,162 @@
+//! Run Discovery
+//!
+//! Lists runs that still have lines eligible for removal, so operators can
+//! browse open work instead of typing run numbers. A run matches the filters
+//! when any of its open lines does; the counts, dates and batches cover all
+//! of its open lines.
+
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::db::mssql::MssqlPool;
+use crate::db::row::column;
+use crate::models::qty::QTY_SQL_TYPE;
+use crate::models::rm::OpenRun;
+use crate::plant::PlantClock;
+use crate::rm::lines::ELIGIBLE_FOR_REMOVAL;
+
+/// Runs returned when the request names no limit
+pub const DEFAULT_RUN_LIMIT: i32 = 100;
+/// Most runs one request may return
+pub const MAX_RUN_LIMIT: i32 = 1000;
+
+/// Which open runs to list
+#[derive(Debug, Clone, Default)]
+pub struct RunFilter {
+    /// First plant-local day an open line was last modified on, inclusive
+    pub from: Option<NaiveDate>,
+    /// Last plant-local day an open line was last modified on, inclusive
+    pub to: Option<NaiveDate>,
+    /// Runs with an open line of this item
+    pub item_key: Option<String>,
+    pub limit: i32,
+}
+
+/// Open runs matching `filter`, most recently modified first
+pub async fn open_runs(
+    pool: &MssqlPool,
+    clock: &PlantClock,
+    filter: &RunFilter,
+) -> Result<Vec<OpenRun>> {
+    let sql = format!(
+        r#"
+        WITH open_lines AS (
+            SELECT RunNo, BatchNo, ItemKey, ToPickedPartialQty, ModifiedDate
+            FROM cust_PartialPicked
+            WHERE {eligible}
+        ),
+        runs AS (
+            SELECT TOP (@P4)
+                RunNo,
+                COUNT(*) AS LineCount,
+                COUNT(DISTINCT ItemKey) AS ItemCount,
+                CAST(SUM(ToPickedPartialQty) AS {qty}) AS OutstandingQty,
+                MIN(ModifiedDate) AS EarliestModified,
+                MAX(ModifiedDate) AS LatestModified
+            FROM open_lines
+            WHERE RunNo IN (
+                SELECT RunNo FROM open_lines
+                WHERE (@P1 IS NULL OR ModifiedDate >= @P1)
+                  AND (@P2 IS NULL OR ModifiedDate < @P2)
+                  AND (@P3 IS NULL OR ItemKey = @P3)
+            )
+            GROUP BY RunNo
+            ORDER BY LatestModified DESC, RunNo DESC
+        )
+        SELECT r.*, b.BatchNo
+        FROM runs r
+        JOIN (SELECT DISTINCT RunNo, BatchNo FROM open_lines) b ON b.RunNo = r.RunNo
+        ORDER BY r.LatestModified DESC, r.RunNo DESC, b.BatchNo
+        "#,
+        eligible = ELIGIBLE_FOR_REMOVAL,
+        qty = QTY_SQL_TYPE
+    );
+
+    let from = filter.from.map(|day| clock.day_range(day).0);
+    let to = filter.to.map(|day| clock.day_range(day).1);
+    let item_key = filter
+        .item_key
+        .as_deref()
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(str::to_string);
+
+    let rows = pool
+        .execute_read_with_params(
+            &sql,
+            |query| {
+                query.bind(from);
+                query.bind(to);
+                query.bind(item_key.clone());
+                query.bind(filter.limit);
+            },
+            |row| {
+                Ok(OpenRun {
+                    run_no: column(row, "RunNo")?,
+                    line_count: column(row, "LineCount")?,
+                    item_count: column(row, "ItemCount")?,
+                    outstanding_qty: column(row, "OutstandingQty")?,
+                    earliest_modified: column::<Option<NaiveDateTime>>(row, "EarliestModified")?
+                        .map(|utc| clock.format(utc)),
+                    latest_modified: column::<Option<NaiveDateTime>>(row, "LatestModified")?
+                        .map(|utc| clock.format(utc)),
+                    batches: vec![column(row, "BatchNo")?],
+                    lease: None,
+                })
+            },
+        )
+        .await?;
+
+    Ok(merge_batches(rows))
+}
+
+/// Fold the one-row-per-batch result, ordered by run, into one entry per run
+pub fn merge_batches(rows: Vec<OpenRun>) -> Vec<OpenRun> {
+    let mut runs: Vec<OpenRun> = Vec::new();
+    for row in rows {
+        match runs.last_mut() {
+            Some(run) if run.run_no == row.run_no => run.batches.extend(row.batches),
+            _ => runs.push(row),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(run_no: i32, batch_no: &str) -> OpenRun {
+        OpenRun {
+            run_no,
+            line_count: 3,
+            item_count: 2,
+            outstanding_qty: "4.5".parse().unwrap(),
+            earliest_modified: None,
+            latest_modified: None,
+            batches: vec![batch_no.to_string()],
+            lease: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_batches_keeps_run_order() {
+        let runs = merge_batches(vec![
+            row(1002, "B1"),
+            row(1002, "B2"),
+            row(1001, "A1"),
+            row(1003, "C1"),
+        ]);
+
+        let summary: Vec<(i32, Vec<String>)> =
+            runs.into_iter().map(|r| (r.run_no, r.batches)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1002, vec!["B1".to_string(), "B2".to_string()]),
+                (1001, vec!["A1".to_string()]),
+                (1003, vec!["C1".to_string()]),
+            ]
+        );
+    }
+}
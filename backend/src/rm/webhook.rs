@@ -0,0 +1,737 @@
+//! Outbound Webhooks
+//!
+//! Removals write an outbox row for every audited line in the same
+//! transaction as the update (see [`OUTBOX_OUTPUT`]), so an event is recorded
+//! exactly when the change commits. A background worker fans new outbox rows
+//! out to the active subscriptions in `cust_PartialPickWebhook` and POSTs them
+//! with an HMAC-SHA256 signature. Failed deliveries are retried with
+//! exponential backoff and marked `DEAD` after the last attempt.
+//!
+//! Receivers verify `X-RM-Signature: sha256=<hex>`, computed over
+//! `<X-RM-Timestamp>.<body>` with the subscription secret.
+//!
+//! | Variable | Description | Default |
+//! |----------|-------------|---------|
+//! | `RM_WEBHOOK_POLL_SECS` | Worker interval, `0` disables delivery | `5` |
+//! | `RM_WEBHOOK_MAX_ATTEMPTS` | Attempts before a delivery is dead-lettered | `8` |
+//! | `RM_WEBHOOK_BACKOFF_SECS` | Delay after the first failure, doubled each time | `30` |
+//! | `RM_WEBHOOK_TIMEOUT_SECS` | HTTP timeout per attempt | `10` |
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use sha2::Sha256;
+use std::env;
+use std::time::Duration;
+
+use crate::db::mssql::MssqlPool;
+use crate::db::row::column;
+use crate::models::rm::{DeliveryStatus, Webhook, WebhookDelivery, WebhookEvent};
+use crate::plant::PlantClock;
+use crate::rm::history::{map_audit_entry, AUDIT_ENTRY_COLUMNS};
+
+/// OUTPUT clause for INSERTs into `cust_PartialPickRemovalAudit` that queues
+/// an outbox row per audited line in the same statement. Lines zeroed are
+/// `partial_pick.removed`, lines lowered to a target `partial_pick.reduced`.
+pub const OUTBOX_OUTPUT: &str = r#"
+    OUTPUT
+        CASE WHEN inserted.QtyAfter = 0 THEN 'partial_pick.removed' ELSE 'partial_pick.reduced' END,
+        inserted.RunNo,
+        inserted.AuditId,
+        inserted.RemovedDate
+    INTO cust_PartialPickOutbox (EventType, RunNo, AuditId, CreatedDate)"#;
+
+/// OUTPUT clause for the audit row of a restore, queued as `partial_pick.restored`
+pub const RESTORE_OUTBOX_OUTPUT: &str = r#"
+    OUTPUT 'partial_pick.restored', inserted.RunNo, inserted.AuditId, inserted.RemovedDate
+    INTO cust_PartialPickOutbox (EventType, RunNo, AuditId, CreatedDate)"#;
+
+const ENV_POLL_SECS: &str = "RM_WEBHOOK_POLL_SECS";
+const ENV_MAX_ATTEMPTS: &str = "RM_WEBHOOK_MAX_ATTEMPTS";
+const ENV_BACKOFF_SECS: &str = "RM_WEBHOOK_BACKOFF_SECS";
+const ENV_TIMEOUT_SECS: &str = "RM_WEBHOOK_TIMEOUT_SECS";
+
+/// Longest wait between two attempts
+const MAX_BACKOFF_SECS: u64 = 3600;
+/// Deliveries claimed per worker pass
+const BATCH_SIZE: i32 = 50;
+
+/// Delivery worker settings
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub poll_interval: Duration,
+    pub max_attempts: i32,
+    pub backoff: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(5),
+            max_attempts: 8,
+            backoff: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Load settings from the environment, `None` when delivery is disabled
+    pub fn from_env() -> Option<Self> {
+        fn parse(var: &str) -> Option<u64> {
+            env::var(var)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+        }
+
+        let defaults = Self::default();
+        let poll_interval = parse(ENV_POLL_SECS)
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.poll_interval);
+        if poll_interval.is_zero() {
+            return None;
+        }
+
+        Some(Self {
+            poll_interval,
+            max_attempts: parse(ENV_MAX_ATTEMPTS)
+                .filter(|v| *v > 0)
+                .map(|v| v.min(i32::MAX as u64) as i32)
+                .unwrap_or(defaults.max_attempts),
+            backoff: parse(ENV_BACKOFF_SECS)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.backoff),
+            timeout: parse(ENV_TIMEOUT_SECS)
+                .filter(|v| *v > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.timeout),
+        })
+    }
+
+    /// Wait before the next attempt after `attempts` failures
+    pub fn backoff_after(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+        let secs = self.backoff.as_secs().saturating_mul(1 << exponent);
+        Duration::from_secs(secs.min(MAX_BACKOFF_SECS))
+    }
+}
+
+/// Hex HMAC-SHA256 of `<timestamp>.<body>`
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn parse_event_types(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+const WEBHOOK_COLUMNS: &str = "WebhookId, Name, Url, Secret, EventTypes, Active";
+
+fn map_webhook(row: &tiberius::Row) -> Result<Webhook> {
+    Ok(Webhook {
+        webhook_id: column(row, "WebhookId")?,
+        name: column(row, "Name")?,
+        url: column(row, "Url")?,
+        secret: column(row, "Secret")?,
+        event_types: parse_event_types(column(row, "EventTypes")?),
+        active: column(row, "Active")?,
+    })
+}
+
+pub async fn list_webhooks(pool: &MssqlPool) -> Result<Vec<Webhook>> {
+    let sql = format!(
+        "SELECT {} FROM cust_PartialPickWebhook ORDER BY Name, WebhookId",
+        WEBHOOK_COLUMNS
+    );
+    pool.execute_query(&sql, map_webhook).await
+}
+
+/// Insert a subscription and return its id
+pub async fn create_webhook(pool: &MssqlPool, webhook: &Webhook, user: &str) -> Result<i32> {
+    let sql = r#"
+        INSERT INTO cust_PartialPickWebhook
+            (Name, Url, Secret, EventTypes, Active, ModifiedBy, ModifiedDate)
+        OUTPUT inserted.WebhookId
+        VALUES (@P1, @P2, @P3, @P4, @P5, @P6, GETUTCDATE())
+    "#;
+
+    let ids = pool
+        .execute_query_with_params(
+            sql,
+            |query| {
+                query.bind(webhook.name.clone());
+                query.bind(webhook.url.clone());
+                query.bind(webhook.secret.clone());
+                query.bind(webhook.event_types.join(","));
+                query.bind(webhook.active);
+                query.bind(user.to_string());
+            },
+            |row| Ok(column::<i32>(row, "WebhookId")?),
+        )
+        .await?;
+
+    ids.into_iter()
+        .next()
+        .context("Insert did not return a WebhookId")
+}
+
+/// Update a subscription, keeping the stored secret when none is given.
+/// Returns false if it does not exist.
+pub async fn update_webhook(pool: &MssqlPool, webhook: &Webhook, user: &str) -> Result<bool> {
+    let sql = r#"
+        UPDATE cust_PartialPickWebhook
+        SET
+            Name = @P2,
+            Url = @P3,
+            Secret = CASE WHEN @P4 = '' THEN Secret ELSE @P4 END,
+            EventTypes = @P5,
+            Active = @P6,
+            ModifiedBy = @P7,
+            ModifiedDate = GETUTCDATE()
+        WHERE WebhookId = @P1
+    "#;
+
+    let affected = pool
+        .execute_idempotent_update(sql, |query| {
+            query.bind(webhook.webhook_id);
+            query.bind(webhook.name.clone());
+            query.bind(webhook.url.clone());
+            query.bind(webhook.secret.clone());
+            query.bind(webhook.event_types.join(","));
+            query.bind(webhook.active);
+            query.bind(user.to_string());
+        })
+        .await?;
+
+    Ok(affected > 0)
+}
+
+/// Stop delivering to a subscription. Returns false if it does not exist.
+pub async fn deactivate_webhook(pool: &MssqlPool, webhook_id: i32, user: &str) -> Result<bool> {
+    let sql = r#"
+        UPDATE cust_PartialPickWebhook
+        SET Active = 0, ModifiedBy = @P2, ModifiedDate = GETUTCDATE()
+        WHERE WebhookId = @P1
+    "#;
+
+    let affected = pool
+        .execute_idempotent_update(sql, |query| {
+            query.bind(webhook_id);
+            query.bind(user.to_string());
+        })
+        .await?;
+
+    Ok(affected > 0)
+}
+
+fn map_delivery(row: &tiberius::Row, clock: &PlantClock) -> Result<WebhookDelivery> {
+    Ok(WebhookDelivery {
+        delivery_id: column(row, "DeliveryId")?,
+        webhook_id: column(row, "WebhookId")?,
+        webhook_name: column(row, "WebhookName")?,
+        outbox_id: column(row, "OutboxId")?,
+        event_type: column(row, "EventType")?,
+        run_no: column(row, "RunNo")?,
+        status: DeliveryStatus::from_db(column(row, "Status")?),
+        attempts: column(row, "Attempts")?,
+        next_attempt_date: clock.format(column(row, "NextAttemptDate")?),
+        last_status_code: column(row, "LastStatusCode")?,
+        last_error: column(row, "LastError")?,
+        created_date: clock.format(column(row, "CreatedDate")?),
+        delivered_date: column::<Option<NaiveDateTime>>(row, "DeliveredDate")?
+            .map(|d| clock.format(d)),
+    })
+}
+
+/// Deliveries in a given state, newest first
+pub async fn list_deliveries(
+    pool: &MssqlPool,
+    clock: &PlantClock,
+    status: DeliveryStatus,
+    webhook_id: Option<i32>,
+) -> Result<Vec<WebhookDelivery>> {
+    let sql = r#"
+        SELECT TOP 500
+            d.DeliveryId, d.WebhookId, w.Name AS WebhookName, d.OutboxId,
+            o.EventType, o.RunNo, d.Status, d.Attempts,
+            d.NextAttemptDate, d.LastStatusCode, d.LastError,
+            d.CreatedDate, d.DeliveredDate
+        FROM cust_PartialPickWebhookDelivery d
+        INNER JOIN cust_PartialPickWebhook w ON w.WebhookId = d.WebhookId
+        INNER JOIN cust_PartialPickOutbox o ON o.OutboxId = d.OutboxId
+        WHERE d.Status = @P1
+          AND (@P2 IS NULL OR d.WebhookId = @P2)
+        ORDER BY d.DeliveryId DESC
+    "#;
+
+    pool.execute_read_with_params(
+        sql,
+        |query| {
+            query.bind(status.as_db());
+            query.bind(webhook_id);
+        },
+        |row| map_delivery(row, clock),
+    )
+    .await
+}
+
+/// Put a dead delivery back in the queue. Returns false if it is not dead.
+pub async fn retry_delivery(pool: &MssqlPool, delivery_id: i32) -> Result<bool> {
+    let sql = r#"
+        UPDATE cust_PartialPickWebhookDelivery
+        SET Status = 'PENDING', Attempts = 0, NextAttemptDate = GETUTCDATE()
+        WHERE DeliveryId = @P1 AND Status = 'DEAD'
+    "#;
+
+    let affected = pool
+        .execute_update(sql, |query| {
+            query.bind(delivery_id);
+        })
+        .await?;
+
+    Ok(affected > 0)
+}
+
+/// A claimed delivery with everything needed to send it
+#[derive(Debug, Clone)]
+pub struct PendingDelivery {
+    pub delivery_id: i32,
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+    pub event: WebhookEvent,
+}
+
+/// Result of one HTTP attempt
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryAttempt {
+    pub delivered: bool,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// Create a delivery per matching active webhook for every new outbox row
+async fn dispatch_outbox(pool: &MssqlPool) -> Result<u64> {
+    // READPAST skips rows of removals that have not committed yet; they are
+    // picked up on a later pass
+    let sql = r#"
+        SET XACT_ABORT ON;
+        BEGIN TRANSACTION;
+
+        SELECT OutboxId, EventType
+        INTO #Batch
+        FROM cust_PartialPickOutbox WITH (UPDLOCK, READPAST)
+        WHERE DispatchedDate IS NULL;
+
+        INSERT INTO cust_PartialPickWebhookDelivery (OutboxId, WebhookId, NextAttemptDate, CreatedDate)
+        SELECT b.OutboxId, w.WebhookId, GETUTCDATE(), GETUTCDATE()
+        FROM #Batch b
+        INNER JOIN cust_PartialPickWebhook w
+            ON w.Active = 1
+           AND (',' + REPLACE(w.EventTypes, ' ', '') + ',' LIKE '%,' + b.EventType + ',%'
+                OR ',' + REPLACE(w.EventTypes, ' ', '') + ',' LIKE '%,*,%');
+
+        UPDATE o
+        SET DispatchedDate = GETUTCDATE()
+        FROM cust_PartialPickOutbox o
+        INNER JOIN #Batch b ON b.OutboxId = o.OutboxId;
+
+        COMMIT TRANSACTION;
+
+        DROP TABLE #Batch;
+    "#;
+
+    pool.execute_idempotent_update(sql, |_| {}).await
+}
+
+/// Claim due deliveries so a second instance does not send them at the same time
+async fn claim_due(
+    pool: &MssqlPool,
+    plant: &str,
+    clock: &PlantClock,
+    config: &WebhookConfig,
+) -> Result<Vec<PendingDelivery>> {
+    let sql = format!(
+        r#"
+        SET XACT_ABORT ON;
+        CREATE TABLE #Claimed (DeliveryId INT PRIMARY KEY);
+
+        WITH due AS (
+            SELECT TOP (@P1) d.DeliveryId, d.NextAttemptDate
+            FROM cust_PartialPickWebhookDelivery d WITH (UPDLOCK, READPAST, ROWLOCK)
+            INNER JOIN cust_PartialPickWebhook w ON w.WebhookId = d.WebhookId
+            WHERE d.Status = 'PENDING'
+              AND d.NextAttemptDate <= GETUTCDATE()
+              AND w.Active = 1
+            ORDER BY d.NextAttemptDate, d.DeliveryId
+        )
+        UPDATE due
+        SET NextAttemptDate = DATEADD(SECOND, @P2, GETUTCDATE())
+        OUTPUT inserted.DeliveryId INTO #Claimed;
+
+        SELECT
+            d.DeliveryId, d.Attempts, w.Url, w.Secret,
+            o.OutboxId, o.EventType,
+            o.CreatedDate AS EventDate,
+            {audit}
+        FROM #Claimed c
+        INNER JOIN cust_PartialPickWebhookDelivery d ON d.DeliveryId = c.DeliveryId
+        INNER JOIN cust_PartialPickWebhook w ON w.WebhookId = d.WebhookId
+        INNER JOIN cust_PartialPickOutbox o ON o.OutboxId = d.OutboxId
+        INNER JOIN cust_PartialPickRemovalAudit a ON a.AuditId = o.AuditId
+        LEFT JOIN cust_PartialPickReasonCode r ON r.ReasonCode = a.ReasonCode
+        ORDER BY d.DeliveryId;
+
+        DROP TABLE #Claimed;
+        "#,
+        audit = AUDIT_ENTRY_COLUMNS
+    );
+
+    // Hold the claim long enough for the attempt to finish or time out
+    let claim_secs = config.timeout.as_secs() as i32 * 2 + 30;
+
+    pool.execute_query_with_params(
+        &sql,
+        |query| {
+            query.bind(BATCH_SIZE);
+            query.bind(claim_secs);
+        },
+        |row| {
+            Ok(PendingDelivery {
+                delivery_id: column(row, "DeliveryId")?,
+                attempts: column(row, "Attempts")?,
+                url: column(row, "Url")?,
+                secret: column(row, "Secret")?,
+                event: WebhookEvent {
+                    event_id: column(row, "OutboxId")?,
+                    event_type: column(row, "EventType")?,
+                    plant: plant.to_string(),
+                    created_date: clock.format(column(row, "EventDate")?),
+                    data: map_audit_entry(row, clock)?,
+                },
+            })
+        },
+    )
+    .await
+}
+
+/// POST one delivery to its webhook
+pub async fn deliver(client: &reqwest::Client, delivery: &PendingDelivery) -> DeliveryAttempt {
+    let body = match serde_json::to_vec(&delivery.event) {
+        Ok(body) => body,
+        Err(e) => {
+            return DeliveryAttempt {
+                delivered: false,
+                status_code: None,
+                error: Some(format!("Failed to serialize event: {}", e)),
+            }
+        }
+    };
+    let timestamp = Utc::now().timestamp();
+    let signature = sign(&delivery.secret, timestamp, &body);
+
+    let response = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-RM-Event", delivery.event.event_type.as_str())
+        .header("X-RM-Delivery", delivery.delivery_id.to_string())
+        .header("X-RM-Timestamp", timestamp.to_string())
+        .header("X-RM-Signature", format!("sha256={}", signature))
+        .body(body)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => DeliveryAttempt {
+            delivered: true,
+            status_code: Some(response.status().as_u16()),
+            error: None,
+        },
+        Ok(response) => DeliveryAttempt {
+            delivered: false,
+            status_code: Some(response.status().as_u16()),
+            error: Some(format!("Receiver responded with {}", response.status())),
+        },
+        Err(e) => DeliveryAttempt {
+            delivered: false,
+            status_code: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+async fn record_attempt(
+    pool: &MssqlPool,
+    config: &WebhookConfig,
+    delivery: &PendingDelivery,
+    attempt: &DeliveryAttempt,
+) -> Result<()> {
+    let sql = r#"
+        UPDATE cust_PartialPickWebhookDelivery
+        SET
+            Attempts = Attempts + 1,
+            Status = CASE
+                WHEN @P2 = 1 THEN 'DELIVERED'
+                WHEN Attempts + 1 >= @P5 THEN 'DEAD'
+                ELSE 'PENDING'
+            END,
+            NextAttemptDate = DATEADD(SECOND, @P6, GETUTCDATE()),
+            LastStatusCode = @P3,
+            LastError = LEFT(@P4, 1000),
+            DeliveredDate = CASE WHEN @P2 = 1 THEN GETUTCDATE() ELSE NULL END
+        WHERE DeliveryId = @P1
+    "#;
+
+    let backoff = config.backoff_after(delivery.attempts + 1).as_secs() as i32;
+
+    pool.execute_update(sql, |query| {
+        query.bind(delivery.delivery_id);
+        query.bind(attempt.delivered);
+        query.bind(attempt.status_code.map(i32::from));
+        query.bind(attempt.error.clone());
+        query.bind(config.max_attempts);
+        query.bind(backoff);
+    })
+    .await?;
+
+    Ok(())
+}
+
+async fn run_once(
+    pool: &MssqlPool,
+    plant: &str,
+    clock: &PlantClock,
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+) -> Result<()> {
+    dispatch_outbox(pool).await?;
+
+    for delivery in claim_due(pool, plant, clock, config).await? {
+        let attempt = deliver(client, &delivery).await;
+        let attempts = delivery.attempts + 1;
+
+        if attempt.delivered {
+            info!(
+                "Webhook delivery #{} ({}) sent to {}",
+                delivery.delivery_id, delivery.event.event_type, delivery.url
+            );
+        } else if attempts >= config.max_attempts {
+            error!(
+                "Webhook delivery #{} to {} dead after {} attempts: {}",
+                delivery.delivery_id,
+                delivery.url,
+                attempts,
+                attempt.error.as_deref().unwrap_or_default()
+            );
+        } else {
+            warn!(
+                "Webhook delivery #{} to {} failed (attempt {}): {}",
+                delivery.delivery_id,
+                delivery.url,
+                attempts,
+                attempt.error.as_deref().unwrap_or_default()
+            );
+        }
+
+        record_attempt(pool, config, &delivery, &attempt).await?;
+    }
+
+    Ok(())
+}
+
+/// Deliver a plant's queued events in the background until the process exits
+pub fn spawn_worker(
+    pool: MssqlPool,
+    plant: String,
+    clock: PlantClock,
+    config: WebhookConfig,
+) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(config.timeout)
+        .build()
+        .context("Failed to build webhook HTTP client")?;
+
+    info!(
+        "Webhook worker for plant {} delivering every {} seconds",
+        plant,
+        config.poll_interval.as_secs()
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.poll_interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = run_once(&pool, &plant, &clock, &client, &config).await {
+                error!("Webhook worker pass for plant {} failed: {}", plant, e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::qty::Qty;
+    use crate::models::rm::RemovalAuditEntry;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_backoff_doubles_up_to_cap() {
+        let config = WebhookConfig::default();
+        assert_eq!(config.backoff_after(1), Duration::from_secs(30));
+        assert_eq!(config.backoff_after(2), Duration::from_secs(60));
+        assert_eq!(config.backoff_after(4), Duration::from_secs(240));
+        assert_eq!(
+            config.backoff_after(12),
+            Duration::from_secs(MAX_BACKOFF_SECS)
+        );
+    }
+
+    #[test]
+    fn test_sign() {
+        // printf '1700000000.{}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            sign("secret", 1_700_000_000, b"{}"),
+            "b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163"
+        );
+        assert_ne!(sign("secret", 1, b"{}"), sign("other", 1, b"{}"));
+    }
+
+    #[test]
+    fn test_parse_event_types() {
+        assert_eq!(
+            parse_event_types(" partial_pick.removed , ,partial_pick.reduced"),
+            vec!["partial_pick.removed", "partial_pick.reduced"]
+        );
+    }
+
+    /// Accept one request on a local port, answer with `status` and return the raw request
+    async fn receive_one(status: u16) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_ascii_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 {} X\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                status
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        (url, handle)
+    }
+
+    fn pending(url: String) -> PendingDelivery {
+        PendingDelivery {
+            delivery_id: 7,
+            attempts: 0,
+            url,
+            secret: "s3cret".to_string(),
+            event: WebhookEvent {
+                event_id: 42,
+                event_type: "partial_pick.removed".to_string(),
+                plant: "TH1".to_string(),
+                created_date: "2024-01-01T08:00:00+07:00".to_string(),
+                data: RemovalAuditEntry {
+                    audit_id: 1,
+                    run_no: 1001,
+                    row_num: 2,
+                    line_id: 3,
+                    batch_no: "B1".to_string(),
+                    item_key: "SUGAR".to_string(),
+                    qty_before: "12.5".parse().unwrap(),
+                    qty_after: Qty::ZERO,
+                    reason_code: "QA_HOLD".to_string(),
+                    reason_description: "QA hold".to_string(),
+                    comment: None,
+                    removed_by: "alice".to_string(),
+                    approved_by: None,
+                    approval_request_id: None,
+                    removed_date: "2024-01-01T08:00:00+07:00".to_string(),
+                },
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deliver_signs_request_for_local_receiver() {
+        let (url, receiver) = receive_one(204).await;
+        let client = reqwest::Client::new();
+
+        let attempt = deliver(&client, &pending(url)).await;
+        assert!(attempt.delivered);
+        assert_eq!(attempt.status_code, Some(204));
+
+        let request = receiver.await.unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        let header = |name: &str| {
+            head.lines()
+                .find_map(|l| {
+                    let (k, v) = l.split_once(':')?;
+                    k.eq_ignore_ascii_case(name).then(|| v.trim().to_string())
+                })
+                .unwrap()
+        };
+
+        assert!(head.starts_with("POST /hook"));
+        assert_eq!(header("x-rm-event"), "partial_pick.removed");
+        let timestamp: i64 = header("x-rm-timestamp").parse().unwrap();
+        assert_eq!(
+            header("x-rm-signature"),
+            format!("sha256={}", sign("s3cret", timestamp, body.as_bytes()))
+        );
+        assert!(body.contains("\"EventType\":\"partial_pick.removed\""));
+    }
+
+    #[tokio::test]
+    async fn test_deliver_reports_receiver_failure() {
+        let (url, receiver) = receive_one(503).await;
+        let client = reqwest::Client::new();
+
+        let attempt = deliver(&client, &pending(url)).await;
+        receiver.await.unwrap();
+        assert!(!attempt.delivered);
+        assert_eq!(attempt.status_code, Some(503));
+        assert!(attempt.error.is_some());
+    }
+}
@@ -0,0 +1,260 @@
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::auth::{AuthenticatedUser, ROLE_SUPERVISOR};
+use crate::models::rm::{
+    ApprovalDecisionRequest, ApprovalListResponse, ApprovalRequest, ApprovalStatus, RemoveResponse,
+};
+use crate::plant::{CurrentPlant, Plant};
+use crate::rm::approval;
+use crate::rm::reason::RemovalReason;
+use crate::rm::removal::{remove_items, RemovalContext};
+use crate::routes::database_error_status;
+use crate::routes::lease::lease_error_response;
+use crate::routes::rm::{removal_response, run_detached};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_approvals)
+        .service(approve_removal)
+        .service(reject_removal);
+}
+
+#[derive(Debug, Deserialize)]
+struct ApprovalListQuery {
+    status: Option<ApprovalStatus>,
+    run_no: Option<i32>,
+}
+
+#[get("/rm/approvals")]
+async fn list_approvals(
+    plant: CurrentPlant,
+    _user: AuthenticatedUser,
+    query: web::Query<ApprovalListQuery>,
+) -> impl Responder {
+    match approval::list_requests(&plant.pool, &plant.clock, query.status, query.run_no).await {
+        Ok(requests) => HttpResponse::Ok().json(ApprovalListResponse {
+            success: true,
+            message: format!("Found {} requests", requests.len()),
+            data: requests,
+        }),
+        Err(e) => {
+            error!("Database error listing approval requests: {}", e);
+            HttpResponse::build(database_error_status(&e)).json(ApprovalListResponse {
+                success: false,
+                data: vec![],
+                message: format!("Database error: {}", e),
+            })
+        }
+    }
+}
+
+fn decision_error(status: StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(RemoveResponse {
+        success: false,
+        message,
+        affected_rows: 0,
+        results: vec![],
+        approval_request_id: None,
+    })
+}
+
+/// Check that `user` may decide on the request and load it
+async fn load_for_decision(
+    plant: &Plant,
+    user: &AuthenticatedUser,
+    request_id: i32,
+) -> Result<ApprovalRequest, HttpResponse> {
+    if !user.has_role(ROLE_SUPERVISOR) {
+        warn!(
+            "User {} tried to decide approval request #{} without supervisor role",
+            user.username, request_id
+        );
+        return Err(decision_error(
+            StatusCode::FORBIDDEN,
+            "Supervisor role required".to_string(),
+        ));
+    }
+
+    let request = match approval::get_request(&plant.pool, &plant.clock, request_id).await {
+        Ok(Some(request)) => request,
+        Ok(None) => {
+            return Err(decision_error(
+                StatusCode::NOT_FOUND,
+                format!("Approval request #{} not found", request_id),
+            ))
+        }
+        Err(e) => {
+            error!("Database error loading approval request: {}", e);
+            return Err(decision_error(
+                database_error_status(&e),
+                format!("Database error: {}", e),
+            ));
+        }
+    };
+
+    if request.status != ApprovalStatus::Pending {
+        return Err(decision_error(
+            StatusCode::CONFLICT,
+            format!(
+                "Approval request #{} is already {:?}",
+                request_id, request.status
+            ),
+        ));
+    }
+
+    // Four eyes: the requester cannot sign off their own removal
+    if request.requested_by.eq_ignore_ascii_case(&user.username) {
+        return Err(decision_error(
+            StatusCode::FORBIDDEN,
+            "A removal must be approved by a different user".to_string(),
+        ));
+    }
+
+    Ok(request)
+}
+
+#[post("/rm/approvals/{request_id}/approve")]
+async fn approve_removal(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    body: Option<web::Json<ApprovalDecisionRequest>>,
+) -> impl Responder {
+    let request_id = path.into_inner();
+    let (comment, lease_id) = match body {
+        Some(body) => {
+            let body = body.into_inner();
+            (body.comment, body.lease_id)
+        }
+        None => (None, None),
+    };
+
+    let request = match load_for_decision(&plant, &user, request_id).await {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
+
+    // Checked before the request is claimed so a locked run leaves it pending
+    if let Err(e) = plant
+        .leases
+        .check_write(request.run_no, lease_id.as_deref(), &user.username)
+    {
+        return lease_error_response(e);
+    }
+
+    // Claiming the request, the removal and recording its result run in their
+    // own task: dropped half-way with the supervisor's connection, the request
+    // would stay APPROVED without its removal and could not be approved again
+    run_detached(async move { apply_approval(&plant, &user, request, request_id, comment).await })
+        .await
+}
+
+/// Claim a pending request for `user` and run its removal
+async fn apply_approval(
+    plant: &Plant,
+    user: &AuthenticatedUser,
+    request: ApprovalRequest,
+    request_id: i32,
+    comment: Option<String>,
+) -> HttpResponse {
+    // Claim the request first so two supervisors cannot both run it
+    match approval::decide_request(
+        &plant.pool,
+        request_id,
+        ApprovalStatus::Approved,
+        &user.username,
+        comment,
+    )
+    .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            return decision_error(
+                StatusCode::CONFLICT,
+                format!("Approval request #{} was already decided", request_id),
+            )
+        }
+        Err(e) => {
+            error!("Database error approving request #{}: {}", request_id, e);
+            return decision_error(database_error_status(&e), format!("Database error: {}", e));
+        }
+    }
+
+    info!(
+        "Approval request #{} for RunNo: {} approved by {} ({}), requested by {}",
+        request_id, request.run_no, user.username, user.display_name, request.requested_by
+    );
+
+    let ctx = RemovalContext {
+        removed_by: request.requested_by.clone(),
+        approved_by: Some(user.username.clone()),
+        approval_request_id: Some(request_id),
+        reason: RemovalReason {
+            code: request.reason_code.clone(),
+            comment: Some(request.reason.clone()).filter(|c| !c.is_empty()),
+        },
+        removed_at: plant.clock.now(),
+    };
+    let outcome = remove_items(&plant.pool, request.run_no, &request.items, &ctx).await;
+    plant
+        .events
+        .publish_removals(request.run_no, &outcome, &ctx.removed_by);
+
+    if let Err(e) = approval::record_result(&plant.pool, request_id, outcome.total_affected).await {
+        error!(
+            "Failed to record result of approval request #{}: {}",
+            request_id, e
+        );
+    }
+
+    removal_response(outcome, Some(request_id))
+}
+
+#[post("/rm/approvals/{request_id}/reject")]
+async fn reject_removal(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    body: Option<web::Json<ApprovalDecisionRequest>>,
+) -> impl Responder {
+    let request_id = path.into_inner();
+    let comment = body.and_then(|b| b.into_inner().comment);
+
+    if let Err(response) = load_for_decision(&plant, &user, request_id).await {
+        return response;
+    }
+
+    match approval::decide_request(
+        &plant.pool,
+        request_id,
+        ApprovalStatus::Rejected,
+        &user.username,
+        comment,
+    )
+    .await
+    {
+        Ok(true) => {
+            info!(
+                "Approval request #{} rejected by {}",
+                request_id, user.username
+            );
+            HttpResponse::Ok().json(RemoveResponse {
+                success: true,
+                message: format!("Approval request #{} rejected", request_id),
+                affected_rows: 0,
+                results: vec![],
+                approval_request_id: Some(request_id),
+            })
+        }
+        Ok(false) => decision_error(
+            StatusCode::CONFLICT,
+            format!("Approval request #{} was already decided", request_id),
+        ),
+        Err(e) => {
+            error!("Database error rejecting request #{}: {}", request_id, e);
+            decision_error(database_error_status(&e), format!("Database error: {}", e))
+        }
+    }
+}
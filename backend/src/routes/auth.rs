@@ -1,59 +1,70 @@
 use actix_web::{post, web, HttpResponse, Responder};
 use jsonwebtoken::{encode, EncodingKey, Header};
 use log::{error, info, warn};
-use std::env;
 use std::time::{SystemTime, UNIX_EPOCH};
+use tiberius::Row;
 
-use crate::db::mssql::{get_string, MssqlPool};
-use crate::ldap::{self, LdapError, LdapUser};
+use crate::auth::{jwt_secret, roles_for, users};
+use crate::db::row::{column, FromRow, RowError};
+use crate::ldap::{LdapDirectory, LdapError, LdapUser};
 use crate::models::auth::{Claims, LoginRequest, LoginResponse, UserInfo};
+use crate::plant::{Plant, PlantRegistry};
+use crate::routes::database_error_status;
+use crate::server::security::no_store;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(login);
+    // Token responses must not end up in browser or proxy caches
+    cfg.service(web::scope("/auth").wrap(no_store()).service(login));
 }
 
-const JWT_SECRET_ENV: &str = "JWT_SECRET";
-const DEFAULT_JWT_SECRET: &str = "rm-partial-pick-remover-secret-key-change-in-production";
 const TOKEN_EXPIRATION_HOURS: usize = 8;
 
-#[post("/auth/login")]
-async fn login(pool: web::Data<MssqlPool>, request: web::Json<LoginRequest>) -> impl Responder {
-    let LoginRequest { username, password } = request.into_inner();
+#[post("/login")]
+async fn login(
+    registry: web::Data<PlantRegistry>,
+    directory: web::Data<LdapDirectory>,
+    request: web::Json<LoginRequest>,
+) -> impl Responder {
+    let LoginRequest {
+        username,
+        password,
+        plant,
+    } = request.into_inner();
 
-    info!("Login attempt for user: {}", username);
+    let Some(plant) = registry.for_login(plant.as_deref()) else {
+        return HttpResponse::BadRequest().json(LoginResponse {
+            success: false,
+            token: None,
+            user: None,
+            message: format!("Unknown plant: {}", plant.unwrap_or_default()),
+        });
+    };
+
+    info!(
+        "Login attempt for user: {} (plant: {})",
+        username, plant.code
+    );
 
     // Check if it's a LOCAL user (SQL authentication)
     if username.to_uppercase().starts_with("LOCAL") {
-        return handle_local_login(pool, username, password).await;
+        return handle_local_login(&registry, plant, username, password).await;
     }
 
     // Try LDAP authentication first
-    match authenticate_ldap(&username, &password).await {
+    match authenticate_ldap(&directory, plant, &username, &password).await {
         Ok(ldap_user) => {
             info!(
                 "LDAP authentication successful for user: {} (display_name: {})",
                 username, ldap_user.display_name
             );
-            match generate_token_with_display_name(&ldap_user.username, &ldap_user.display_name) {
-                Ok(token) => HttpResponse::Ok().json(LoginResponse {
-                    success: true,
-                    token: Some(token),
-                    user: Some(UserInfo {
-                        username: ldap_user.username.clone(),
-                        display_name: ldap_user.display_name.clone(),
-                    }),
-                    message: "Login successful".to_string(),
-                }),
-                Err(e) => {
-                    error!("Failed to generate token: {}", e);
-                    HttpResponse::InternalServerError().json(LoginResponse {
-                        success: false,
-                        token: None,
-                        user: None,
-                        message: "Failed to generate authentication token".to_string(),
-                    })
-                }
-            }
+            let user = UserInfo {
+                username: ldap_user.username.clone(),
+                display_name: ldap_user.display_name.clone(),
+                roles: roles_for(&ldap_user.username),
+                plant: String::new(),
+                plants: vec![],
+            };
+            issue_token(&registry, plant, user, "Login successful")
         }
         Err(LdapError::AuthError(_)) => {
             // LDAP authentication explicitly failed (invalid credentials)
@@ -79,45 +90,161 @@ async fn login(pool: web::Data<MssqlPool>, request: web::Json<LoginRequest>) ->
             // LDAP error (connection, configuration, timeout, etc.)
             // Fall back to SQL authentication for non-LDAP users
             error!("LDAP error for user {}: {}", username, e);
-            handle_sql_fallback(pool, username, password).await
+            handle_sql_fallback(&registry, plant, username, password).await
+        }
+    }
+}
+
+/// Sign a token for a verified user if they may work at the plant
+fn issue_token(
+    registry: &PlantRegistry,
+    plant: &Plant,
+    mut user: UserInfo,
+    message: &str,
+) -> HttpResponse {
+    let plants = registry.plants_for(&user.username);
+    if !plants.contains(&plant.code) {
+        warn!(
+            "User {} is not authorized for plant {}",
+            user.username, plant.code
+        );
+        return HttpResponse::Forbidden().json(LoginResponse {
+            success: false,
+            token: None,
+            user: None,
+            message: format!("Not authorized for plant {}", plant.code),
+        });
+    }
+    user.plant = plant.code.clone();
+    user.plants = plants;
+
+    match generate_token(&user) {
+        Ok(token) => HttpResponse::Ok().json(LoginResponse {
+            success: true,
+            token: Some(token),
+            user: Some(user),
+            message: message.to_string(),
+        }),
+        Err(e) => {
+            error!("Failed to generate token: {}", e);
+            HttpResponse::InternalServerError().json(LoginResponse {
+                success: false,
+                token: None,
+                user: None,
+                message: "Failed to generate authentication token".to_string(),
+            })
+        }
+    }
+}
+
+/// Columns of `tbl_user` and `cust_PartialPickUserAccount` read at login
+const USER_COLUMNS: &str = "u.uname, u.Fname, u.Lname, u.pword, u.auth_source, a.Roles, a.Disabled";
+
+/// `tbl_user` with the settings of LOCAL accounts
+const USER_TABLES: &str = "tbl_user u LEFT JOIN cust_PartialPickUserAccount a ON a.uname = u.uname";
+
+/// A `tbl_user` row selected with `USER_COLUMNS`
+struct UserRow {
+    uname: String,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    /// bcrypt hash, plaintext for rows never reset, NULL for directory accounts
+    password: Option<String>,
+    auth_source: String,
+    /// Roles stored by an admin, NULL without an account row
+    roles: Option<String>,
+    disabled: Option<bool>,
+}
+
+impl FromRow for UserRow {
+    fn from_row(row: &Row) -> Result<Self, RowError> {
+        Ok(UserRow {
+            uname: column(row, "uname")?,
+            first_name: column(row, "Fname")?,
+            last_name: column(row, "Lname")?,
+            password: column(row, "pword")?,
+            auth_source: column(row, "auth_source")?,
+            roles: column(row, "Roles")?,
+            disabled: column(row, "Disabled")?,
+        })
+    }
+}
+
+impl UserRow {
+    /// Configured roles plus the ones stored for the account
+    fn roles(&self) -> Vec<String> {
+        let mut roles = roles_for(&self.uname);
+        for role in users::parse_roles(self.roles.as_deref()) {
+            if !roles.contains(&role) {
+                roles.push(role);
+            }
+        }
+        roles
+    }
+
+    /// Fname and Lname combined, the username when both are empty
+    fn display_name(&self) -> String {
+        let name = format!(
+            "{} {}",
+            self.first_name.as_deref().unwrap_or_default(),
+            self.last_name.as_deref().unwrap_or_default()
+        )
+        .trim()
+        .to_string();
+        if name.is_empty() {
+            self.uname.clone()
+        } else {
+            name
         }
     }
 }
 
+/// Refuse an account an admin has disabled
+fn disabled_response(row: &UserRow) -> Option<HttpResponse> {
+    if !row.disabled.unwrap_or(false) {
+        return None;
+    }
+    warn!("Disabled LOCAL user {} tried to log in", row.uname);
+    Some(HttpResponse::Forbidden().json(LoginResponse {
+        success: false,
+        token: None,
+        user: None,
+        message: "Account is disabled".to_string(),
+    }))
+}
+
 async fn handle_local_login(
-    pool: web::Data<MssqlPool>,
+    registry: &PlantRegistry,
+    plant: &Plant,
     username: String,
     password: String,
 ) -> HttpResponse {
     // Use parameterized query to prevent SQL injection
     // Query tbl_user table for LOCAL authentication
-    let sql = r#"
-        SELECT uname, Fname, Lname, pword, auth_source
-        FROM tbl_user
-        WHERE uname = @P1 AND auth_source = 'LOCAL'
-        "#;
-
-    let result = pool
-        .execute_query_with_params(
-            sql,
+    let sql = format!(
+        "SELECT {} FROM {} WHERE u.uname = @P1 AND u.auth_source = 'LOCAL'",
+        USER_COLUMNS, USER_TABLES
+    );
+
+    let result = plant
+        .pool
+        .execute_read_with_params(
+            &sql,
             move |query| {
                 query.bind(username.clone());
             },
-            |row| {
-                Ok((
-                    get_string(row, "uname"),
-                    get_string(row, "Fname"),
-                    get_string(row, "Lname"),
-                    get_string(row, "pword"),
-                    get_string(row, "auth_source"),
-                ))
-            },
+            |row| Ok(UserRow::from_row(row)?),
         )
         .await;
 
     match result {
-        Ok(users) if !users.is_empty() => {
-            let (uname, fname, lname, stored_password, auth_source) = &users[0];
+        Ok(rows) if !rows.is_empty() => {
+            let UserRow {
+                uname,
+                auth_source,
+                password: stored_password,
+                ..
+            } = &rows[0];
 
             // Verify this is a LOCAL user
             if auth_source != "LOCAL" {
@@ -129,8 +256,7 @@ async fn handle_local_login(
                 });
             }
 
-            // Plaintext password comparison (matches BME-Partial-Picking)
-            if *stored_password != password {
+            if !users::verify_password(password, stored_password.clone()).await {
                 return HttpResponse::Unauthorized().json(LoginResponse {
                     success: false,
                     token: None,
@@ -139,30 +265,19 @@ async fn handle_local_login(
                 });
             }
 
-            // Combine Fname and Lname for display name
-            let display_name = format!("{} {}", fname, lname).trim().to_string();
+            if let Some(response) = disabled_response(&rows[0]) {
+                return response;
+            }
+
             let user = UserInfo {
                 username: uname.clone(),
-                display_name: if display_name.is_empty() { uname.clone() } else { display_name },
+                display_name: rows[0].display_name(),
+                roles: rows[0].roles(),
+                plant: String::new(),
+                plants: vec![],
             };
 
-            match generate_token(&user.username) {
-                Ok(token) => HttpResponse::Ok().json(LoginResponse {
-                    success: true,
-                    token: Some(token),
-                    user: Some(user),
-                    message: "Login successful".to_string(),
-                }),
-                Err(e) => {
-                    error!("Failed to generate token: {}", e);
-                    HttpResponse::InternalServerError().json(LoginResponse {
-                        success: false,
-                        token: None,
-                        user: None,
-                        message: "Failed to generate authentication token".to_string(),
-                    })
-                }
-            }
+            issue_token(registry, plant, user, "Login successful")
         }
         Ok(_) => HttpResponse::Unauthorized().json(LoginResponse {
             success: false,
@@ -172,7 +287,7 @@ async fn handle_local_login(
         }),
         Err(e) => {
             error!("Database error during local login: {}", e);
-            HttpResponse::InternalServerError().json(LoginResponse {
+            HttpResponse::build(database_error_status(&e)).json(LoginResponse {
                 success: false,
                 token: None,
                 user: None,
@@ -183,7 +298,8 @@ async fn handle_local_login(
 }
 
 async fn handle_sql_fallback(
-    pool: web::Data<MssqlPool>,
+    registry: &PlantRegistry,
+    plant: &Plant,
     username: String,
     password: String,
 ) -> HttpResponse {
@@ -197,41 +313,35 @@ async fn handle_sql_fallback(
 
     // Use parameterized query to prevent SQL injection
     // Query tbl_user table - only allow LOCAL users for SQL fallback
-    let sql = r#"
-        SELECT uname, Fname, Lname, pword, auth_source
-        FROM tbl_user
-        WHERE uname = @P1
-        "#;
-
-    let result = pool
-        .execute_query_with_params(
-            sql,
+    let sql = format!(
+        "SELECT {} FROM {} WHERE u.uname = @P1",
+        USER_COLUMNS, USER_TABLES
+    );
+
+    let result = plant
+        .pool
+        .execute_read_with_params(
+            &sql,
             move |query| {
-                query.bind(username_for_query);
-            },
-            |row| {
-                Ok((
-                    get_string(row, "uname"),
-                    get_string(row, "Fname"),
-                    get_string(row, "Lname"),
-                    get_string(row, "pword"),
-                    get_string(row, "auth_source"),
-                ))
+                query.bind(username_for_query.clone());
             },
+            |row| Ok(UserRow::from_row(row)?),
         )
         .await;
 
     match result {
-        Ok(users) if !users.is_empty() => {
-            let (uname, fname, lname, stored_password, auth_source) = &users[0];
+        Ok(rows) if !rows.is_empty() => {
+            let UserRow {
+                uname,
+                auth_source,
+                password: stored_password,
+                ..
+            } = &rows[0];
 
             // Only allow SQL fallback for LOCAL users
             // LDAP users must authenticate via LDAP
             if auth_source == "LDAP" {
-                warn!(
-                    "LDAP user {} attempted SQL fallback - denied",
-                    username
-                );
+                warn!("LDAP user {} attempted SQL fallback - denied", username);
                 return HttpResponse::Unauthorized().json(LoginResponse {
                     success: false,
                     token: None,
@@ -250,8 +360,7 @@ async fn handle_sql_fallback(
                 });
             }
 
-            // Plaintext password comparison (matches BME-Partial-Picking)
-            if *stored_password != password {
+            if !users::verify_password(password, stored_password.clone()).await {
                 return HttpResponse::Unauthorized().json(LoginResponse {
                     success: false,
                     token: None,
@@ -260,30 +369,19 @@ async fn handle_sql_fallback(
                 });
             }
 
-            // Combine Fname and Lname for display name
-            let display_name = format!("{} {}", fname, lname).trim().to_string();
+            if let Some(response) = disabled_response(&rows[0]) {
+                return response;
+            }
+
             let user = UserInfo {
                 username: uname.clone(),
-                display_name: if display_name.is_empty() { uname.clone() } else { display_name },
+                display_name: rows[0].display_name(),
+                roles: rows[0].roles(),
+                plant: String::new(),
+                plants: vec![],
             };
 
-            match generate_token(&user.username) {
-                Ok(token) => HttpResponse::Ok().json(LoginResponse {
-                    success: true,
-                    token: Some(token),
-                    user: Some(user),
-                    message: "Login successful (SQL fallback)".to_string(),
-                }),
-                Err(e) => {
-                    error!("Failed to generate token: {}", e);
-                    HttpResponse::InternalServerError().json(LoginResponse {
-                        success: false,
-                        token: None,
-                        user: None,
-                        message: "Failed to generate authentication token".to_string(),
-                    })
-                }
-            }
+            issue_token(registry, plant, user, "Login successful (SQL fallback)")
         }
         Ok(_) => HttpResponse::Unauthorized().json(LoginResponse {
             success: false,
@@ -293,7 +391,7 @@ async fn handle_sql_fallback(
         }),
         Err(e) => {
             error!("Database error during SQL fallback: {}", e);
-            HttpResponse::InternalServerError().json(LoginResponse {
+            HttpResponse::build(database_error_status(&e)).json(LoginResponse {
                 success: false,
                 token: None,
                 user: None,
@@ -303,24 +401,25 @@ async fn handle_sql_fallback(
     }
 }
 
-/// Authenticate a user against LDAP/Active Directory
+/// Authenticate a user against the plant's LDAP/Active Directory
 ///
 /// Returns the LdapUser on success, or an LdapError on failure.
 /// Note: AuthError and UserNotFound are explicit authentication failures,
 /// while other errors may indicate configuration or connection issues.
-async fn authenticate_ldap(username: &str, password: &str) -> Result<LdapUser, LdapError> {
-    ldap::authenticate(username, password).await
-}
-
-fn generate_token(username: &str) -> Result<String, jsonwebtoken::errors::Error> {
-    generate_token_with_display_name(username, username)
+async fn authenticate_ldap(
+    directory: &LdapDirectory,
+    plant: &Plant,
+    username: &str,
+    password: &str,
+) -> Result<LdapUser, LdapError> {
+    directory
+        .client(&plant.code)?
+        .authenticate(username, password)
+        .await
 }
 
-fn generate_token_with_display_name(
-    username: &str,
-    display_name: &str,
-) -> Result<String, jsonwebtoken::errors::Error> {
-    let secret = env::var(JWT_SECRET_ENV).unwrap_or_else(|_| DEFAULT_JWT_SECRET.to_string());
+fn generate_token(user: &UserInfo) -> Result<String, jsonwebtoken::errors::Error> {
+    let secret = jwt_secret();
 
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -330,8 +429,11 @@ fn generate_token_with_display_name(
     let exp = now + (TOKEN_EXPIRATION_HOURS * 3600);
 
     let claims = Claims {
-        sub: username.to_string(),
-        display_name: display_name.to_string(),
+        sub: user.username.clone(),
+        display_name: user.display_name.clone(),
+        roles: user.roles.clone(),
+        plant: user.plant.clone(),
+        plants: user.plants.clone(),
         exp,
         iat: now,
     };
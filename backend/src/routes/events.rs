@@ -0,0 +1,80 @@
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpResponse, Responder};
+use futures_util::stream::{self, StreamExt};
+use log::{info, warn};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::plant::CurrentPlant;
+use crate::rm::events::RunEvent;
+
+/// Comment line sent while idle so proxies keep the connection open
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(run_events);
+}
+
+fn format_event(event: &RunEvent) -> Bytes {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    Bytes::from(format!(
+        "event: {}\ndata: {}\n\n",
+        event.kind.as_str(),
+        data
+    ))
+}
+
+#[get("/rm/runs/{run_no}/events")]
+async fn run_events(plant: CurrentPlant, path: web::Path<i32>) -> impl Responder {
+    let run_no = path.into_inner();
+    let mut subscription = plant.events.subscribe(run_no);
+    let receiver = subscription.take_receiver();
+
+    info!("Station subscribed to live events for RunNo: {}", run_no);
+
+    // The subscription travels with the stream so the run is unwatched on disconnect
+    let updates = stream::unfold(
+        (receiver, subscription),
+        |(mut receiver, subscription)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if event.run_no == subscription.run_no() => {
+                        return Some((format_event(&event), (receiver, subscription)))
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "Live events for RunNo: {} lagged, {} events skipped",
+                            subscription.run_no(),
+                            skipped
+                        );
+                        let notice = format!("event: lagged\ndata: {}\n\n", skipped);
+                        return Some((Bytes::from(notice), (receiver, subscription)));
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+
+    let keep_alive = stream::unfold((), |_| async {
+        tokio::time::sleep(KEEP_ALIVE_INTERVAL).await;
+        Some((Bytes::from_static(b": keep-alive\n\n"), ()))
+    });
+
+    let opened = stream::once(async move {
+        Bytes::from(format!(
+            "event: subscribed\ndata: {{\"run_no\":{}}}\n\n",
+            run_no
+        ))
+    });
+
+    let body = opened
+        .chain(stream::select(updates, keep_alive))
+        .map(Ok::<_, actix_web::Error>);
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(body)
+}
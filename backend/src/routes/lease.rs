@@ -0,0 +1,155 @@
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use log::{info, warn};
+
+use crate::auth::AuthenticatedUser;
+use crate::models::rm::{LeaseRequest, LeaseResponse};
+use crate::plant::CurrentPlant;
+use crate::rm::LeaseError;
+use crate::routes::acting_user;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_lease)
+        .service(acquire_lease)
+        .service(renew_lease)
+        .service(release_lease);
+}
+
+#[get("/rm/runs/{run_no}/lease")]
+async fn get_lease(plant: CurrentPlant, path: web::Path<i32>) -> impl Responder {
+    let run_no = path.into_inner();
+    let lease = plant.leases.current(run_no);
+    let message = match &lease {
+        Some(l) => format!("Run {} is leased by {}", run_no, l.holder),
+        None => format!("Run {} is not leased", run_no),
+    };
+
+    HttpResponse::Ok().json(LeaseResponse {
+        success: true,
+        lease,
+        message,
+        lease_id: None,
+    })
+}
+
+#[post("/rm/runs/{run_no}/lease")]
+async fn acquire_lease(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    request: web::Json<LeaseRequest>,
+) -> impl Responder {
+    let run_no = path.into_inner();
+    let LeaseRequest {
+        user_logon,
+        station,
+        exclusive,
+    } = request.into_inner();
+
+    let holder = match acting_user(&user, &user_logon) {
+        Ok(holder) => holder,
+        Err(message) => {
+            return HttpResponse::Forbidden().json(LeaseResponse {
+                success: false,
+                lease: None,
+                message,
+                lease_id: None,
+            })
+        }
+    };
+
+    match plant.leases.acquire(run_no, &holder, station, exclusive) {
+        Ok(lease) => {
+            info!(
+                "Lease on RunNo: {} acquired by {} (exclusive: {})",
+                run_no, lease.holder, lease.exclusive
+            );
+            // The only response carrying the id, and it goes to the holder
+            HttpResponse::Ok().json(LeaseResponse {
+                success: true,
+                message: format!(
+                    "Lease acquired for {} seconds",
+                    plant.leases.ttl().as_secs()
+                ),
+                lease_id: Some(lease.lease_id.clone()),
+                lease: Some(lease),
+            })
+        }
+        Err(e) => lease_error_response(e),
+    }
+}
+
+#[put("/rm/runs/{run_no}/lease/{lease_id}")]
+async fn renew_lease(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<(i32, String)>,
+) -> impl Responder {
+    let (run_no, lease_id) = path.into_inner();
+
+    match plant.leases.renew(run_no, &lease_id, &user.username) {
+        Ok(lease) => HttpResponse::Ok().json(LeaseResponse {
+            success: true,
+            lease: Some(lease),
+            message: "Lease renewed".to_string(),
+            lease_id: None,
+        }),
+        Err(e) => lease_error_response(e),
+    }
+}
+
+#[delete("/rm/runs/{run_no}/lease/{lease_id}")]
+async fn release_lease(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<(i32, String)>,
+) -> impl Responder {
+    let (run_no, lease_id) = path.into_inner();
+
+    match plant.leases.release(run_no, &lease_id, &user.username) {
+        Ok(()) => {
+            info!("Lease on RunNo: {} released by {}", run_no, user.username);
+            HttpResponse::Ok().json(LeaseResponse {
+                success: true,
+                lease: None,
+                message: "Lease released".to_string(),
+                lease_id: None,
+            })
+        }
+        Err(e) => lease_error_response(e),
+    }
+}
+
+/// Map a lease error to the response used by all lease-aware endpoints
+///
+/// The lease in the body never carries its id, see [`RunLease`](crate::rm::lease::RunLease).
+pub fn lease_error_response(e: LeaseError) -> HttpResponse {
+    match e {
+        LeaseError::HeldByOther(lease) => {
+            warn!("RunNo: {} is leased by {}", lease.run_no, lease.holder);
+            HttpResponse::Locked().json(LeaseResponse {
+                success: false,
+                message: format!("Run {} is being edited by {}", lease.run_no, lease.holder),
+                lease: Some(lease),
+                lease_id: None,
+            })
+        }
+        LeaseError::NotHolder(lease) => {
+            warn!(
+                "Lease of {} on RunNo: {} used by another user",
+                lease.holder, lease.run_no
+            );
+            HttpResponse::Forbidden().json(LeaseResponse {
+                success: false,
+                message: format!("Run {} is leased by {}", lease.run_no, lease.holder),
+                lease: Some(lease),
+                lease_id: None,
+            })
+        }
+        LeaseError::NotFound => HttpResponse::NotFound().json(LeaseResponse {
+            success: false,
+            lease: None,
+            message: e.to_string(),
+            lease_id: None,
+        }),
+    }
+}
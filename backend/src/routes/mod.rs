@@ -1,8 +1,20 @@
+use actix_web::http::StatusCode;
 use actix_web::{get, web, HttpResponse, Responder};
+use log::warn;
 use serde_json::json;
 
+use crate::auth::AuthenticatedUser;
+use crate::db::error::DbError;
+
+pub mod approval;
 pub mod auth;
+pub mod events;
+pub mod lease;
+pub mod plant;
+pub mod reason;
 pub mod rm;
+pub mod users;
+pub mod webhook;
 
 #[get("/")]
 async fn root() -> impl Responder {
@@ -13,8 +25,18 @@ async fn root() -> impl Responder {
         "endpoints": {
             "health": "/api/health",
             "auth": "/api/auth/login",
+            "plants": "/api/plants",
             "rm_search": "/api/rm/search?runno={runno}",
-            "rm_remove": "/api/rm/remove"
+            "rm_remove": "/api/rm/remove",
+            "rm_remove_by_criteria": "/api/rm/remove-by-criteria",
+            "rm_run_summary": "/api/rm/runs/{run_no}/summary",
+            "rm_run_lease": "/api/rm/runs/{run_no}/lease",
+            "rm_approvals": "/api/rm/approvals",
+            "rm_reason_codes": "/api/rm/reason-codes",
+            "rm_run_history": "/api/rm/runs/{run_no}/history",
+            "rm_run_events": "/api/rm/runs/{run_no}/events",
+            "rm_webhooks": "/api/rm/webhooks",
+            "users": "/api/users"
         }
     }))
 }
@@ -27,12 +49,45 @@ async fn health_check() -> impl Responder {
     }))
 }
 
-pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(root)
-        .service(
-            web::scope("/api")
-                .configure(rm::config)
-                .configure(auth::config)
-                .service(health_check),
+/// Status for a failed database call: 504 when a statement ran out of time,
+/// 503 for a transient fault worth retrying, otherwise 500
+pub fn database_error_status(e: &anyhow::Error) -> StatusCode {
+    match DbError::find(e) {
+        Some(DbError::QueryTimeout(_)) => StatusCode::GATEWAY_TIMEOUT,
+        Some(err) if err.is_retryable() => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// The signed-in user a change is recorded for; `user_logon` from the body
+/// is only checked, so a client cannot act under another name
+pub(crate) fn acting_user(user: &AuthenticatedUser, user_logon: &str) -> Result<String, String> {
+    let user_logon = user_logon.trim();
+    if !user_logon.is_empty() && !user_logon.eq_ignore_ascii_case(&user.username) {
+        warn!(
+            "User {} sent a request with user_logon {}",
+            user.username, user_logon
         );
+        return Err(format!(
+            "user_logon {} does not match the signed-in user",
+            user_logon
+        ));
+    }
+    Ok(user.username.clone())
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(root).service(
+        web::scope("/api")
+            .configure(rm::config)
+            .configure(lease::config)
+            .configure(approval::config)
+            .configure(reason::config)
+            .configure(events::config)
+            .configure(webhook::config)
+            .configure(auth::config)
+            .configure(users::config)
+            .configure(plant::config)
+            .service(health_check),
+    );
 }
@@ -0,0 +1,29 @@
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::models::auth::{PlantInfo, PlantListResponse};
+use crate::plant::PlantRegistry;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_plants);
+}
+
+/// Plants a user can sign in to; only codes and names are exposed
+#[get("/plants")]
+async fn list_plants(registry: web::Data<PlantRegistry>) -> impl Responder {
+    let default_code = &registry.default_plant().code;
+    let data: Vec<PlantInfo> = registry
+        .plants()
+        .iter()
+        .map(|p| PlantInfo {
+            code: p.code.clone(),
+            name: p.name.clone(),
+            default: p.code == *default_code,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(PlantListResponse {
+        success: true,
+        message: format!("Found {} plants", data.len()),
+        data,
+    })
+}
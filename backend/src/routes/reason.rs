@@ -0,0 +1,131 @@
+use actix_web::{delete, get, put, web, HttpResponse, Responder};
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::auth::{AuthenticatedUser, ROLE_ADMIN};
+use crate::models::rm::{ReasonCode, ReasonCodeListResponse};
+use crate::plant::CurrentPlant;
+use crate::rm::reason;
+use crate::routes::database_error_status;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_reason_codes)
+        .service(upsert_reason_code)
+        .service(deactivate_reason_code);
+}
+
+#[derive(Debug, Deserialize)]
+struct ReasonCodeQuery {
+    #[serde(default)]
+    include_inactive: bool,
+}
+
+fn forbidden(user: &AuthenticatedUser) -> HttpResponse {
+    warn!(
+        "User {} tried to maintain reason codes without admin role",
+        user.username
+    );
+    HttpResponse::Forbidden().json(ReasonCodeListResponse {
+        success: false,
+        data: vec![],
+        message: "Admin role required".to_string(),
+    })
+}
+
+fn database_error(e: anyhow::Error) -> HttpResponse {
+    error!("Database error maintaining reason codes: {}", e);
+    HttpResponse::build(database_error_status(&e)).json(ReasonCodeListResponse {
+        success: false,
+        data: vec![],
+        message: format!("Database error: {}", e),
+    })
+}
+
+#[get("/rm/reason-codes")]
+async fn list_reason_codes(
+    plant: CurrentPlant,
+    query: web::Query<ReasonCodeQuery>,
+) -> impl Responder {
+    match reason::list_codes(&plant.pool, query.include_inactive).await {
+        Ok(codes) => HttpResponse::Ok().json(ReasonCodeListResponse {
+            success: true,
+            message: format!("Found {} reason codes", codes.len()),
+            data: codes,
+        }),
+        Err(e) => database_error(e),
+    }
+}
+
+#[put("/rm/reason-codes/{code}")]
+async fn upsert_reason_code(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    body: web::Json<ReasonCode>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let mut code = body.into_inner();
+    code.reason_code = path.into_inner().trim().to_uppercase();
+
+    if code.reason_code.is_empty() || code.reason_code.len() > 20 {
+        return HttpResponse::BadRequest().json(ReasonCodeListResponse {
+            success: false,
+            data: vec![],
+            message: "Reason code must be 1 to 20 characters".to_string(),
+        });
+    }
+    if code.description.trim().is_empty() {
+        return HttpResponse::BadRequest().json(ReasonCodeListResponse {
+            success: false,
+            data: vec![],
+            message: "Description is required".to_string(),
+        });
+    }
+
+    match reason::upsert_code(&plant.pool, &code, &user.username).await {
+        Ok(()) => {
+            info!(
+                "Reason code {} saved by {}",
+                code.reason_code, user.username
+            );
+            HttpResponse::Ok().json(ReasonCodeListResponse {
+                success: true,
+                message: format!("Reason code {} saved", code.reason_code),
+                data: vec![code],
+            })
+        }
+        Err(e) => database_error(e),
+    }
+}
+
+#[delete("/rm/reason-codes/{code}")]
+async fn deactivate_reason_code(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let code = path.into_inner();
+    match reason::deactivate_code(&plant.pool, &code, &user.username).await {
+        Ok(true) => {
+            info!("Reason code {} deactivated by {}", code, user.username);
+            HttpResponse::Ok().json(ReasonCodeListResponse {
+                success: true,
+                data: vec![],
+                message: format!("Reason code {} deactivated", code),
+            })
+        }
+        Ok(false) => HttpResponse::NotFound().json(ReasonCodeListResponse {
+            success: false,
+            data: vec![],
+            message: format!("Reason code {} not found", code),
+        }),
+        Err(e) => database_error(e),
+    }
+}
@@ -1,16 +1,67 @@
-use actix_web::{get, post, web, HttpResponse, Responder};
-use log::{error, info};
+use actix_web::body::to_bytes;
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::NaiveDate;
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::env;
+use std::future::Future;
+use tiberius::Query;
 
-use crate::db::mssql::{get_f64, get_i32, get_optional_f64, get_string, MssqlPool};
-use crate::models::rm::{RMLine, RemoveRequest, RemoveResponse, SearchResponse};
+use crate::auth::AuthenticatedUser;
+use crate::db::mssql::MssqlPool;
+use crate::db::row::column;
+use crate::models::qty::QTY_SQL_TYPE;
+use crate::models::rm::{
+    BatchSummary, HistoryResponse, ItemSummary, QtySummary, RMLine, RemoveByCriteriaRequest,
+    RemoveByCriteriaResponse, RemoveItem, RemoveItemResult, RemoveItemStatus, RemoveRequest,
+    RemoveResponse, RunListResponse, RunSummary, RunSummaryResponse, SearchResponse,
+};
+use crate::plant::{CurrentPlant, Plant};
+use crate::rm::approval::{self, ApprovalPolicy};
+use crate::rm::history;
+use crate::rm::idempotency::{
+    Claim, IdempotencyError, IdempotencyGuard, IdempotencyStore, StoredResponse, MAX_KEY_LEN,
+};
+use crate::rm::lines::{
+    eligible_lines, map_rm_line, CONCURRENCY_TOKEN_EXPR, ELIGIBLE_FOR_REMOVAL, RM_LINE_COLUMNS,
+};
+use crate::rm::reason::{self, ReasonError, RemovalReason};
+use crate::rm::removal::{
+    plan_full, remove_items, unplanned, user8_plus, validate_item, PlannedRemoval, RemovalContext,
+    RemovalOutcome,
+};
+use crate::rm::runs::{self, RunFilter, DEFAULT_RUN_LIMIT, MAX_RUN_LIMIT};
+use crate::rm::webhook::OUTBOX_OUTPUT;
+use crate::routes::lease::lease_error_response;
+use crate::routes::{acting_user, database_error_status};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(search_rm_lines).service(remove_partial_qty);
+    cfg.service(search_rm_lines)
+        .service(remove_partial_qty)
+        .service(remove_by_criteria)
+        .service(list_runs)
+        .service(run_summary)
+        .service(run_history);
+}
+
+/// Header a client sets to make retries of `POST /rm/remove` safe
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+const MAX_BULK_REMOVE_ROWS_ENV: &str = "RM_MAX_BULK_REMOVE_ROWS";
+const DEFAULT_MAX_BULK_REMOVE_ROWS: i32 = 200;
+
+fn max_bulk_remove_rows() -> i32 {
+    env::var(MAX_BULK_REMOVE_ROWS_ENV)
+        .ok()
+        .and_then(|v| v.parse::<i32>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_MAX_BULK_REMOVE_ROWS)
 }
 
 #[get("/rm/search")]
 async fn search_rm_lines(
-    pool: web::Data<MssqlPool>,
+    plant: CurrentPlant,
     query: web::Query<std::collections::HashMap<String, String>>,
 ) -> impl Responder {
     let runno = match query.get("runno") {
@@ -21,6 +72,7 @@ async fn search_rm_lines(
                     success: false,
                     data: vec![],
                     message: "Invalid runno parameter".to_string(),
+                    lease: None,
                 });
             }
         },
@@ -29,150 +81,388 @@ async fn search_rm_lines(
                 success: false,
                 data: vec![],
                 message: "Missing runno parameter".to_string(),
+                lease: None,
             });
         }
     };
 
     info!("Searching RM lines for RunNo: {}", runno);
 
-    let sql = format!(
-        r#"
-        SELECT
-            RunNo,
-            RowNum,
-            BatchNo,
-            LineTyp,
-            LineId,
-            ItemKey,
-            Location,
-            Unit,
-            StandardQty,
-            PackSize,
-            ToPickedPartialQty,
-            PickedPartialQty,
-            RecUserId,
-            ModifiedBy
-        FROM cust_PartialPicked
-        WHERE RunNo = {}
-          AND ToPickedPartialQty > 0
-          AND (PickedPartialQty IS NULL OR PickedPartialQty <= 0)
-        ORDER BY BatchNo, LineId, ItemKey
-        "#,
-        runno
-    );
-
-    let result = pool
-        .execute_query(&sql, |row| {
-            Ok(RMLine {
-                run_no: get_i32(row, "RunNo"),
-                row_num: get_i32(row, "RowNum"),
-                batch_no: get_string(row, "BatchNo"),
-                line_typ: get_string(row, "LineTyp"),
-                line_id: get_i32(row, "LineId"),
-                item_key: get_string(row, "ItemKey"),
-                location: get_string(row, "Location"),
-                unit: get_string(row, "Unit"),
-                standard_qty: get_f64(row, "StandardQty"),
-                pack_size: get_f64(row, "PackSize"),
-                to_picked_partial_qty: get_f64(row, "ToPickedPartialQty"),
-                picked_partial_qty: get_optional_f64(row, "PickedPartialQty"),
-                rec_user_id: get_string(row, "RecUserId"),
-                modified_by: get_string(row, "ModifiedBy"),
-            })
-        })
-        .await;
+    let result = eligible_lines(&plant.pool, runno).await;
 
     match result {
-        Ok(lines) => {
+        Ok(mut lines) => {
+            plant.enrich(&mut lines).await;
             let count = lines.len();
             info!("Found {} RM lines for RunNo: {}", count, runno);
             HttpResponse::Ok().json(SearchResponse {
                 success: true,
                 data: lines,
                 message: format!("Found {} records", count),
+                lease: plant.leases.current(runno),
             })
         }
         Err(e) => {
             error!("Database error searching RM lines: {}", e);
-            HttpResponse::InternalServerError().json(SearchResponse {
+            HttpResponse::build(database_error_status(&e)).json(SearchResponse {
                 success: false,
                 data: vec![],
                 message: format!("Database error: {}", e),
+                lease: None,
             })
         }
     }
 }
 
+fn remove_error(status: StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(RemoveResponse {
+        success: false,
+        message,
+        affected_rows: 0,
+        results: vec![],
+        approval_request_id: None,
+    })
+}
+
 #[post("/rm/remove")]
 async fn remove_partial_qty(
-    pool: web::Data<MssqlPool>,
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    idempotency: web::Data<IdempotencyStore>,
+    http: HttpRequest,
     request: web::Json<RemoveRequest>,
-) -> impl Responder {
+) -> HttpResponse {
+    let request = request.into_inner();
+
+    let key = match http.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        None => {
+            return run_detached(async move { process_removal(&plant, &user, request).await }).await
+        }
+        Some(value) => match value.to_str().map(str::trim) {
+            Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => key.to_string(),
+            _ => {
+                return remove_error(
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "{} must be 1 to {} visible ASCII characters",
+                        IDEMPOTENCY_KEY_HEADER, MAX_KEY_LEN
+                    ),
+                )
+            }
+        },
+    };
+
+    let fingerprint =
+        IdempotencyStore::fingerprint(&serde_json::to_vec(&request).unwrap_or_default());
+    // Keys are per plant as well as per user; run numbers repeat across plants
+    let owner = format!("{}/{}", plant.code, user.username);
+    let guard = match idempotency.claim(&owner, &key, &fingerprint) {
+        Ok(Claim::New(guard)) => guard,
+        Ok(Claim::Replay(stored)) => {
+            info!(
+                "Replaying stored response for {} {} of {}",
+                IDEMPOTENCY_KEY_HEADER, key, user.username
+            );
+            return HttpResponse::build(stored.status)
+                .content_type("application/json")
+                .insert_header(("Idempotent-Replayed", "true"))
+                .body(stored.body);
+        }
+        Err(e @ IdempotencyError::Mismatch) => {
+            warn!(
+                "{} {} of {} reused with a different request",
+                IDEMPOTENCY_KEY_HEADER, key, user.username
+            );
+            return remove_error(StatusCode::UNPROCESSABLE_ENTITY, e.to_string());
+        }
+        Err(e @ IdempotencyError::InFlight) => {
+            return remove_error(StatusCode::CONFLICT, e.to_string())
+        }
+    };
+
+    // Storing the response is part of the detached task: if the handler were
+    // dropped in between, the guard would free the key after lines changed
+    run_detached(async move {
+        let response = process_removal(&plant, &user, request).await;
+        store_for_replay(guard, response).await
+    })
+    .await
+}
+
+/// Run a removal in its own task so it finishes even when the client
+/// disconnects and actix drops the handler
+pub(crate) async fn run_detached(
+    removal: impl Future<Output = HttpResponse> + 'static,
+) -> HttpResponse {
+    actix_web::rt::spawn(removal).await.unwrap_or_else(|e| {
+        error!("Removal task failed: {}", e);
+        remove_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Removal failed".to_string(),
+        )
+    })
+}
+
+/// Keep the response of a finished removal for replays of its key
+async fn store_for_replay(guard: IdempotencyGuard, response: HttpResponse) -> HttpResponse {
+    // Only keep outcomes of a removal that ran; validation, lock and server
+    // errors free the key so a corrected retry can go through
+    let status = response.status();
+    if !(status.is_success() || status == StatusCode::CONFLICT) {
+        return response;
+    }
+
+    let (response, body) = response.into_parts();
+    match to_bytes(body).await {
+        Ok(body) => {
+            guard.complete(StoredResponse {
+                status,
+                body: body.clone(),
+            });
+            response.set_body(body).map_into_boxed_body()
+        }
+        Err(e) => {
+            error!("Failed to buffer removal response for replay: {}", e);
+            remove_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to store response".to_string(),
+            )
+        }
+    }
+}
+
+async fn process_removal(
+    plant: &Plant,
+    user: &AuthenticatedUser,
+    request: RemoveRequest,
+) -> HttpResponse {
+    let Plant {
+        pool,
+        leases,
+        events,
+        clock,
+        ..
+    } = plant;
     let RemoveRequest {
         run_no,
         items,
         user_logon,
-    } = request.into_inner();
+        lease_id,
+        reason_code,
+        comment,
+    } = request;
+
+    let removed_by = match acting_user(user, &user_logon) {
+        Ok(removed_by) => removed_by,
+        Err(message) => return remove_error(StatusCode::FORBIDDEN, message),
+    };
 
     if items.is_empty() {
         return HttpResponse::BadRequest().json(RemoveResponse {
             success: false,
             message: "No items provided".to_string(),
             affected_rows: 0,
+            results: vec![],
+            approval_request_id: None,
         });
     }
 
-    info!(
-        "Removing partial quantities for RunNo: {}, Items: {:?}, User: {}",
-        run_no, items, user_logon
-    );
+    // Without the token a line changed since the caller read it would be
+    // removed anyway; the approval workflow fills it in from its own read
+    if let Some(item) = items.iter().find(|item| {
+        item.concurrency_token
+            .as_deref()
+            .is_none_or(|t| t.trim().is_empty())
+    }) {
+        return HttpResponse::BadRequest().json(RemoveResponse {
+            success: false,
+            message: format!(
+                "Item (Row: {}, Line: {}): concurrency_token is required, search the run again",
+                item.row_num, item.line_id
+            ),
+            affected_rows: 0,
+            results: vec![],
+            approval_request_id: None,
+        });
+    }
 
-    let sql = r#"
-        UPDATE cust_PartialPicked
-        SET
-            User8 = ToPickedPartialQty,
-            User9 = CAST(CONVERT(VARCHAR(8), GETDATE(), 112) AS DECIMAL(18,0)),
-            User3 = LEFT(@P1, 8),
-            ToPickedPartialQty = 0,
-            ModifiedBy = LEFT(@P1, 8),
-            ModifiedDate = GETDATE()
-        WHERE RunNo = @P2
-          AND RowNum = @P3
-          AND LineId = @P4
-          AND ToPickedPartialQty > 0
-    "#;
-
-    let mut total_affected: u64 = 0;
-    let mut errors: Vec<String> = vec![];
-
-    for item in &items {
-        let user_logon_clone = user_logon.clone();
-        let current_row = item.row_num;
-        let current_line = item.line_id;
-        
-        let result = pool
-            .execute_update(sql, |query| {
-                query.bind(user_logon_clone.clone());
-                query.bind(run_no);
-                query.bind(current_row);
-                query.bind(current_line);
+    if let Some((item, msg)) = items
+        .iter()
+        .find_map(|item| validate_item(item).err().map(|msg| (item, msg)))
+    {
+        return HttpResponse::BadRequest().json(RemoveResponse {
+            success: false,
+            message: format!(
+                "Item (Row: {}, Line: {}): {}",
+                item.row_num, item.line_id, msg
+            ),
+            affected_rows: 0,
+            results: vec![],
+            approval_request_id: None,
+        });
+    }
+
+    if let Err(e) = leases.check_write(run_no, lease_id.as_deref(), &removed_by) {
+        return lease_error_response(e);
+    }
+
+    let reason = match reason::validate(pool, reason_code.as_deref(), comment.as_deref()).await {
+        Ok(reason) => reason,
+        Err(e) => {
+            return reason_error_response(&e).json(RemoveResponse {
+                success: false,
+                message: e.to_string(),
+                affected_rows: 0,
+                results: vec![],
+                approval_request_id: None,
             })
-            .await;
+        }
+    };
 
-        match result {
-            Ok(affected) => {
-                total_affected += affected;
-                if affected == 0 {
-                    errors.push(format!("Item (Row: {}, Line: {}) not found or already processed", current_row, current_line));
+    let policy = ApprovalPolicy::from_env();
+    if policy.is_enabled() {
+        let check = match approval::plan_for_items(pool, run_no, &items).await {
+            Ok(planned) => {
+                // A held request has to cover every item sent, and the
+                // thresholds have to be judged on all of them
+                let missing = unplanned(&items, &planned);
+                if !missing.is_empty() {
+                    return missing_lines_response(&missing);
                 }
+                check_approval(pool, &policy, run_no, &planned, &reason, &removed_by).await
+            }
+            Err(e) => Err(e),
+        };
+
+        match check {
+            Ok(ApprovalCheck::NotRequired) => {}
+            Ok(ApprovalCheck::Held {
+                request_id,
+                threshold,
+            }) => {
+                return HttpResponse::Accepted().json(RemoveResponse {
+                    success: true,
+                    message: format!(
+                        "Removal submitted for supervisor approval ({}), request #{}",
+                        threshold, request_id
+                    ),
+                    affected_rows: 0,
+                    results: vec![],
+                    approval_request_id: Some(request_id),
+                });
             }
             Err(e) => {
-                error!("Error updating item (Row: {}, Line: {}): {}", current_row, current_line, e);
-                errors.push(format!("Item (Row: {}, Line: {}): {}", current_row, current_line, e));
+                error!("Database error checking removal approval: {}", e);
+                return HttpResponse::build(database_error_status(&e)).json(RemoveResponse {
+                    success: false,
+                    message: format!("Database error: {}", e),
+                    affected_rows: 0,
+                    results: vec![],
+                    approval_request_id: None,
+                });
             }
         }
     }
 
+    info!(
+        "Removing partial quantities for RunNo: {}, Items: {:?}, User: {}, Reason: {}",
+        run_no, items, removed_by, reason.code
+    );
+
+    let ctx = RemovalContext {
+        removed_by,
+        approved_by: None,
+        approval_request_id: None,
+        reason,
+        removed_at: clock.now(),
+    };
+    let outcome = remove_items(pool, run_no, &items, &ctx).await;
+    events.publish_removals(run_no, &outcome, &ctx.removed_by);
+    removal_response(outcome, None)
+}
+
+/// Items whose line no longer exists or is no longer eligible, reported as
+/// `not_found` without changing or holding anything
+fn missing_lines_response(missing: &[&RemoveItem]) -> HttpResponse {
+    let lines: Vec<String> = missing
+        .iter()
+        .map(|item| format!("{}:{}", item.row_num, item.line_id))
+        .collect();
+    HttpResponse::NotFound().json(RemoveResponse {
+        success: false,
+        message: format!(
+            "Lines not found or no longer eligible: {}. Search the run again",
+            lines.join(", ")
+        ),
+        affected_rows: 0,
+        results: missing
+            .iter()
+            .map(|item| RemoveItemResult {
+                row_num: item.row_num,
+                line_id: item.line_id,
+                status: RemoveItemStatus::NotFound,
+                message: None,
+                qty_before: None,
+                qty_after: None,
+            })
+            .collect(),
+        approval_request_id: None,
+    })
+}
+
+/// Whether a removal may run now or has to wait for a supervisor
+enum ApprovalCheck {
+    NotRequired,
+    Held { request_id: i32, threshold: String },
+}
+
+async fn check_approval(
+    pool: &MssqlPool,
+    policy: &ApprovalPolicy,
+    run_no: i32,
+    planned: &[PlannedRemoval],
+    reason: &RemovalReason,
+    user_logon: &str,
+) -> anyhow::Result<ApprovalCheck> {
+    let threshold = match policy.evaluate(planned) {
+        Some(threshold) => threshold,
+        None => return Ok(ApprovalCheck::NotRequired),
+    };
+
+    let request_id =
+        approval::create_request(pool, run_no, planned, reason, &threshold, user_logon).await?;
+
+    info!(
+        "Removal for RunNo: {} by {} held for approval as request #{} ({})",
+        run_no, user_logon, request_id, threshold
+    );
+
+    Ok(ApprovalCheck::Held {
+        request_id,
+        threshold,
+    })
+}
+
+/// Status for a rejected reason; the caller adds the body
+pub fn reason_error_response(e: &ReasonError) -> actix_web::HttpResponseBuilder {
+    match e {
+        ReasonError::Database(err) => {
+            error!("Database error validating reason code: {}", err);
+            HttpResponse::build(database_error_status(err))
+        }
+        _ => HttpResponse::BadRequest(),
+    }
+}
+
+/// Build the HTTP response for a finished removal
+pub fn removal_response(outcome: RemovalOutcome, approval_request_id: Option<i32>) -> HttpResponse {
+    let conflicts = outcome.conflicts();
+    let RemovalOutcome {
+        total_affected,
+        results,
+        errors,
+    } = outcome;
+
     if errors.is_empty() {
         info!(
             "Successfully removed partial quantities for {} rows",
@@ -182,6 +472,8 @@ async fn remove_partial_qty(
             success: true,
             message: format!("Successfully updated {} rows", total_affected),
             affected_rows: total_affected as usize,
+            results,
+            approval_request_id,
         })
     } else if total_affected > 0 {
         HttpResponse::PartialContent().json(RemoveResponse {
@@ -192,12 +484,849 @@ async fn remove_partial_qty(
                 errors.join(", ")
             ),
             affected_rows: total_affected as usize,
+            results,
+            approval_request_id,
+        })
+    } else if conflicts > 0 {
+        HttpResponse::Conflict().json(RemoveResponse {
+            success: false,
+            message: format!("No rows updated. Errors: {}", errors.join(", ")),
+            affected_rows: 0,
+            results,
+            approval_request_id,
+        })
+    } else if results
+        .iter()
+        .all(|r| r.status == RemoveItemStatus::InvalidQuantity)
+    {
+        HttpResponse::BadRequest().json(RemoveResponse {
+            success: false,
+            message: format!("No rows updated. Errors: {}", errors.join(", ")),
+            affected_rows: 0,
+            results,
+            approval_request_id,
         })
     } else {
         HttpResponse::InternalServerError().json(RemoveResponse {
             success: false,
             message: format!("Failed to update any rows. Errors: {}", errors.join(", ")),
             affected_rows: 0,
+            results,
+            approval_request_id,
         })
     }
 }
+
+/// Optional filters of a bulk removal; blank values count as omitted
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CriteriaFilters {
+    batch_no: Option<String>,
+    item_key: Option<String>,
+    location: Option<String>,
+    line_typ: Option<String>,
+}
+
+impl CriteriaFilters {
+    fn new(
+        batch_no: Option<String>,
+        item_key: Option<String>,
+        location: Option<String>,
+        line_typ: Option<String>,
+    ) -> Self {
+        let given = |value: Option<String>| value.filter(|v| !v.trim().is_empty());
+        Self {
+            batch_no: given(batch_no),
+            item_key: given(item_key),
+            location: given(location),
+            line_typ: given(line_typ),
+        }
+    }
+
+    /// Bind the filters as `@P2` to `@P5`, after the run number
+    fn bind(&self, query: &mut Query<'_>) {
+        query.bind(self.batch_no.clone());
+        query.bind(self.item_key.clone());
+        query.bind(self.location.clone());
+        query.bind(self.line_typ.clone());
+    }
+}
+
+/// What the locked bulk removal did with the lines matching the criteria
+#[derive(Debug)]
+enum BulkOutcome {
+    NoMatch,
+    /// More lines match than one request may remove; none were changed
+    OverLimit(usize),
+    /// The locked lines differ from the ones the approval check was made on;
+    /// none were changed
+    Changed,
+    /// The lines as they were before removal
+    Removed(Vec<RMLine>),
+}
+
+impl BulkOutcome {
+    /// Interpret the matching lines returned by the batch, each tagged with
+    /// the batch's `Outcome`
+    fn from_rows(rows: Vec<(RMLine, String)>) -> Self {
+        match rows.first().map(|(_, outcome)| outcome.as_str()) {
+            None => BulkOutcome::NoMatch,
+            Some("removed") => {
+                BulkOutcome::Removed(rows.into_iter().map(|(line, _)| line).collect())
+            }
+            Some("changed") => BulkOutcome::Changed,
+            Some(_) => BulkOutcome::OverLimit(rows.len()),
+        }
+    }
+}
+
+/// Identifies a set of lines and their state, in the form the bulk removal
+/// batch builds for the lines it locked
+fn previewed_lines_key(lines: &[RMLine]) -> String {
+    let mut keys: Vec<_> = lines
+        .iter()
+        .map(|l| (l.row_num, l.line_id, l.concurrency_token.as_str()))
+        .collect();
+    keys.sort_unstable();
+    keys.iter()
+        .map(|(row, line, token)| format!("{}:{}:{},", row, line, token))
+        .collect()
+}
+
+fn bulk_removal_response(run_no: i32, outcome: BulkOutcome, max_rows: i32) -> HttpResponse {
+    match outcome {
+        BulkOutcome::NoMatch => HttpResponse::NotFound().json(RemoveByCriteriaResponse {
+            success: false,
+            message: "No eligible lines match the given criteria".to_string(),
+            affected_rows: 0,
+            data: vec![],
+            approval_request_id: None,
+        }),
+        BulkOutcome::OverLimit(count) => {
+            warn!(
+                "Bulk removal for RunNo: {} rejected, {} lines exceed limit of {}",
+                run_no, count, max_rows
+            );
+            HttpResponse::BadRequest().json(RemoveByCriteriaResponse {
+                success: false,
+                message: format!(
+                    "{} lines match the criteria, which exceeds the limit of {}. Narrow the criteria and try again",
+                    count, max_rows
+                ),
+                affected_rows: 0,
+                data: vec![],
+                approval_request_id: None,
+            })
+        }
+        BulkOutcome::Changed => {
+            warn!(
+                "Bulk removal for RunNo: {} rejected, lines changed after the approval check",
+                run_no
+            );
+            HttpResponse::Conflict().json(RemoveByCriteriaResponse {
+                success: false,
+                message: "Lines changed while the removal was checked, try again".to_string(),
+                affected_rows: 0,
+                data: vec![],
+                approval_request_id: None,
+            })
+        }
+        BulkOutcome::Removed(lines) => {
+            let count = lines.len();
+            info!(
+                "Successfully removed partial quantities for {} rows by criteria (RunNo: {})",
+                count, run_no
+            );
+            HttpResponse::Ok().json(RemoveByCriteriaResponse {
+                success: true,
+                message: format!("Successfully updated {} rows", count),
+                affected_rows: count,
+                data: lines,
+                approval_request_id: None,
+            })
+        }
+    }
+}
+
+#[post("/rm/remove-by-criteria")]
+async fn remove_by_criteria(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    request: web::Json<RemoveByCriteriaRequest>,
+) -> impl Responder {
+    let RemoveByCriteriaRequest {
+        run_no,
+        batch_no,
+        item_key,
+        location,
+        line_typ,
+        user_logon,
+        lease_id,
+        reason_code,
+        comment,
+    } = request.into_inner();
+
+    let filters = CriteriaFilters::new(batch_no, item_key, location, line_typ);
+
+    let removed_by = match acting_user(&user, &user_logon) {
+        Ok(removed_by) => removed_by,
+        Err(message) => {
+            return HttpResponse::Forbidden().json(RemoveByCriteriaResponse {
+                success: false,
+                message,
+                affected_rows: 0,
+                data: vec![],
+                approval_request_id: None,
+            })
+        }
+    };
+
+    if let Err(e) = plant
+        .leases
+        .check_write(run_no, lease_id.as_deref(), &removed_by)
+    {
+        return lease_error_response(e);
+    }
+
+    let reason =
+        match reason::validate(&plant.pool, reason_code.as_deref(), comment.as_deref()).await {
+            Ok(reason) => reason,
+            Err(e) => {
+                return reason_error_response(&e).json(RemoveByCriteriaResponse {
+                    success: false,
+                    message: e.to_string(),
+                    affected_rows: 0,
+                    data: vec![],
+                    approval_request_id: None,
+                })
+            }
+        };
+
+    let max_rows = max_bulk_remove_rows();
+
+    info!(
+        "Removing partial quantities by criteria for RunNo: {}, BatchNo: {:?}, ItemKey: {:?}, Location: {:?}, LineTyp: {:?}, User: {}",
+        run_no,
+        filters.batch_no,
+        filters.item_key,
+        filters.location,
+        filters.line_typ,
+        removed_by
+    );
+
+    let criteria = format!(
+        r#"
+            RunNo = @P1
+            AND {}
+            AND (@P2 IS NULL OR BatchNo = @P2)
+            AND (@P3 IS NULL OR ItemKey = @P3)
+            AND (@P4 IS NULL OR Location = @P4)
+            AND (@P5 IS NULL OR LineTyp = @P5)"#,
+        ELIGIBLE_FOR_REMOVAL
+    );
+
+    // Lines the approval check was made on; the batch refuses to remove any
+    // other set, so a line added or changed in between cannot skip approval
+    let mut previewed = None;
+    let policy = ApprovalPolicy::from_env();
+    if policy.is_enabled() {
+        let preview_sql = format!(
+            "SELECT {}, {} AS ConcurrencyToken FROM cust_PartialPicked WHERE {}",
+            RM_LINE_COLUMNS, CONCURRENCY_TOKEN_EXPR, criteria
+        );
+        let preview = plant
+            .pool
+            .execute_read_with_params(
+                &preview_sql,
+                |query| {
+                    query.bind(run_no);
+                    filters.bind(query);
+                },
+                map_rm_line,
+            )
+            .await;
+
+        if let Ok(lines) = &preview {
+            previewed = Some(previewed_lines_key(lines));
+        }
+
+        // Over the row limit is rejected below without creating a request
+        let check = match preview {
+            Ok(lines) if lines.len() as i32 > max_rows => Ok(ApprovalCheck::NotRequired),
+            Ok(lines) => {
+                let planned = plan_full(&lines);
+                check_approval(&plant.pool, &policy, run_no, &planned, &reason, &removed_by).await
+            }
+            Err(e) => Err(e),
+        };
+
+        match check {
+            Ok(ApprovalCheck::NotRequired) => {}
+            Ok(ApprovalCheck::Held {
+                request_id,
+                threshold,
+            }) => {
+                return HttpResponse::Accepted().json(RemoveByCriteriaResponse {
+                    success: true,
+                    message: format!(
+                        "Removal submitted for supervisor approval ({}), request #{}",
+                        threshold, request_id
+                    ),
+                    affected_rows: 0,
+                    data: vec![],
+                    approval_request_id: Some(request_id),
+                });
+            }
+            Err(e) => {
+                error!("Database error checking removal approval: {}", e);
+                return HttpResponse::build(database_error_status(&e)).json(
+                    RemoveByCriteriaResponse {
+                        success: false,
+                        message: format!("Database error: {}", e),
+                        affected_rows: 0,
+                        data: vec![],
+                        approval_request_id: None,
+                    },
+                );
+            }
+        }
+    }
+
+    // Lock the matching lines, refuse the whole request if it exceeds the
+    // configured limit or differs from the previewed lines, otherwise zero
+    // them and return what was removed.
+    let removed_at = plant.clock.now();
+    let sql = format!(
+        r#"
+        SET XACT_ABORT ON;
+        BEGIN TRANSACTION;
+
+        SELECT {columns}, {token} AS ConcurrencyToken
+        INTO #Removed
+        FROM cust_PartialPicked WITH (UPDLOCK, HOLDLOCK)
+        WHERE {criteria};
+
+        DECLARE @Outcome VARCHAR(10) = CASE
+            WHEN (SELECT COUNT(*) FROM #Removed) > @P7 THEN 'over_limit'
+            WHEN @P12 IS NOT NULL AND ISNULL((
+                SELECT CONCAT(RowNum, ':', LineId, ':', ConcurrencyToken, ',')
+                FROM #Removed
+                ORDER BY RowNum, LineId
+                FOR XML PATH('')
+            ), '') <> @P12 THEN 'changed'
+            ELSE 'removed'
+        END;
+
+        IF @Outcome = 'removed'
+        BEGIN
+            UPDATE p
+            SET
+                User8 = {user8},
+                User9 = @P11,
+                User3 = LEFT(@P6, 8),
+                ToPickedPartialQty = 0,
+                ModifiedBy = LEFT(@P6, 8),
+                ModifiedDate = @P10
+            FROM cust_PartialPicked p
+            INNER JOIN #Removed r
+                ON p.RunNo = r.RunNo AND p.RowNum = r.RowNum AND p.LineId = r.LineId
+            WHERE p.ToPickedPartialQty > 0;
+
+            INSERT INTO cust_PartialPickRemovalAudit
+                (RunNo, RowNum, LineId, BatchNo, ItemKey, QtyBefore, QtyAfter, ReasonCode,
+                 Comment, RemovedBy, RemovedDate)
+            {outbox}
+            SELECT r.RunNo, r.RowNum, r.LineId, r.BatchNo, r.ItemKey, r.ToPickedPartialQty, 0,
+                   @P8, @P9, @P6, @P10
+            FROM #Removed r;
+        END
+
+        COMMIT TRANSACTION;
+
+        SELECT *, @Outcome AS Outcome
+        FROM #Removed
+        ORDER BY BatchNo, LineId, ItemKey;
+
+        DROP TABLE #Removed;
+        "#,
+        columns = RM_LINE_COLUMNS,
+        token = CONCURRENCY_TOKEN_EXPR,
+        criteria = criteria,
+        user8 = user8_plus("p.User8", "p.ToPickedPartialQty"),
+        outbox = OUTBOX_OUTPUT
+    );
+
+    let result = plant
+        .pool
+        .execute_query_with_params(
+            &sql,
+            |query| {
+                query.bind(run_no);
+                filters.bind(query);
+                query.bind(removed_by.clone());
+                query.bind(max_rows);
+                query.bind(reason.code.clone());
+                query.bind(reason.comment.clone());
+                query.bind(removed_at.utc);
+                query.bind(removed_at.legacy_date);
+                query.bind(previewed.clone());
+            },
+            |row| Ok((map_rm_line(row)?, column::<String>(row, "Outcome")?)),
+        )
+        .await;
+
+    match result {
+        Ok(rows) => {
+            let outcome = BulkOutcome::from_rows(rows);
+            if let BulkOutcome::Removed(lines) = &outcome {
+                plant
+                    .events
+                    .publish_zeroed_lines(run_no, lines, &removed_by);
+            }
+            bulk_removal_response(run_no, outcome, max_rows)
+        }
+        Err(e) => {
+            error!("Database error removing RM lines by criteria: {}", e);
+            HttpResponse::build(database_error_status(&e)).json(RemoveByCriteriaResponse {
+                success: false,
+                message: format!("Database error: {}", e),
+                affected_rows: 0,
+                data: vec![],
+                approval_request_id: None,
+            })
+        }
+    }
+}
+
+/// One row of the grouped summary query, tagged with the grouping level
+enum SummaryRow {
+    Batch(BatchSummary),
+    Item(ItemSummary),
+    Run(QtySummary),
+}
+
+/// Fold the grouped rows into the run's totals, batches and items
+fn fold_summary(run_no: i32, rows: Vec<SummaryRow>) -> RunSummary {
+    let mut summary = RunSummary {
+        run_no,
+        totals: QtySummary::default(),
+        batches: vec![],
+        items: vec![],
+    };
+    for row in rows {
+        match row {
+            SummaryRow::Batch(batch) => summary.batches.push(batch),
+            SummaryRow::Item(item) => summary.items.push(item),
+            SummaryRow::Run(totals) => summary.totals = totals,
+        }
+    }
+    summary
+}
+
+#[derive(Debug, Deserialize)]
+struct RunListQuery {
+    /// Plant-local days as `YYYY-MM-DD`, both inclusive
+    from: Option<String>,
+    to: Option<String>,
+    item_key: Option<String>,
+    limit: Option<i32>,
+}
+
+fn run_list_error(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(RunListResponse {
+        success: false,
+        data: vec![],
+        message,
+    })
+}
+
+fn parse_day(name: &str, value: Option<&str>) -> Result<Option<NaiveDate>, String> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        None => Ok(None),
+        Some(value) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| format!("Invalid {} date, expected YYYY-MM-DD", name)),
+    }
+}
+
+#[get("/rm/runs")]
+async fn list_runs(plant: CurrentPlant, query: web::Query<RunListQuery>) -> impl Responder {
+    let query = query.into_inner();
+
+    let from = match parse_day("from", query.from.as_deref()) {
+        Ok(day) => day,
+        Err(message) => return run_list_error(message),
+    };
+    let to = match parse_day("to", query.to.as_deref()) {
+        Ok(day) => day,
+        Err(message) => return run_list_error(message),
+    };
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return run_list_error("from must not be after to".to_string());
+        }
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_RUN_LIMIT);
+    if !(1..=MAX_RUN_LIMIT).contains(&limit) {
+        return run_list_error(format!("limit must be between 1 and {}", MAX_RUN_LIMIT));
+    }
+
+    let filter = RunFilter {
+        from,
+        to,
+        item_key: query.item_key,
+        limit,
+    };
+
+    match runs::open_runs(&plant.pool, &plant.clock, &filter).await {
+        Ok(mut open) => {
+            for run in &mut open {
+                run.lease = plant.leases.current(run.run_no);
+            }
+            info!("Found {} runs with open partial picks", open.len());
+            HttpResponse::Ok().json(RunListResponse {
+                success: true,
+                message: format!("Found {} runs", open.len()),
+                data: open,
+            })
+        }
+        Err(e) => {
+            error!("Database error listing open runs: {}", e);
+            HttpResponse::build(database_error_status(&e)).json(RunListResponse {
+                success: false,
+                data: vec![],
+                message: format!("Database error: {}", e),
+            })
+        }
+    }
+}
+
+#[get("/rm/runs/{run_no}/summary")]
+async fn run_summary(plant: CurrentPlant, path: web::Path<i32>) -> impl Responder {
+    let run_no = path.into_inner();
+
+    info!("Building summary for RunNo: {}", run_no);
+
+    // GROUPING SETS yields per-batch rows, per-item rows and one run total
+    let sql = format!(
+        r#"
+        SELECT
+            CASE
+                WHEN GROUPING(BatchNo) = 0 THEN 'BATCH'
+                WHEN GROUPING(ItemKey) = 0 THEN 'ITEM'
+                ELSE 'RUN'
+            END AS Level,
+            BatchNo,
+            ItemKey,
+            COUNT(*) AS LineCount,
+            CAST(SUM(ISNULL(StandardQty, 0)) AS {qty}) AS StandardQty,
+            CAST(SUM(CASE WHEN ToPickedPartialQty > 0 THEN ToPickedPartialQty ELSE 0 END) AS {qty}) AS OutstandingQty,
+            CAST(SUM(CASE WHEN PickedPartialQty > 0 THEN PickedPartialQty ELSE 0 END) AS {qty}) AS PickedQty,
+            CAST(SUM(ISNULL(TRY_CAST(User8 AS {qty}), 0)) AS {qty}) AS RemovedQty
+        FROM cust_PartialPicked
+        WHERE RunNo = @P1
+        GROUP BY GROUPING SETS ((BatchNo), (ItemKey), ())
+        ORDER BY Level, BatchNo, ItemKey
+        "#,
+        qty = QTY_SQL_TYPE
+    );
+
+    let result = plant
+        .pool
+        .execute_read_with_params(
+            &sql,
+            |query| {
+                query.bind(run_no);
+            },
+            |row| {
+                let totals = QtySummary {
+                    line_count: column(row, "LineCount")?,
+                    standard_qty: column(row, "StandardQty")?,
+                    outstanding_qty: column(row, "OutstandingQty")?,
+                    picked_qty: column(row, "PickedQty")?,
+                    removed_qty: column(row, "RemovedQty")?,
+                };
+                // BatchNo and ItemKey are NULL on the rows of the other levels
+                Ok(match column::<&str>(row, "Level")? {
+                    "BATCH" => SummaryRow::Batch(BatchSummary {
+                        batch_no: column(row, "BatchNo")?,
+                        totals,
+                    }),
+                    "ITEM" => SummaryRow::Item(ItemSummary {
+                        item_key: column(row, "ItemKey")?,
+                        totals,
+                    }),
+                    _ => SummaryRow::Run(totals),
+                })
+            },
+        )
+        .await;
+
+    match result {
+        Ok(rows) => {
+            let summary = fold_summary(run_no, rows);
+
+            if summary.totals.line_count == 0 {
+                return HttpResponse::NotFound().json(RunSummaryResponse {
+                    success: false,
+                    data: None,
+                    message: format!("RunNo {} not found", run_no),
+                });
+            }
+
+            HttpResponse::Ok().json(RunSummaryResponse {
+                success: true,
+                message: format!(
+                    "{} lines in {} batches",
+                    summary.totals.line_count,
+                    summary.batches.len()
+                ),
+                data: Some(summary),
+            })
+        }
+        Err(e) => {
+            error!("Database error building run summary: {}", e);
+            HttpResponse::build(database_error_status(&e)).json(RunSummaryResponse {
+                success: false,
+                data: None,
+                message: format!("Database error: {}", e),
+            })
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    /// `json` (default) or `csv`
+    format: Option<String>,
+}
+
+#[get("/rm/runs/{run_no}/history")]
+async fn run_history(
+    plant: CurrentPlant,
+    path: web::Path<i32>,
+    query: web::Query<HistoryQuery>,
+) -> impl Responder {
+    let run_no = path.into_inner();
+    let as_csv = query
+        .format
+        .as_deref()
+        .is_some_and(|f| f.eq_ignore_ascii_case("csv"));
+
+    let entries = match history::run_history(&plant.pool, &plant.clock, run_no).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Database error reading removal history: {}", e);
+            return HttpResponse::build(database_error_status(&e)).json(HistoryResponse {
+                success: false,
+                data: vec![],
+                message: format!("Database error: {}", e),
+            });
+        }
+    };
+
+    if as_csv {
+        return match history::to_csv(&entries) {
+            Ok(body) => HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .insert_header((
+                    "Content-Disposition",
+                    format!("attachment; filename=\"run-{}-removals.csv\"", run_no),
+                ))
+                .body(body),
+            Err(e) => {
+                error!("Failed to render removal history as CSV: {}", e);
+                HttpResponse::InternalServerError().json(HistoryResponse {
+                    success: false,
+                    data: vec![],
+                    message: "Failed to render CSV".to_string(),
+                })
+            }
+        };
+    }
+
+    HttpResponse::Ok().json(HistoryResponse {
+        success: true,
+        message: format!("Found {} removals", entries.len()),
+        data: entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::qty::Qty;
+
+    fn line(row_num: i32) -> RMLine {
+        RMLine {
+            run_no: 1001,
+            row_num,
+            batch_no: "B1".to_string(),
+            line_typ: "FI".to_string(),
+            line_id: 1,
+            item_key: "ITEM".to_string(),
+            location: "TFC1".to_string(),
+            unit: "KG".to_string(),
+            standard_qty: Qty::from(100),
+            pack_size: Qty::from(25),
+            to_picked_partial_qty: Qty::from(30),
+            picked_partial_qty: None,
+            rec_user_id: "".to_string(),
+            modified_by: "".to_string(),
+            concurrency_token: "".to_string(),
+            item_description: None,
+            item_class: None,
+            storage_condition: None,
+            location_description: None,
+        }
+    }
+
+    async fn criteria_body(response: HttpResponse) -> RemoveByCriteriaResponse {
+        let body = to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn totals(line_count: i32, removed: i32) -> QtySummary {
+        QtySummary {
+            line_count,
+            removed_qty: Qty::from(removed),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fold_summary() {
+        let summary = fold_summary(
+            1001,
+            vec![
+                SummaryRow::Batch(BatchSummary {
+                    batch_no: "B1".to_string(),
+                    totals: totals(2, 10),
+                }),
+                SummaryRow::Batch(BatchSummary {
+                    batch_no: "B2".to_string(),
+                    totals: totals(1, 5),
+                }),
+                SummaryRow::Item(ItemSummary {
+                    item_key: "ITEM".to_string(),
+                    totals: totals(3, 15),
+                }),
+                SummaryRow::Run(totals(3, 15)),
+            ],
+        );
+
+        assert_eq!(summary.run_no, 1001);
+        assert_eq!(summary.totals.line_count, 3);
+        assert_eq!(summary.totals.removed_qty, Qty::from(15));
+        assert_eq!(
+            summary
+                .batches
+                .iter()
+                .map(|b| (b.batch_no.as_str(), b.totals.line_count))
+                .collect::<Vec<_>>(),
+            vec![("B1", 2), ("B2", 1)]
+        );
+        assert_eq!(summary.items.len(), 1);
+        assert_eq!(summary.items[0].totals.removed_qty, Qty::from(15));
+    }
+
+    #[test]
+    fn test_fold_summary_without_rows() {
+        // An unknown run yields no rows and is reported as not found
+        let summary = fold_summary(1001, vec![]);
+        assert_eq!(summary.totals.line_count, 0);
+        assert!(summary.batches.is_empty() && summary.items.is_empty());
+    }
+
+    #[test]
+    fn test_acting_user_is_the_signed_in_user() {
+        let user = AuthenticatedUser {
+            username: "alice".to_string(),
+            display_name: "Alice".to_string(),
+            roles: vec![],
+            plant: "TH1".to_string(),
+            plants: vec!["TH1".to_string()],
+        };
+        assert_eq!(acting_user(&user, "").unwrap(), "alice");
+        assert_eq!(acting_user(&user, " ALICE ").unwrap(), "alice");
+        assert!(acting_user(&user, "bob").is_err());
+    }
+
+    #[test]
+    fn test_blank_filters_are_omitted() {
+        let filters = CriteriaFilters::new(
+            Some("".to_string()),
+            Some("  ".to_string()),
+            None,
+            Some("FI".to_string()),
+        );
+        assert_eq!(
+            filters,
+            CriteriaFilters {
+                line_typ: Some("FI".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_over_limit_is_rejected_without_changes() {
+        let outcome = BulkOutcome::from_rows(vec![
+            (line(1), "over_limit".to_string()),
+            (line(2), "over_limit".to_string()),
+        ]);
+        assert!(matches!(outcome, BulkOutcome::OverLimit(2)));
+
+        let response = bulk_removal_response(1001, outcome, 1);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = criteria_body(response).await;
+        assert!(!body.success);
+        assert_eq!(body.affected_rows, 0);
+        assert!(body.data.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_no_match_is_not_found() {
+        let response = bulk_removal_response(1001, BulkOutcome::from_rows(vec![]), 200);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(criteria_body(response).await.affected_rows, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_changed_lines_are_a_conflict() {
+        let outcome = BulkOutcome::from_rows(vec![(line(1), "changed".to_string())]);
+        assert!(matches!(outcome, BulkOutcome::Changed));
+
+        let response = bulk_removal_response(1001, outcome, 200);
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = criteria_body(response).await;
+        assert_eq!(body.affected_rows, 0);
+        assert!(body.data.is_empty());
+    }
+
+    #[test]
+    fn test_previewed_lines_key_is_ordered() {
+        let mut first = line(2);
+        first.concurrency_token = "AB".to_string();
+        let mut second = line(1);
+        second.concurrency_token = "CD".to_string();
+        assert_eq!(previewed_lines_key(&[first, second]), "1:1:CD,2:1:AB,");
+        assert_eq!(previewed_lines_key(&[]), "");
+    }
+
+    #[actix_web::test]
+    async fn test_applied_removal_returns_lines() {
+        let outcome = BulkOutcome::from_rows(vec![
+            (line(1), "removed".to_string()),
+            (line(2), "removed".to_string()),
+        ]);
+        let response = bulk_removal_response(1001, outcome, 200);
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = criteria_body(response).await;
+        assert_eq!(body.affected_rows, 2);
+        assert_eq!(
+            body.data.iter().map(|l| l.row_num).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+}
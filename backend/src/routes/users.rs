@@ -0,0 +1,301 @@
+use actix_web::http::StatusCode;
+use actix_web::{get, post, put, web, HttpResponse, Responder};
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::auth::users::{self, UserAdminError};
+use crate::auth::{AuthenticatedUser, ROLE_ADMIN};
+use crate::models::auth::{
+    CreateLocalUserRequest, LocalUserListResponse, PasswordResetRequest, UpdateLocalUserRequest,
+    UserAuditListResponse,
+};
+use crate::plant::CurrentPlant;
+use crate::routes::database_error_status;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_users)
+        .service(list_user_audit)
+        .service(create_user)
+        .service(update_user)
+        .service(disable_user)
+        .service(enable_user)
+        .service(reset_password);
+}
+
+#[derive(Debug, Deserialize)]
+struct UserListQuery {
+    #[serde(default)]
+    include_disabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserAuditQuery {
+    username: Option<String>,
+}
+
+fn failure(status: StatusCode, message: String) -> HttpResponse {
+    HttpResponse::build(status).json(LocalUserListResponse {
+        success: false,
+        data: vec![],
+        message,
+    })
+}
+
+fn forbidden(user: &AuthenticatedUser) -> HttpResponse {
+    warn!(
+        "User {} tried to administer LOCAL users without admin role",
+        user.username
+    );
+    failure(StatusCode::FORBIDDEN, "Admin role required".to_string())
+}
+
+fn admin_error(e: UserAdminError) -> HttpResponse {
+    let status = match &e {
+        UserAdminError::Invalid(_) => StatusCode::BAD_REQUEST,
+        UserAdminError::NotFound(_) => StatusCode::NOT_FOUND,
+        UserAdminError::DirectoryAccount(_) => StatusCode::FORBIDDEN,
+        UserAdminError::Exists(_) => StatusCode::CONFLICT,
+        UserAdminError::Database(db) => {
+            error!("Database error administering LOCAL users: {}", db);
+            database_error_status(db)
+        }
+    };
+    failure(status, e.to_string())
+}
+
+/// Answer a successful change with the account as it is now
+async fn changed(plant: &CurrentPlant, username: &str, message: String) -> HttpResponse {
+    match users::get_user(&plant.pool, &plant.clock, username).await {
+        Ok(user) => HttpResponse::Ok().json(LocalUserListResponse {
+            success: true,
+            data: user.into_iter().collect(),
+            message,
+        }),
+        Err(e) => admin_error(e.into()),
+    }
+}
+
+#[get("/users")]
+async fn list_users(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    query: web::Query<UserListQuery>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    match users::list_users(&plant.pool, &plant.clock, query.include_disabled).await {
+        Ok(list) => HttpResponse::Ok().json(LocalUserListResponse {
+            success: true,
+            message: format!("Found {} LOCAL users", list.len()),
+            data: list,
+        }),
+        Err(e) => admin_error(e.into()),
+    }
+}
+
+#[get("/users/audit")]
+async fn list_user_audit(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    query: web::Query<UserAuditQuery>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let username = query
+        .username
+        .as_deref()
+        .map(str::trim)
+        .filter(|u| !u.is_empty());
+    match users::list_audit(&plant.pool, &plant.clock, username).await {
+        Ok(entries) => HttpResponse::Ok().json(UserAuditListResponse {
+            success: true,
+            message: format!("Found {} changes", entries.len()),
+            data: entries,
+        }),
+        Err(e) => admin_error(e.into()),
+    }
+}
+
+#[post("/users")]
+async fn create_user(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    body: web::Json<CreateLocalUserRequest>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let request = body.into_inner();
+    let username = request.username.trim().to_string();
+    let roles = match users::validate_username(&username)
+        .and_then(|_| users::validate_password(&request.password))
+        .and_then(|_| users::normalize_roles(&request.roles))
+    {
+        Ok(roles) => roles,
+        Err(e) => return admin_error(e),
+    };
+    let hash = match users::hash_password(request.password).await {
+        Ok(hash) => hash,
+        Err(e) => {
+            error!("Failed to hash password for {}: {}", username, e);
+            return failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to hash password".to_string(),
+            );
+        }
+    };
+
+    let result = users::create_user(
+        &plant.pool,
+        &username,
+        request.first_name.trim(),
+        request.last_name.trim(),
+        hash,
+        &roles,
+        &user.username,
+    )
+    .await;
+
+    match result {
+        Ok(()) => {
+            info!("LOCAL user {} created by {}", username, user.username);
+            let mut response =
+                changed(&plant, &username, format!("User {} created", username)).await;
+            if response.status() == StatusCode::OK {
+                *response.status_mut() = StatusCode::CREATED;
+            }
+            response
+        }
+        Err(e) => admin_error(e),
+    }
+}
+
+#[put("/users/{username}")]
+async fn update_user(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    body: web::Json<UpdateLocalUserRequest>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let username = path.into_inner();
+    let request = body.into_inner();
+    let roles = match request
+        .roles
+        .as_deref()
+        .map(users::normalize_roles)
+        .transpose()
+    {
+        Ok(roles) => roles,
+        Err(e) => return admin_error(e),
+    };
+
+    let result = users::update_user(
+        &plant.pool,
+        &username,
+        request.first_name.as_deref().map(str::trim),
+        request.last_name.as_deref().map(str::trim),
+        roles.as_deref(),
+        &user.username,
+    )
+    .await;
+
+    match result {
+        Ok(()) => {
+            info!("LOCAL user {} updated by {}", username, user.username);
+            changed(&plant, &username, format!("User {} updated", username)).await
+        }
+        Err(e) => admin_error(e),
+    }
+}
+
+async fn set_disabled(
+    plant: &CurrentPlant,
+    user: &AuthenticatedUser,
+    username: &str,
+    disabled: bool,
+) -> HttpResponse {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(user);
+    }
+    if disabled && username.eq_ignore_ascii_case(&user.username) {
+        return failure(
+            StatusCode::BAD_REQUEST,
+            "You cannot disable your own account".to_string(),
+        );
+    }
+
+    let verb = if disabled { "disabled" } else { "enabled" };
+    match users::set_disabled(&plant.pool, username, disabled, &user.username).await {
+        Ok(()) => {
+            info!("LOCAL user {} {} by {}", username, verb, user.username);
+            changed(plant, username, format!("User {} {}", username, verb)).await
+        }
+        Err(e) => admin_error(e),
+    }
+}
+
+#[post("/users/{username}/disable")]
+async fn disable_user(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> impl Responder {
+    set_disabled(&plant, &user, &path.into_inner(), true).await
+}
+
+#[post("/users/{username}/enable")]
+async fn enable_user(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+) -> impl Responder {
+    set_disabled(&plant, &user, &path.into_inner(), false).await
+}
+
+#[post("/users/{username}/password")]
+async fn reset_password(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<String>,
+    body: web::Json<PasswordResetRequest>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let username = path.into_inner();
+    let password = body.into_inner().password;
+    if let Err(e) = users::validate_password(&password) {
+        return admin_error(e);
+    }
+    let hash = match users::hash_password(password).await {
+        Ok(hash) => hash,
+        Err(e) => {
+            error!("Failed to hash password for {}: {}", username, e);
+            return failure(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to hash password".to_string(),
+            );
+        }
+    };
+
+    match users::set_password(&plant.pool, &username, hash, &user.username).await {
+        Ok(()) => {
+            info!(
+                "Password of LOCAL user {} reset by {}",
+                username, user.username
+            );
+            changed(&plant, &username, format!("Password of {} reset", username)).await
+        }
+        Err(e) => admin_error(e),
+    }
+}
@@ -0,0 +1,239 @@
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::auth::{AuthenticatedUser, ROLE_ADMIN};
+use crate::models::rm::{
+    DeliveryStatus, Webhook, WebhookDeliveryListResponse, WebhookListResponse,
+};
+use crate::plant::CurrentPlant;
+use crate::rm::webhook;
+use crate::routes::database_error_status;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_webhooks)
+        .service(create_webhook)
+        .service(list_deliveries)
+        .service(retry_delivery)
+        .service(update_webhook)
+        .service(deactivate_webhook);
+}
+
+#[derive(Debug, Deserialize)]
+struct DeliveryQuery {
+    status: Option<DeliveryStatus>,
+    webhook_id: Option<i32>,
+}
+
+fn forbidden(user: &AuthenticatedUser) -> HttpResponse {
+    warn!(
+        "User {} tried to maintain webhooks without admin role",
+        user.username
+    );
+    HttpResponse::Forbidden().json(WebhookListResponse {
+        success: false,
+        data: vec![],
+        message: "Admin role required".to_string(),
+    })
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(WebhookListResponse {
+        success: false,
+        data: vec![],
+        message: message.to_string(),
+    })
+}
+
+fn database_error(e: anyhow::Error) -> HttpResponse {
+    error!("Database error maintaining webhooks: {}", e);
+    HttpResponse::build(database_error_status(&e)).json(WebhookListResponse {
+        success: false,
+        data: vec![],
+        message: format!("Database error: {}", e),
+    })
+}
+
+/// Check a subscription sent by an admin, `secret_required` for new ones
+fn validate_webhook(webhook: &Webhook, secret_required: bool) -> Result<(), &'static str> {
+    if webhook.name.trim().is_empty() {
+        return Err("Name is required");
+    }
+    match url::Url::parse(webhook.url.trim()) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        _ => return Err("Url must be an absolute http or https URL"),
+    }
+    if secret_required && webhook.secret.is_empty() {
+        return Err("Secret is required");
+    }
+    if webhook.event_types.iter().all(|t| t.trim().is_empty()) {
+        return Err("At least one event type is required");
+    }
+    Ok(())
+}
+
+#[get("/rm/webhooks")]
+async fn list_webhooks(plant: CurrentPlant, user: AuthenticatedUser) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    match webhook::list_webhooks(&plant.pool).await {
+        Ok(webhooks) => HttpResponse::Ok().json(WebhookListResponse {
+            success: true,
+            message: format!("Found {} webhooks", webhooks.len()),
+            data: webhooks,
+        }),
+        Err(e) => database_error(e),
+    }
+}
+
+#[post("/rm/webhooks")]
+async fn create_webhook(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    body: web::Json<Webhook>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let mut hook = body.into_inner();
+    if let Err(msg) = validate_webhook(&hook, true) {
+        return bad_request(msg);
+    }
+
+    match webhook::create_webhook(&plant.pool, &hook, &user.username).await {
+        Ok(id) => {
+            hook.webhook_id = id;
+            info!(
+                "Webhook #{} ({}) created by {}",
+                id, hook.url, user.username
+            );
+            HttpResponse::Created().json(WebhookListResponse {
+                success: true,
+                message: format!("Webhook #{} created", id),
+                data: vec![hook],
+            })
+        }
+        Err(e) => database_error(e),
+    }
+}
+
+#[put("/rm/webhooks/{webhook_id}")]
+async fn update_webhook(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+    body: web::Json<Webhook>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let mut hook = body.into_inner();
+    hook.webhook_id = path.into_inner();
+    if let Err(msg) = validate_webhook(&hook, false) {
+        return bad_request(msg);
+    }
+
+    match webhook::update_webhook(&plant.pool, &hook, &user.username).await {
+        Ok(true) => {
+            info!("Webhook #{} updated by {}", hook.webhook_id, user.username);
+            HttpResponse::Ok().json(WebhookListResponse {
+                success: true,
+                message: format!("Webhook #{} updated", hook.webhook_id),
+                data: vec![hook],
+            })
+        }
+        Ok(false) => HttpResponse::NotFound().json(WebhookListResponse {
+            success: false,
+            data: vec![],
+            message: format!("Webhook #{} not found", hook.webhook_id),
+        }),
+        Err(e) => database_error(e),
+    }
+}
+
+#[delete("/rm/webhooks/{webhook_id}")]
+async fn deactivate_webhook(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let webhook_id = path.into_inner();
+    match webhook::deactivate_webhook(&plant.pool, webhook_id, &user.username).await {
+        Ok(true) => {
+            info!("Webhook #{} deactivated by {}", webhook_id, user.username);
+            HttpResponse::Ok().json(WebhookListResponse {
+                success: true,
+                data: vec![],
+                message: format!("Webhook #{} deactivated", webhook_id),
+            })
+        }
+        Ok(false) => HttpResponse::NotFound().json(WebhookListResponse {
+            success: false,
+            data: vec![],
+            message: format!("Webhook #{} not found", webhook_id),
+        }),
+        Err(e) => database_error(e),
+    }
+}
+
+/// Dead-letter view by default; `?status=PENDING` or `DELIVERED` for the rest
+#[get("/rm/webhooks/deliveries")]
+async fn list_deliveries(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    query: web::Query<DeliveryQuery>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let status = query.status.unwrap_or(DeliveryStatus::Dead);
+    match webhook::list_deliveries(&plant.pool, &plant.clock, status, query.webhook_id).await {
+        Ok(deliveries) => HttpResponse::Ok().json(WebhookDeliveryListResponse {
+            success: true,
+            message: format!("Found {} {} deliveries", deliveries.len(), status.as_db()),
+            data: deliveries,
+        }),
+        Err(e) => database_error(e),
+    }
+}
+
+#[post("/rm/webhooks/deliveries/{delivery_id}/retry")]
+async fn retry_delivery(
+    plant: CurrentPlant,
+    user: AuthenticatedUser,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if !user.has_role(ROLE_ADMIN) {
+        return forbidden(&user);
+    }
+
+    let delivery_id = path.into_inner();
+    match webhook::retry_delivery(&plant.pool, delivery_id).await {
+        Ok(true) => {
+            info!(
+                "Webhook delivery #{} requeued by {}",
+                delivery_id, user.username
+            );
+            HttpResponse::Ok().json(WebhookDeliveryListResponse {
+                success: true,
+                data: vec![],
+                message: format!("Delivery #{} requeued", delivery_id),
+            })
+        }
+        Ok(false) => HttpResponse::NotFound().json(WebhookDeliveryListResponse {
+            success: false,
+            data: vec![],
+            message: format!("No dead delivery #{}", delivery_id),
+        }),
+        Err(e) => database_error(e),
+    }
+}
@@ -0,0 +1,150 @@
+//! Server Configuration
+//!
+//! Loads the listener settings from environment variables.
+
+use anyhow::{bail, Result};
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// HTTPS certificate files
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first
+    pub cert_path: PathBuf,
+
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1)
+    pub key_path: PathBuf,
+
+    /// How often the files are checked for changes
+    pub reload_interval: Duration,
+}
+
+/// Listener settings loaded from environment variables
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    pub port: u16,
+
+    /// Worker threads, `None` for one per CPU core
+    pub workers: Option<usize>,
+
+    pub keep_alive: Duration,
+    pub client_request_timeout: Duration,
+    pub client_disconnect_timeout: Duration,
+
+    /// How long in-flight requests may run after a stop signal
+    pub shutdown_timeout: Duration,
+
+    /// HTTPS settings, `None` for plain HTTP
+    pub tls: Option<TlsConfig>,
+}
+
+impl ServerConfig {
+    /// Environment variable names
+    const ENV_BIND_ADDRESS: &'static str = "SERVER_BIND_ADDRESS";
+    const ENV_PORT: &'static str = "SERVER_PORT";
+    const ENV_WORKERS: &'static str = "SERVER_WORKERS";
+    const ENV_KEEP_ALIVE_SECS: &'static str = "SERVER_KEEP_ALIVE_SECS";
+    const ENV_CLIENT_REQUEST_TIMEOUT_SECS: &'static str = "SERVER_CLIENT_REQUEST_TIMEOUT_SECS";
+    const ENV_CLIENT_DISCONNECT_TIMEOUT_SECS: &'static str =
+        "SERVER_CLIENT_DISCONNECT_TIMEOUT_SECS";
+    const ENV_SHUTDOWN_TIMEOUT_SECS: &'static str = "SERVER_SHUTDOWN_TIMEOUT_SECS";
+    const ENV_TLS_CERT_PATH: &'static str = "TLS_CERT_PATH";
+    const ENV_TLS_KEY_PATH: &'static str = "TLS_KEY_PATH";
+    const ENV_TLS_RELOAD_SECS: &'static str = "TLS_RELOAD_SECS";
+
+    /// Default values
+    const DEFAULT_BIND_ADDRESS: &'static str = "0.0.0.0";
+    const DEFAULT_PORT: u16 = 8080;
+    const DEFAULT_KEEP_ALIVE_SECS: u64 = 5;
+    const DEFAULT_CLIENT_REQUEST_TIMEOUT_SECS: u64 = 5;
+    const DEFAULT_CLIENT_DISCONNECT_TIMEOUT_SECS: u64 = 5;
+    const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+    const DEFAULT_TLS_RELOAD_SECS: u64 = 60;
+
+    /// Load configuration from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a number cannot be parsed or only one of the TLS
+    /// file paths is set.
+    pub fn from_env() -> Result<Self> {
+        let bind_address = env::var(Self::ENV_BIND_ADDRESS)
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or_else(|| Self::DEFAULT_BIND_ADDRESS.to_string());
+
+        let port = parse_var::<u16>(Self::ENV_PORT)?.unwrap_or(Self::DEFAULT_PORT);
+
+        let workers = parse_var::<usize>(Self::ENV_WORKERS)?.filter(|w| *w > 0);
+
+        let secs = |var: &str, default: u64| -> Result<Duration> {
+            Ok(Duration::from_secs(
+                parse_var::<u64>(var)?.unwrap_or(default),
+            ))
+        };
+
+        let tls = match (
+            non_empty_var(Self::ENV_TLS_CERT_PATH),
+            non_empty_var(Self::ENV_TLS_KEY_PATH),
+        ) {
+            (Some(cert), Some(key)) => Some(TlsConfig {
+                cert_path: PathBuf::from(cert),
+                key_path: PathBuf::from(key),
+                reload_interval: secs(Self::ENV_TLS_RELOAD_SECS, Self::DEFAULT_TLS_RELOAD_SECS)?,
+            }),
+            (None, None) => None,
+            _ => bail!(
+                "{} and {} must be set together",
+                Self::ENV_TLS_CERT_PATH,
+                Self::ENV_TLS_KEY_PATH
+            ),
+        };
+
+        Ok(Self {
+            bind_address,
+            port,
+            workers,
+            keep_alive: secs(Self::ENV_KEEP_ALIVE_SECS, Self::DEFAULT_KEEP_ALIVE_SECS)?,
+            client_request_timeout: secs(
+                Self::ENV_CLIENT_REQUEST_TIMEOUT_SECS,
+                Self::DEFAULT_CLIENT_REQUEST_TIMEOUT_SECS,
+            )?,
+            client_disconnect_timeout: secs(
+                Self::ENV_CLIENT_DISCONNECT_TIMEOUT_SECS,
+                Self::DEFAULT_CLIENT_DISCONNECT_TIMEOUT_SECS,
+            )?,
+            shutdown_timeout: secs(
+                Self::ENV_SHUTDOWN_TIMEOUT_SECS,
+                Self::DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+            )?,
+            tls,
+        })
+    }
+
+    /// `host:port` to bind
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.bind_address, self.port)
+    }
+
+    pub fn tls_enabled(&self) -> bool {
+        self.tls.is_some()
+    }
+}
+
+fn non_empty_var(var: &str) -> Option<String> {
+    env::var(var).ok().filter(|s| !s.trim().is_empty())
+}
+
+/// Parse a variable if it is set, failing on invalid values instead of
+/// silently falling back to the default
+fn parse_var<T: std::str::FromStr>(var: &str) -> Result<Option<T>> {
+    match non_empty_var(var) {
+        None => Ok(None),
+        Some(value) => match value.trim().parse::<T>() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(_) => bail!("{} must be a valid number, got: {}", var, value),
+        },
+    }
+}
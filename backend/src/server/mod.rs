@@ -0,0 +1,32 @@
+//! HTTP Server Settings
+//!
+//! Bind address, worker and timeout settings for `HttpServer`, optional
+//! HTTPS termination with rustls, CORS and security headers.
+//!
+//! # Configuration
+//!
+//! | Variable | Description | Default |
+//! |----------|-------------|---------|
+//! | `SERVER_BIND_ADDRESS` | Address to listen on | `0.0.0.0` |
+//! | `SERVER_PORT` | Port to listen on | `8080` |
+//! | `SERVER_WORKERS` | Worker threads | number of CPU cores |
+//! | `SERVER_KEEP_ALIVE_SECS` | Idle keep-alive, `0` disables | `5` |
+//! | `SERVER_CLIENT_REQUEST_TIMEOUT_SECS` | Time allowed to send request headers | `5` |
+//! | `SERVER_CLIENT_DISCONNECT_TIMEOUT_SECS` | Time allowed to close a connection | `5` |
+//! | `SERVER_SHUTDOWN_TIMEOUT_SECS` | Grace period for in-flight requests on SIGTERM | `30` |
+//! | `TLS_CERT_PATH` | PEM certificate chain, enables HTTPS with `TLS_KEY_PATH` | |
+//! | `TLS_KEY_PATH` | PEM private key | |
+//! | `TLS_RELOAD_SECS` | How often to check the files for a renewed certificate | `60` |
+//! | `CORS_ALLOWED_ORIGINS` | Comma-separated origins, `*` for any | none |
+//! | `CORS_ALLOWED_METHODS` | Comma-separated methods | `GET,POST,PUT,DELETE` |
+//! | `CORS_ALLOWED_HEADERS` | Comma-separated request headers | `Authorization,Content-Type,Idempotency-Key` |
+//! | `CORS_MAX_AGE_SECS` | Preflight cache lifetime | `3600` |
+//! | `JSON_BODY_LIMIT_BYTES` | Largest JSON request body | `262144` |
+//! | `HSTS_MAX_AGE_SECS` | HSTS max-age when TLS is on, `0` disables | `31536000` |
+
+pub mod config;
+pub mod security;
+pub mod tls;
+
+pub use config::ServerConfig;
+pub use security::SecurityConfig;
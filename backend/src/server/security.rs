@@ -0,0 +1,315 @@
+//! CORS and Security Headers
+//!
+//! Cross-origin access is limited to the configured origins; with nothing
+//! configured, browsers on other origins are refused. Every response gets
+//! standard hardening headers, plus HSTS when the server terminates TLS.
+
+use actix_cors::Cors;
+use actix_web::error::JsonPayloadError;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::DefaultHeaders;
+use actix_web::{error, web, HttpRequest, HttpResponse};
+use anyhow::{bail, Result};
+use log::warn;
+use serde_json::json;
+use std::env;
+
+/// CORS, response header and request size settings
+#[derive(Debug, Clone)]
+pub struct SecurityConfig {
+    /// Origins allowed to call the API from a browser, `*` for any
+    pub cors_origins: Vec<String>,
+
+    pub cors_methods: Vec<Method>,
+
+    pub cors_headers: Vec<HeaderName>,
+
+    /// How long browsers may cache a preflight response
+    pub cors_max_age_secs: usize,
+
+    /// Largest JSON request body accepted
+    pub json_limit_bytes: usize,
+
+    /// `Strict-Transport-Security` max-age, sent only over TLS
+    pub hsts_max_age_secs: u64,
+}
+
+impl SecurityConfig {
+    /// Environment variable names
+    const ENV_CORS_ALLOWED_ORIGINS: &'static str = "CORS_ALLOWED_ORIGINS";
+    const ENV_CORS_ALLOWED_METHODS: &'static str = "CORS_ALLOWED_METHODS";
+    const ENV_CORS_ALLOWED_HEADERS: &'static str = "CORS_ALLOWED_HEADERS";
+    const ENV_CORS_MAX_AGE_SECS: &'static str = "CORS_MAX_AGE_SECS";
+    const ENV_JSON_BODY_LIMIT_BYTES: &'static str = "JSON_BODY_LIMIT_BYTES";
+    const ENV_HSTS_MAX_AGE_SECS: &'static str = "HSTS_MAX_AGE_SECS";
+
+    /// Default values
+    const DEFAULT_CORS_ALLOWED_METHODS: &'static str = "GET,POST,PUT,DELETE";
+    const DEFAULT_CORS_ALLOWED_HEADERS: &'static str =
+        "Authorization,Content-Type,Idempotency-Key,X-Plant";
+    const DEFAULT_CORS_MAX_AGE_SECS: usize = 3600;
+    const DEFAULT_JSON_BODY_LIMIT_BYTES: usize = 256 * 1024;
+    const DEFAULT_HSTS_MAX_AGE_SECS: u64 = 31_536_000;
+
+    /// Load configuration from environment variables
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for an unknown method, an invalid header name or a
+    /// number that cannot be parsed.
+    pub fn from_env() -> Result<Self> {
+        let cors_origins = list_var(Self::ENV_CORS_ALLOWED_ORIGINS, "");
+
+        let cors_methods = list_var(
+            Self::ENV_CORS_ALLOWED_METHODS,
+            Self::DEFAULT_CORS_ALLOWED_METHODS,
+        )
+        .iter()
+        .map(|m| match Method::from_bytes(m.to_uppercase().as_bytes()) {
+            Ok(method) => Ok(method),
+            Err(_) => bail!(
+                "Invalid method in {}: {}",
+                Self::ENV_CORS_ALLOWED_METHODS,
+                m
+            ),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        let cors_headers = list_var(
+            Self::ENV_CORS_ALLOWED_HEADERS,
+            Self::DEFAULT_CORS_ALLOWED_HEADERS,
+        )
+        .iter()
+        .map(|h| match HeaderName::from_bytes(h.as_bytes()) {
+            Ok(name) => Ok(name),
+            Err(_) => bail!(
+                "Invalid header in {}: {}",
+                Self::ENV_CORS_ALLOWED_HEADERS,
+                h
+            ),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            cors_origins,
+            cors_methods,
+            cors_headers,
+            cors_max_age_secs: number_var(
+                Self::ENV_CORS_MAX_AGE_SECS,
+                Self::DEFAULT_CORS_MAX_AGE_SECS,
+            )?,
+            json_limit_bytes: number_var(
+                Self::ENV_JSON_BODY_LIMIT_BYTES,
+                Self::DEFAULT_JSON_BODY_LIMIT_BYTES,
+            )?,
+            hsts_max_age_secs: number_var(
+                Self::ENV_HSTS_MAX_AGE_SECS,
+                Self::DEFAULT_HSTS_MAX_AGE_SECS,
+            )?,
+        })
+    }
+
+    /// CORS middleware for the configured origins
+    pub fn cors(&self) -> Cors {
+        let mut cors = Cors::default()
+            .allowed_methods(self.cors_methods.clone())
+            .allowed_headers(self.cors_headers.clone())
+            .expose_headers(["Idempotent-Replayed"])
+            .max_age(self.cors_max_age_secs);
+
+        if self.cors_origins.iter().any(|o| o == "*") {
+            cors = cors.allow_any_origin();
+        } else {
+            for origin in &self.cors_origins {
+                cors = cors.allowed_origin(origin);
+            }
+        }
+
+        cors
+    }
+
+    /// Log settings that weaken the defaults
+    pub fn warn_if_permissive(&self) {
+        if self.cors_origins.iter().any(|o| o == "*") {
+            warn!("CORS allows any origin; set CORS_ALLOWED_ORIGINS to the frontend URL in production");
+        } else if self.cors_origins.is_empty() {
+            warn!("CORS_ALLOWED_ORIGINS is empty; browsers on other origins cannot call the API");
+        }
+    }
+
+    /// Hardening headers added to every response that does not set them itself
+    pub fn security_headers(&self, tls_enabled: bool) -> DefaultHeaders {
+        let headers = DefaultHeaders::new()
+            .add(("X-Content-Type-Options", "nosniff"))
+            .add(("X-Frame-Options", "DENY"))
+            .add((
+                "Content-Security-Policy",
+                "default-src 'none'; frame-ancestors 'none'",
+            ))
+            .add(("Referrer-Policy", "no-referrer"));
+
+        if tls_enabled && self.hsts_max_age_secs > 0 {
+            let value = format!("max-age={}; includeSubDomains", self.hsts_max_age_secs);
+            headers.add((
+                "Strict-Transport-Security",
+                HeaderValue::from_str(&value).expect("HSTS header value is ASCII"),
+            ))
+        } else {
+            headers
+        }
+    }
+
+    /// JSON extractor settings with the body limit and a JSON error body
+    pub fn json_config(&self) -> web::JsonConfig {
+        web::JsonConfig::default()
+            .limit(self.json_limit_bytes)
+            .error_handler(json_error_handler)
+    }
+}
+
+/// Responses that must not be cached by browsers or proxies, e.g. tokens
+pub fn no_store() -> DefaultHeaders {
+    DefaultHeaders::new()
+        .add(("Cache-Control", "no-store"))
+        .add(("Pragma", "no-cache"))
+}
+
+fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> error::Error {
+    let response = match &err {
+        JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+            HttpResponse::PayloadTooLarge()
+        }
+        JsonPayloadError::ContentType => HttpResponse::UnsupportedMediaType(),
+        _ => HttpResponse::BadRequest(),
+    }
+    .json(json!({
+        "success": false,
+        "message": err.to_string(),
+    }));
+
+    error::InternalError::from_response(err, response).into()
+}
+
+/// Comma-separated list, `default` when unset
+fn list_var(var: &str, default: &str) -> Vec<String> {
+    env::var(var)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn number_var<T: std::str::FromStr>(var: &str, default: T) -> Result<T> {
+    match env::var(var) {
+        Ok(value) if !value.trim().is_empty() => match value.trim().parse::<T>() {
+            Ok(parsed) => Ok(parsed),
+            Err(_) => bail!("{} must be a valid number, got: {}", var, value),
+        },
+        _ => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::header, test, App};
+
+    fn config(origins: &[&str]) -> SecurityConfig {
+        SecurityConfig {
+            cors_origins: origins.iter().map(|o| o.to_string()).collect(),
+            cors_methods: vec![Method::GET, Method::POST],
+            cors_headers: vec![header::AUTHORIZATION, header::CONTENT_TYPE],
+            cors_max_age_secs: 60,
+            json_limit_bytes: 16,
+            hsts_max_age_secs: 600,
+        }
+    }
+
+    async fn ok() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    async fn echo(body: web::Json<serde_json::Value>) -> HttpResponse {
+        HttpResponse::Ok().json(body.into_inner())
+    }
+
+    #[actix_web::test]
+    async fn test_cors_allowlist() {
+        let security = config(&["https://rm.example.com"]);
+        let app = test::init_service(
+            App::new()
+                .wrap(security.cors())
+                .route("/", web::get().to(ok)),
+        )
+        .await;
+
+        let allowed = test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::ORIGIN, "https://rm.example.com"))
+            .send_request(&app)
+            .await;
+        assert_eq!(
+            allowed
+                .headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap(),
+            "https://rm.example.com"
+        );
+
+        let refused = test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::ORIGIN, "https://evil.example.com"))
+            .send_request(&app)
+            .await;
+        assert!(refused
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_security_headers_and_body_limit() {
+        let security = config(&[]);
+        let app = test::init_service(
+            App::new()
+                .wrap(security.security_headers(true))
+                .app_data(security.json_config())
+                .route("/", web::post().to(echo)),
+        )
+        .await;
+
+        let response = test::TestRequest::post()
+            .uri("/")
+            .set_json(json!({ "a": 1 }))
+            .send_request(&app)
+            .await;
+        assert!(response.status().is_success());
+        assert_eq!(
+            response.headers().get("X-Content-Type-Options").unwrap(),
+            "nosniff"
+        );
+        assert_eq!(response.headers().get("X-Frame-Options").unwrap(), "DENY");
+        assert_eq!(
+            response.headers().get("Strict-Transport-Security").unwrap(),
+            "max-age=600; includeSubDomains"
+        );
+
+        let too_large = test::TestRequest::post()
+            .uri("/")
+            .set_json(json!({ "a": "more than sixteen bytes" }))
+            .send_request(&app)
+            .await;
+        assert_eq!(too_large.status(), 413);
+
+        let plain = security.security_headers(false);
+        let app = test::init_service(App::new().wrap(plain).route("/", web::get().to(ok))).await;
+        let response = test::TestRequest::get().uri("/").send_request(&app).await;
+        assert!(response
+            .headers()
+            .get("Strict-Transport-Security")
+            .is_none());
+    }
+}